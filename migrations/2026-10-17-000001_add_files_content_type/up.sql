@@ -0,0 +1 @@
+ALTER TABLE files ADD COLUMN content_type VARCHAR(255) NOT NULL DEFAULT 'application/octet-stream';
@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS idx_files_sha256;
+
+ALTER TABLE files DROP COLUMN crc32c;
+ALTER TABLE files DROP COLUMN md5;
+ALTER TABLE files DROP COLUMN sha256;
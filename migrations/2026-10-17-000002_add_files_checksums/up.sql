@@ -0,0 +1,5 @@
+ALTER TABLE files ADD COLUMN sha256 VARCHAR(64);
+ALTER TABLE files ADD COLUMN md5 VARCHAR(32);
+ALTER TABLE files ADD COLUMN crc32c VARCHAR(8);
+
+CREATE INDEX idx_files_sha256 ON files(sha256);
@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS idx_files_blob_oid;
+
+ALTER TABLE files DROP COLUMN blob_oid;
+
+DROP TABLE IF EXISTS blobs;
@@ -0,0 +1,15 @@
+CREATE TABLE blobs (
+    oid BIGINT PRIMARY KEY,
+    id VARCHAR(255) NOT NULL UNIQUE,
+    tenant_oid BIGINT NOT NULL REFERENCES tenants(oid) ON DELETE CASCADE,
+    sha256 VARCHAR(64) NOT NULL,
+    bytes BIGINT NOT NULL,
+    storage_key VARCHAR(512) NOT NULL,
+    ref_count BIGINT NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (tenant_oid, sha256)
+);
+
+ALTER TABLE files ADD COLUMN blob_oid BIGINT REFERENCES blobs(oid);
+
+CREATE INDEX idx_files_blob_oid ON files(blob_oid);
@@ -0,0 +1,13 @@
+CREATE TABLE api_keys (
+    oid BIGINT PRIMARY KEY,
+    id VARCHAR(255) NOT NULL UNIQUE,
+    tenant_oid BIGINT NOT NULL REFERENCES tenants(oid) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    lookup VARCHAR(32) NOT NULL UNIQUE,
+    key_hash VARCHAR(64) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_used_at TIMESTAMP,
+    revoked_at TIMESTAMP
+);
+
+CREATE INDEX idx_api_keys_tenant_oid ON api_keys(tenant_oid);
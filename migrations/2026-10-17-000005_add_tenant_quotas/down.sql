@@ -0,0 +1,3 @@
+ALTER TABLE tenants DROP COLUMN max_file_size_bytes;
+ALTER TABLE tenants DROP COLUMN max_file_count;
+ALTER TABLE tenants DROP COLUMN max_total_bytes;
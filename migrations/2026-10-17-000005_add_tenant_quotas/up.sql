@@ -0,0 +1,3 @@
+ALTER TABLE tenants ADD COLUMN max_total_bytes BIGINT;
+ALTER TABLE tenants ADD COLUMN max_file_count BIGINT;
+ALTER TABLE tenants ADD COLUMN max_file_size_bytes BIGINT;
@@ -0,0 +1,10 @@
+CREATE TABLE pending_deletions (
+    oid BIGINT PRIMARY KEY,
+    storage_key VARCHAR(512) NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_pending_deletions_updated_at ON pending_deletions(updated_at);
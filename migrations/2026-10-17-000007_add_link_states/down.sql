@@ -0,0 +1,2 @@
+ALTER TABLE file_links DROP COLUMN revoked_at;
+ALTER TABLE file_links DROP COLUMN starts_at;
@@ -0,0 +1,2 @@
+ALTER TABLE file_links ADD COLUMN starts_at TIMESTAMP;
+ALTER TABLE file_links ADD COLUMN revoked_at TIMESTAMP;
@@ -0,0 +1,3 @@
+ALTER TABLE file_links DROP COLUMN last_accessed_at;
+ALTER TABLE file_links DROP COLUMN download_count;
+ALTER TABLE file_links DROP COLUMN max_downloads;
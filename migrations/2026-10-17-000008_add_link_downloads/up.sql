@@ -0,0 +1,3 @@
+ALTER TABLE file_links ADD COLUMN max_downloads BIGINT;
+ALTER TABLE file_links ADD COLUMN download_count BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE file_links ADD COLUMN last_accessed_at TIMESTAMP;
@@ -0,0 +1,3 @@
+ALTER TABLE file_links DROP COLUMN password_locked_until;
+ALTER TABLE file_links DROP COLUMN failed_password_attempts;
+ALTER TABLE file_links DROP COLUMN password_hash;
@@ -0,0 +1,3 @@
+ALTER TABLE file_links ADD COLUMN password_hash VARCHAR;
+ALTER TABLE file_links ADD COLUMN failed_password_attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE file_links ADD COLUMN password_locked_until TIMESTAMP;
@@ -0,0 +1,2 @@
+DROP INDEX idx_files_expires_at;
+ALTER TABLE files DROP COLUMN expires_at;
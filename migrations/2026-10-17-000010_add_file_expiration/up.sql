@@ -0,0 +1,2 @@
+ALTER TABLE files ADD COLUMN expires_at TIMESTAMP;
+CREATE INDEX idx_files_expires_at ON files(expires_at) WHERE expires_at IS NOT NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE files ADD COLUMN deleted_at TIMESTAMP;
+CREATE INDEX idx_files_deleted_at ON files(deleted_at) WHERE deleted_at IS NOT NULL;
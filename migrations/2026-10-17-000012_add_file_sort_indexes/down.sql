@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_files_filename_oid;
+DROP INDEX IF EXISTS idx_files_bytes_oid;
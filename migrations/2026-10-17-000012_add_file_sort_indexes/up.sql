@@ -0,0 +1,3 @@
+-- Keyset pagination over the non-default file sorts seeks on (column, oid).
+CREATE INDEX idx_files_bytes_oid ON files (bytes, oid);
+CREATE INDEX idx_files_filename_oid ON files (filename, oid);
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_files_filename_oid;
+CREATE INDEX idx_files_filename_oid ON files (filename, oid);
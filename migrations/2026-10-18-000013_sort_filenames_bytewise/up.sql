@@ -0,0 +1,3 @@
+-- sort=filename orders by byte value, independent of the database collation.
+DROP INDEX IF EXISTS idx_files_filename_oid;
+CREATE INDEX idx_files_filename_oid ON files (filename COLLATE "C", oid);
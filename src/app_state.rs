@@ -1,15 +1,21 @@
+use crate::auth::AuthChain;
 use crate::config::Config;
 use crate::db::DbPool;
+use crate::repository::Repositories;
 use crate::snowflake::SnowflakeGeneratorWrapper;
 use crate::storage::ObjectStorageClient;
 use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct AppState {
+    /// The pool behind the Postgres repositories, for tests that set up rows directly.
+    #[allow(dead_code)]
     pub db_pool: DbPool,
+    pub repos: Repositories,
     pub storage_client: ObjectStorageClient,
     pub snowflake_gen: Arc<SnowflakeGeneratorWrapper>,
-    pub config: Config,
+    pub auth: AuthChain,
+    pub config: Arc<Config>,
 }
 
 impl AppState {
@@ -17,13 +23,24 @@ impl AppState {
         db_pool: DbPool,
         storage_client: ObjectStorageClient,
         snowflake_gen: SnowflakeGeneratorWrapper,
+        auth: AuthChain,
         config: Config,
     ) -> Self {
+        let snowflake_gen = Arc::new(snowflake_gen);
         Self {
+            repos: Repositories::postgres(db_pool.clone(), snowflake_gen.clone()),
             db_pool,
             storage_client,
-            snowflake_gen: Arc::new(snowflake_gen),
-            config,
+            snowflake_gen,
+            auth,
+            config: Arc::new(config),
         }
     }
+
+    /// Swaps the Postgres repositories for others, such as
+    /// [`Repositories::in_memory`] in tests.
+    #[allow(dead_code)]
+    pub fn with_repositories(self, repos: Repositories) -> Self {
+        Self { repos, ..self }
+    }
 }
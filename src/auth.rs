@@ -0,0 +1,427 @@
+use crate::app_state::AppState;
+use crate::config::Config;
+use crate::error::AppError;
+use crate::models::{NewTenant, Tenant};
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderMap},
+};
+use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+use std::sync::Arc;
+
+pub const API_KEY_PREFIX: &str = "ch";
+const API_KEY_LOOKUP_LEN: usize = 12;
+const API_KEY_SECRET_LEN: usize = 32;
+
+pub(crate) const INVALID_TENANT_ID: &str =
+    "Tenant ids are 1 to 255 letters, digits, hyphens and underscores";
+
+/// A way for public API callers to identify their tenant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMethod {
+    /// Legacy `X-Tenant-ID` header, trusted as-is.
+    TenantHeader,
+    ApiKey,
+    Jwt,
+}
+
+impl FromStr for AuthMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tenant_header" => Ok(AuthMethod::TenantHeader),
+            "api_key" => Ok(AuthMethod::ApiKey),
+            "jwt" => Ok(AuthMethod::Jwt),
+            other => Err(format!("Unknown auth method: {}", other)),
+        }
+    }
+}
+
+/// Resolves the tenant for a request from one kind of credential.
+///
+/// Returns `Ok(None)` when the request carries no credential of this kind, so
+/// the next authenticator in the chain gets a chance.
+#[async_trait]
+pub trait Authenticator: Send + Sync {
+    async fn authenticate(
+        &self,
+        headers: &HeaderMap,
+        state: &AppState,
+    ) -> Result<Option<Tenant>, AppError>;
+}
+
+/// The configured authenticators, tried in order.
+#[derive(Clone)]
+pub struct AuthChain {
+    authenticators: Arc<Vec<Box<dyn Authenticator>>>,
+}
+
+impl AuthChain {
+    /// `tenant_header` trusts any caller, so it would make every other method
+    /// pointless and may only be used alone.
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        if config.auth_methods.contains(&AuthMethod::TenantHeader) {
+            if config.auth_methods.len() > 1 {
+                return Err(
+                    "AUTH_METHODS: tenant_header cannot be combined with other methods".to_string(),
+                );
+            }
+            tracing::warn!(
+                "AUTH_METHODS includes tenant_header: any caller can act as any tenant \
+                 by sending X-Tenant-ID; only use it behind a trusted gateway"
+            );
+        }
+
+        let mut authenticators: Vec<Box<dyn Authenticator>> = Vec::new();
+
+        for method in &config.auth_methods {
+            match method {
+                AuthMethod::TenantHeader => {
+                    authenticators.push(Box::new(TenantHeaderAuthenticator))
+                }
+                AuthMethod::ApiKey => authenticators.push(Box::new(ApiKeyAuthenticator)),
+                AuthMethod::Jwt => authenticators.push(Box::new(JwtAuthenticator::new(config)?)),
+            }
+        }
+
+        Ok(Self {
+            authenticators: Arc::new(authenticators),
+        })
+    }
+
+    pub async fn authenticate(
+        &self,
+        headers: &HeaderMap,
+        state: &AppState,
+    ) -> Result<Tenant, AppError> {
+        for authenticator in self.authenticators.iter() {
+            if let Some(tenant) = authenticator.authenticate(headers, state).await? {
+                return Ok(tenant);
+            }
+        }
+
+        Err(AppError::Unauthorized("Missing credentials".to_string()))
+    }
+}
+
+/// The tenant the current public API request acts on behalf of.
+pub struct AuthenticatedTenant(pub Tenant);
+
+#[async_trait]
+impl FromRequestParts<AppState> for AuthenticatedTenant {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let tenant = state.auth.authenticate(&parts.headers, state).await?;
+        Ok(AuthenticatedTenant(tenant))
+    }
+}
+
+pub struct TenantHeaderAuthenticator;
+
+#[async_trait]
+impl Authenticator for TenantHeaderAuthenticator {
+    async fn authenticate(
+        &self,
+        headers: &HeaderMap,
+        state: &AppState,
+    ) -> Result<Option<Tenant>, AppError> {
+        let Some(tenant_id) = headers.get("X-Tenant-ID").and_then(|v| v.to_str().ok()) else {
+            return Ok(None);
+        };
+
+        get_or_create_tenant(tenant_id, "X-Tenant-ID", state)
+            .await
+            .map(Some)
+    }
+}
+
+/// Accepts keys issued by the private API, sent as `X-API-Key` or as a bearer
+/// token. Only a SHA-256 hash of each key is stored.
+pub struct ApiKeyAuthenticator;
+
+#[async_trait]
+impl Authenticator for ApiKeyAuthenticator {
+    async fn authenticate(
+        &self,
+        headers: &HeaderMap,
+        state: &AppState,
+    ) -> Result<Option<Tenant>, AppError> {
+        let key = headers
+            .get("X-API-Key")
+            .and_then(|v| v.to_str().ok())
+            .or_else(|| bearer_token(headers).filter(|token| is_api_key(token)));
+
+        let Some(key) = key else {
+            return Ok(None);
+        };
+
+        let invalid = || AppError::Unauthorized("Invalid API key".to_string());
+
+        let lookup = api_key_lookup(key).ok_or_else(invalid)?;
+        let api_key = state
+            .repos
+            .api_keys
+            .find_by_lookup(lookup)
+            .await?
+            .ok_or_else(invalid)?;
+
+        if !constant_time_eq(hash_api_key(key).as_bytes(), api_key.key_hash.as_bytes()) {
+            return Err(invalid());
+        }
+
+        if let Err(e) = state.repos.api_keys.mark_used(api_key.oid).await {
+            tracing::warn!("Failed to record API key usage for {}: {}", api_key.id, e);
+        }
+
+        let tenant = state
+            .repos
+            .tenants
+            .find(api_key.tenant_oid)
+            .await?
+            .ok_or_else(invalid)?;
+
+        Ok(Some(tenant))
+    }
+}
+
+/// Verifies HS256 or RS256 bearer tokens and reads the tenant from a claim.
+pub struct JwtAuthenticator {
+    keys: Vec<(DecodingKey, Algorithm)>,
+    tenant_claim: String,
+    issuer: Option<String>,
+    audience: Option<String>,
+}
+
+impl JwtAuthenticator {
+    pub fn new(config: &Config) -> Result<Self, String> {
+        let mut keys = Vec::new();
+
+        if let Some(secret) = &config.jwt_hs256_secret {
+            keys.push((
+                DecodingKey::from_secret(secret.as_bytes()),
+                Algorithm::HS256,
+            ));
+        }
+
+        if let Some(pem) = &config.jwt_rs256_public_key {
+            let key = DecodingKey::from_rsa_pem(pem.as_bytes())
+                .map_err(|e| format!("JWT_RS256_PUBLIC_KEY is not a valid RSA PEM key: {}", e))?;
+            keys.push((key, Algorithm::RS256));
+        }
+
+        if keys.is_empty() {
+            return Err(
+                "jwt auth requires JWT_HS256_SECRET or JWT_RS256_PUBLIC_KEY to be set".to_string(),
+            );
+        }
+
+        Ok(Self {
+            keys,
+            tenant_claim: config.jwt_tenant_claim.clone(),
+            issuer: config.jwt_issuer.clone(),
+            audience: config.jwt_audience.clone(),
+        })
+    }
+
+    fn validation(&self, algorithm: Algorithm) -> Validation {
+        let mut validation = Validation::new(algorithm);
+
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+
+        validation
+    }
+
+    fn tenant_id_from_token(&self, token: &str) -> Result<String, AppError> {
+        let header = jsonwebtoken::decode_header(token)
+            .map_err(|_| AppError::Unauthorized("Invalid token".to_string()))?;
+
+        let (key, algorithm) = self
+            .keys
+            .iter()
+            .find(|(_, algorithm)| *algorithm == header.alg)
+            .ok_or_else(|| AppError::Unauthorized("Unsupported token algorithm".to_string()))?;
+
+        let claims = decode::<serde_json::Value>(token, key, &self.validation(*algorithm))
+            .map_err(|e| AppError::Unauthorized(format!("Invalid token: {}", e)))?
+            .claims;
+
+        claims
+            .get(&self.tenant_claim)
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+            .ok_or_else(|| {
+                AppError::Unauthorized(format!("Token is missing the {} claim", self.tenant_claim))
+            })
+    }
+}
+
+#[async_trait]
+impl Authenticator for JwtAuthenticator {
+    async fn authenticate(
+        &self,
+        headers: &HeaderMap,
+        state: &AppState,
+    ) -> Result<Option<Tenant>, AppError> {
+        let Some(token) = bearer_token(headers).filter(|token| !is_api_key(token)) else {
+            return Ok(None);
+        };
+
+        let tenant_id = self.tenant_id_from_token(token)?;
+
+        get_or_create_tenant(&tenant_id, "Authorization", state)
+            .await
+            .map(Some)
+    }
+}
+
+/// Generates a new API key, returning `(key, lookup, key_hash)`.
+///
+/// Keys look like `ch_<lookup>_<secret>`; the lookup part is stored in clear to
+/// find the row, the whole key only as a hash.
+pub fn generate_api_key() -> (String, String, String) {
+    let random = |len: usize| -> String {
+        rand::thread_rng()
+            .sample_iter(&rand::distributions::Alphanumeric)
+            .take(len)
+            .map(char::from)
+            .collect()
+    };
+
+    let lookup = random(API_KEY_LOOKUP_LEN);
+    let key = format!(
+        "{}_{}_{}",
+        API_KEY_PREFIX,
+        lookup,
+        random(API_KEY_SECRET_LEN)
+    );
+    let key_hash = hash_api_key(&key);
+
+    (key, lookup, key_hash)
+}
+
+fn hash_api_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+fn is_api_key(token: &str) -> bool {
+    token.starts_with(&format!("{}_", API_KEY_PREFIX))
+}
+
+fn api_key_lookup(key: &str) -> Option<&str> {
+    let mut parts = key.splitn(3, '_');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(API_KEY_PREFIX), Some(lookup), Some(secret))
+            if lookup.len() == API_KEY_LOOKUP_LEN && !secret.is_empty() =>
+        {
+            Some(lookup)
+        }
+        _ => None,
+    }
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|token| token.trim())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// `param` names where the id came from, for the error when it is malformed.
+pub(crate) async fn get_or_create_tenant(
+    tenant_id_str: &str,
+    param: &'static str,
+    state: &AppState,
+) -> Result<Tenant, AppError> {
+    if !Tenant::is_valid_id(tenant_id_str) {
+        return Err(AppError::invalid_param(param, INVALID_TENANT_ID));
+    }
+
+    if let Some(tenant) = state.repos.tenants.find_by_id(tenant_id_str).await? {
+        return Ok(tenant);
+    }
+
+    if !state.config.tenant_auto_create {
+        return Err(AppError::Unauthorized("Unknown tenant".to_string()));
+    }
+
+    let tenant_oid = state.snowflake_gen.generate().map_err(AppError::internal)?;
+
+    // Keep the caller's identifier as the tenant id so later requests find it.
+    let new_tenant = NewTenant {
+        oid: tenant_oid,
+        id: tenant_id_str.to_string(),
+        name: tenant_id_str.to_string(),
+        max_total_bytes: None,
+        max_file_count: None,
+        max_file_size_bytes: None,
+    };
+
+    state.repos.tenants.create_or_get(new_tenant).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_api_key_round_trip() {
+        let (key, lookup, key_hash) = generate_api_key();
+
+        assert!(is_api_key(&key));
+        assert_eq!(api_key_lookup(&key), Some(lookup.as_str()));
+        assert_eq!(hash_api_key(&key), key_hash);
+    }
+
+    #[test]
+    fn test_api_key_lookup_rejects_malformed_keys() {
+        assert_eq!(api_key_lookup("ch_short_secret"), None);
+        assert_eq!(api_key_lookup("xx_abcdefghijkl_secret"), None);
+        assert_eq!(api_key_lookup("ch_abcdefghijkl_"), None);
+        assert_eq!(api_key_lookup("ch_abcdefghijkl"), None);
+    }
+
+    #[test]
+    fn test_auth_method_parsing() {
+        assert_eq!("api_key".parse(), Ok(AuthMethod::ApiKey));
+        assert_eq!("jwt".parse(), Ok(AuthMethod::Jwt));
+        assert_eq!("tenant_header".parse(), Ok(AuthMethod::TenantHeader));
+        assert!("basic".parse::<AuthMethod>().is_err());
+    }
+
+    #[test]
+    fn test_tenant_header_must_be_used_alone() {
+        let mut config = crate::test_utils::create_test_config();
+        assert!(AuthChain::from_config(&config).is_ok());
+
+        config.auth_methods = vec![AuthMethod::ApiKey, AuthMethod::TenantHeader];
+        assert!(AuthChain::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"ab"));
+    }
+}
@@ -0,0 +1,83 @@
+use crate::models::{Blob, NewBlob};
+use crate::schema::blobs;
+use diesel::prelude::*;
+
+/// Finds the tenant's blob for a content hash, if one is still referenced.
+pub fn find_by_hash(
+    conn: &mut PgConnection,
+    tenant_oid: i64,
+    sha256: &str,
+) -> QueryResult<Option<Blob>> {
+    blobs::table
+        .filter(blobs::tenant_oid.eq(tenant_oid))
+        .filter(blobs::sha256.eq(sha256))
+        .filter(blobs::ref_count.gt(0))
+        .first(conn)
+        .optional()
+}
+
+/// Takes a reference on the tenant's blob for `sha256`, creating it if needed.
+///
+/// The insert and the reference count bump happen in a single upsert, so two
+/// concurrent uploads of the same content end up sharing one blob. If the
+/// returned blob's storage key differs from `storage_key`, the content already
+/// existed and the caller's freshly uploaded object is redundant.
+pub fn acquire(
+    conn: &mut PgConnection,
+    blob_oid: i64,
+    tenant_oid: i64,
+    sha256: &str,
+    bytes: i64,
+    storage_key: &str,
+) -> QueryResult<Blob> {
+    let new_blob = NewBlob {
+        oid: blob_oid,
+        id: crate::snowflake::generate_prefixed_id("blob", blob_oid),
+        tenant_oid,
+        sha256: sha256.to_string(),
+        bytes,
+        storage_key: storage_key.to_string(),
+        ref_count: 1,
+    };
+
+    diesel::insert_into(blobs::table)
+        .values(&new_blob)
+        .on_conflict((blobs::tenant_oid, blobs::sha256))
+        .do_update()
+        .set(blobs::ref_count.eq(blobs::ref_count + 1))
+        .get_result(conn)
+}
+
+/// Takes another reference on an existing blob.
+///
+/// Returns `None` if the blob was released concurrently and no longer exists.
+pub fn retain(conn: &mut PgConnection, blob_oid: i64) -> QueryResult<Option<Blob>> {
+    diesel::update(blobs::table.find(blob_oid))
+        .filter(blobs::ref_count.gt(0))
+        .set(blobs::ref_count.eq(blobs::ref_count + 1))
+        .get_result(conn)
+        .optional()
+}
+
+/// Drops a reference on a blob.
+///
+/// Returns the storage key to delete when this was the last reference, at
+/// which point the blob row itself is removed. Callers must remove or detach
+/// the referencing file row first.
+pub fn release(conn: &mut PgConnection, blob_oid: i64) -> QueryResult<Option<String>> {
+    let blob: Option<Blob> = diesel::update(blobs::table.find(blob_oid))
+        .set(blobs::ref_count.eq(blobs::ref_count - 1))
+        .get_result(conn)
+        .optional()?;
+
+    let Some(blob) = blob.filter(|blob| blob.ref_count <= 0) else {
+        return Ok(None);
+    };
+
+    // Only delete if nobody took a new reference in the meantime.
+    let deleted = diesel::delete(blobs::table.find(blob.oid))
+        .filter(blobs::ref_count.le(0))
+        .execute(conn)?;
+
+    Ok((deleted > 0).then_some(blob.storage_key))
+}
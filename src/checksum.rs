@@ -0,0 +1,129 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+pub const SHA256: &str = "sha256";
+pub const MD5: &str = "md5";
+pub const CRC32C: &str = "crc32c";
+
+/// The algorithms `EXTRA_CHECKSUMS` may name.
+pub const EXTRA_ALGORITHMS: &[&str] = &[MD5, CRC32C];
+
+/// Hex-encoded digests of an object's content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksums {
+    pub sha256: String,
+    pub md5: Option<String>,
+    pub crc32c: Option<String>,
+}
+
+/// Incrementally hashes content as it streams through.
+///
+/// SHA-256 is always computed; MD5 and CRC32C only when requested.
+pub struct ContentHasher {
+    sha256: Sha256,
+    md5: Option<Md5>,
+    crc32c: Option<u32>,
+}
+
+impl ContentHasher {
+    pub fn new(extra_algorithms: &[String]) -> Self {
+        let enabled = |name: &str| {
+            extra_algorithms
+                .iter()
+                .any(|a| a.eq_ignore_ascii_case(name))
+        };
+
+        Self {
+            sha256: Sha256::new(),
+            md5: enabled(MD5).then(Md5::new),
+            crc32c: enabled(CRC32C).then_some(0),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.sha256.update(data);
+
+        if let Some(md5) = self.md5.as_mut() {
+            md5.update(data);
+        }
+
+        if let Some(crc) = self.crc32c.as_mut() {
+            *crc = crc32c::crc32c_append(*crc, data);
+        }
+    }
+
+    pub fn finalize(self) -> Checksums {
+        Checksums {
+            sha256: format!("{:x}", self.sha256.finalize()),
+            md5: self.md5.map(|md5| format!("{:x}", md5.finalize())),
+            crc32c: self.crc32c.map(|crc| format!("{:08x}", crc)),
+        }
+    }
+}
+
+/// Quoted strong entity tag for a hex SHA-256 digest.
+pub fn etag(sha256_hex: &str) -> String {
+    format!("\"{}\"", sha256_hex)
+}
+
+/// RFC 3230 `Digest` header value for a hex SHA-256 digest.
+pub fn digest_header(sha256_hex: &str) -> Option<String> {
+    let bytes = decode_hex(sha256_hex)?;
+    Some(format!("sha-256={}", STANDARD.encode(bytes)))
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256_only_by_default() {
+        let mut hasher = ContentHasher::new(&[]);
+        hasher.update(b"hello ");
+        hasher.update(b"world");
+
+        let checksums = hasher.finalize();
+        assert_eq!(
+            checksums.sha256,
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+        assert_eq!(checksums.md5, None);
+        assert_eq!(checksums.crc32c, None);
+    }
+
+    #[test]
+    fn test_extra_algorithms() {
+        let mut hasher = ContentHasher::new(&["md5".to_string(), "CRC32C".to_string()]);
+        hasher.update(b"hello world");
+
+        let checksums = hasher.finalize();
+        assert_eq!(
+            checksums.md5.as_deref(),
+            Some("5eb63bbbe01eeed093cb22bb8f5acdc3")
+        );
+        assert_eq!(checksums.crc32c.as_deref(), Some("c99465aa"));
+    }
+
+    #[test]
+    fn test_digest_header() {
+        assert_eq!(
+            digest_header("b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9")
+                .as_deref(),
+            Some("sha-256=uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=")
+        );
+        assert_eq!(digest_header("abc"), None);
+        assert_eq!(digest_header("zz"), None);
+    }
+}
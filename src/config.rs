@@ -1,8 +1,21 @@
+use crate::auth::AuthMethod;
+use crate::checksum;
+use crate::file_reaper::PurposeTtl;
+use crate::links::{MAX_KEY_LEN, MAX_LINK_TTL_SECS, MIN_GENERATED_KEY_LEN};
+use crate::signed_urls::SigningKey;
 use std::env;
 
+/// The most any duration setting may be, a hundred years. Longer durations
+/// overflow the date arithmetic they feed into.
+pub const MAX_DURATION_SECS: i64 = 100 * 365 * 24 * 60 * 60;
+
 #[derive(Clone)]
 pub struct Config {
     pub database_url: String,
+    pub db_pool_max_size: u32,
+    pub db_pool_min_idle: Option<u32>,
+    pub db_pool_timeout_secs: u64,
+    pub db_pool_idle_timeout_secs: u64,
     pub public_host: String,
     pub public_port: u16,
     pub private_host: String,
@@ -10,16 +23,62 @@ pub struct Config {
     pub storage_base_url: String,
     pub storage_bucket: String,
     pub max_file_size_bytes: i64,
+    pub tenant_max_total_bytes: Option<i64>,
+    pub tenant_max_file_count: Option<i64>,
     pub allowed_purposes: Vec<String>,
+    pub purpose_default_ttls: Vec<PurposeTtl>,
+    pub file_reap_interval_secs: u64,
+    pub file_reap_batch_size: i64,
+    pub file_trash_retention_secs: i64,
+    pub extra_checksums: Vec<String>,
+    pub dedup_enabled: bool,
+    pub tenant_auto_create: bool,
+    pub reconcile_interval_secs: u64,
+    pub reconcile_fix: bool,
+    pub reconcile_orphan_grace_secs: i64,
+    pub auth_methods: Vec<AuthMethod>,
+    pub jwt_hs256_secret: Option<String>,
+    pub jwt_rs256_public_key: Option<String>,
+    pub jwt_tenant_claim: String,
+    pub jwt_issuer: Option<String>,
+    pub jwt_audience: Option<String>,
+    pub brand_name: Option<String>,
+    pub link_password_max_attempts: i32,
+    pub link_password_lockout_secs: i64,
+    pub link_min_ttl_secs: i64,
+    pub link_max_ttl_secs: i64,
+    pub link_key_length: usize,
+    pub link_sweep_interval_secs: u64,
+    pub link_sweep_batch_size: i64,
+    pub link_sweep_grace_secs: i64,
+    pub url_signing_keys: Vec<SigningKey>,
+    pub public_base_url: String,
     pub worker_id: u64,
     pub datacenter_id: u64,
 }
 
 impl Config {
     pub fn from_env() -> Result<Self, String> {
-        Ok(Self {
+        let config = Self {
             database_url: env::var("DATABASE_URL")
                 .map_err(|_| "DATABASE_URL must be set".to_string())?,
+            db_pool_max_size: env::var("DB_POOL_MAX_SIZE")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .map_err(|_| "DB_POOL_MAX_SIZE must be a valid u32".to_string())?,
+            db_pool_min_idle: env::var("DB_POOL_MIN_IDLE")
+                .ok()
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|_| "DB_POOL_MIN_IDLE must be a valid u32".to_string())?,
+            db_pool_timeout_secs: env::var("DB_POOL_TIMEOUT_SECS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .map_err(|_| "DB_POOL_TIMEOUT_SECS must be a valid u64".to_string())?,
+            db_pool_idle_timeout_secs: env::var("DB_POOL_IDLE_TIMEOUT_SECS")
+                .unwrap_or_else(|_| "600".to_string())
+                .parse()
+                .map_err(|_| "DB_POOL_IDLE_TIMEOUT_SECS must be a valid u64".to_string())?,
             public_host: env::var("PUBLIC_HOST").unwrap_or_else(|_| "0.0.0.0".to_string()),
             public_port: env::var("PUBLIC_PORT")
                 .unwrap_or_else(|_| "8080".to_string())
@@ -37,12 +96,124 @@ impl Config {
                 .unwrap_or_else(|_| "104857600".to_string())
                 .parse()
                 .map_err(|_| "MAX_FILE_SIZE_BYTES must be a valid i64".to_string())?,
+            tenant_max_total_bytes: env::var("TENANT_MAX_TOTAL_BYTES")
+                .ok()
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|_| "TENANT_MAX_TOTAL_BYTES must be a valid i64".to_string())?,
+            tenant_max_file_count: env::var("TENANT_MAX_FILE_COUNT")
+                .ok()
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|_| "TENANT_MAX_FILE_COUNT must be a valid i64".to_string())?,
             allowed_purposes: env::var("ALLOWED_PURPOSES")
                 .unwrap_or_else(|_| "user-upload,document,image,avatar".to_string())
                 .split(',')
                 .map(|s| s.trim().to_string())
                 .filter(|s| !s.is_empty())
                 .collect(),
+            purpose_default_ttls: env::var("PURPOSE_DEFAULT_TTLS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.parse())
+                .collect::<Result<_, _>>()?,
+            file_reap_interval_secs: env::var("FILE_REAP_INTERVAL_SECS")
+                .unwrap_or_else(|_| "3600".to_string())
+                .parse()
+                .map_err(|_| "FILE_REAP_INTERVAL_SECS must be a valid u64".to_string())?,
+            file_reap_batch_size: env::var("FILE_REAP_BATCH_SIZE")
+                .unwrap_or_else(|_| "100".to_string())
+                .parse()
+                .map_err(|_| "FILE_REAP_BATCH_SIZE must be a valid i64".to_string())?,
+            file_trash_retention_secs: env::var("FILE_TRASH_RETENTION_SECS")
+                .unwrap_or_else(|_| "604800".to_string())
+                .parse()
+                .map_err(|_| "FILE_TRASH_RETENTION_SECS must be a valid i64".to_string())?,
+            extra_checksums: env::var("EXTRA_CHECKSUMS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_ascii_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            dedup_enabled: env::var("DEDUP_ENABLED")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|_| "DEDUP_ENABLED must be true or false".to_string())?,
+            tenant_auto_create: env::var("TENANT_AUTO_CREATE")
+                .unwrap_or_else(|_| "true".to_string())
+                .parse()
+                .map_err(|_| "TENANT_AUTO_CREATE must be true or false".to_string())?,
+            reconcile_interval_secs: env::var("RECONCILE_INTERVAL_SECS")
+                .unwrap_or_else(|_| "3600".to_string())
+                .parse()
+                .map_err(|_| "RECONCILE_INTERVAL_SECS must be a valid u64".to_string())?,
+            reconcile_fix: env::var("RECONCILE_FIX")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|_| "RECONCILE_FIX must be true or false".to_string())?,
+            reconcile_orphan_grace_secs: env::var("RECONCILE_ORPHAN_GRACE_SECS")
+                .unwrap_or_else(|_| "3600".to_string())
+                .parse()
+                .map_err(|_| "RECONCILE_ORPHAN_GRACE_SECS must be a valid i64".to_string())?,
+            auth_methods: env::var("AUTH_METHODS")
+                .unwrap_or_else(|_| "api_key".to_string())
+                .split(',')
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.parse())
+                .collect::<Result<_, _>>()?,
+            jwt_hs256_secret: env::var("JWT_HS256_SECRET").ok(),
+            jwt_rs256_public_key: env::var("JWT_RS256_PUBLIC_KEY").ok(),
+            jwt_tenant_claim: env::var("JWT_TENANT_CLAIM")
+                .unwrap_or_else(|_| "tenant_id".to_string()),
+            jwt_issuer: env::var("JWT_ISSUER").ok(),
+            jwt_audience: env::var("JWT_AUDIENCE").ok(),
+            brand_name: env::var("BRAND_NAME").ok().filter(|s| !s.is_empty()),
+            link_password_max_attempts: env::var("LINK_PASSWORD_MAX_ATTEMPTS")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .map_err(|_| "LINK_PASSWORD_MAX_ATTEMPTS must be a valid i32".to_string())?,
+            link_password_lockout_secs: env::var("LINK_PASSWORD_LOCKOUT_SECS")
+                .unwrap_or_else(|_| "300".to_string())
+                .parse()
+                .map_err(|_| "LINK_PASSWORD_LOCKOUT_SECS must be a valid i64".to_string())?,
+            link_min_ttl_secs: env::var("LINK_MIN_TTL_SECS")
+                .unwrap_or_else(|_| "60".to_string())
+                .parse()
+                .map_err(|_| "LINK_MIN_TTL_SECS must be a valid i64".to_string())?,
+            link_max_ttl_secs: env::var("LINK_MAX_TTL_SECS")
+                .unwrap_or_else(|_| "2592000".to_string())
+                .parse()
+                .map_err(|_| "LINK_MAX_TTL_SECS must be a valid i64".to_string())?,
+            link_key_length: env::var("LINK_KEY_LENGTH")
+                .unwrap_or_else(|_| "16".to_string())
+                .parse()
+                .map_err(|_| "LINK_KEY_LENGTH must be a valid usize".to_string())?,
+            link_sweep_interval_secs: env::var("LINK_SWEEP_INTERVAL_SECS")
+                .unwrap_or_else(|_| "3600".to_string())
+                .parse()
+                .map_err(|_| "LINK_SWEEP_INTERVAL_SECS must be a valid u64".to_string())?,
+            link_sweep_batch_size: env::var("LINK_SWEEP_BATCH_SIZE")
+                .unwrap_or_else(|_| "1000".to_string())
+                .parse()
+                .map_err(|_| "LINK_SWEEP_BATCH_SIZE must be a valid i64".to_string())?,
+            link_sweep_grace_secs: env::var("LINK_SWEEP_GRACE_SECS")
+                .unwrap_or_else(|_| "604800".to_string())
+                .parse()
+                .map_err(|_| "LINK_SWEEP_GRACE_SECS must be a valid i64".to_string())?,
+            url_signing_keys: env::var("URL_SIGNING_KEYS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.parse())
+                .collect::<Result<_, _>>()?,
+            public_base_url: env::var("PUBLIC_BASE_URL")
+                .unwrap_or_default()
+                .trim_end_matches('/')
+                .to_string(),
             worker_id: env::var("WORKER_ID")
                 .unwrap_or_else(|_| "1".to_string())
                 .parse()
@@ -51,6 +222,73 @@ impl Config {
                 .unwrap_or_else(|_| "1".to_string())
                 .parse()
                 .map_err(|_| "DATACENTER_ID must be a valid u64".to_string())?,
-        })
+        };
+
+        if config.db_pool_max_size < 1 {
+            return Err("DB_POOL_MAX_SIZE must be at least 1".to_string());
+        }
+        if config
+            .db_pool_min_idle
+            .is_some_and(|min_idle| min_idle > config.db_pool_max_size)
+        {
+            return Err("DB_POOL_MIN_IDLE must not exceed DB_POOL_MAX_SIZE".to_string());
+        }
+        if config.db_pool_timeout_secs < 1 {
+            return Err("DB_POOL_TIMEOUT_SECS must be at least 1".to_string());
+        }
+        if !(1..=MAX_DURATION_SECS).contains(&config.link_password_lockout_secs) {
+            return Err(format!(
+                "LINK_PASSWORD_LOCKOUT_SECS must be between 1 and {}",
+                MAX_DURATION_SECS
+            ));
+        }
+        if config.link_min_ttl_secs < 1 || config.link_min_ttl_secs > config.link_max_ttl_secs {
+            return Err(
+                "LINK_MIN_TTL_SECS must be at least 1 and at most LINK_MAX_TTL_SECS".to_string(),
+            );
+        }
+        if config.link_max_ttl_secs > MAX_LINK_TTL_SECS {
+            return Err(format!(
+                "LINK_MAX_TTL_SECS must be at most {}",
+                MAX_LINK_TTL_SECS
+            ));
+        }
+        if config.file_reap_batch_size < 1 {
+            return Err("FILE_REAP_BATCH_SIZE must be at least 1".to_string());
+        }
+        if !(0..=MAX_DURATION_SECS).contains(&config.file_trash_retention_secs) {
+            return Err(format!(
+                "FILE_TRASH_RETENTION_SECS must be between 0 and {}",
+                MAX_DURATION_SECS
+            ));
+        }
+        if let Some(unknown) = config
+            .extra_checksums
+            .iter()
+            .find(|name| !checksum::EXTRA_ALGORITHMS.contains(&name.as_str()))
+        {
+            return Err(format!(
+                "EXTRA_CHECKSUMS: unknown algorithm '{}', expected one of {}",
+                unknown,
+                checksum::EXTRA_ALGORITHMS.join(", ")
+            ));
+        }
+        if !(0..=MAX_DURATION_SECS).contains(&config.link_sweep_grace_secs) {
+            return Err(format!(
+                "LINK_SWEEP_GRACE_SECS must be between 0 and {}",
+                MAX_DURATION_SECS
+            ));
+        }
+        if config.link_sweep_batch_size < 1 {
+            return Err("LINK_SWEEP_BATCH_SIZE must be at least 1".to_string());
+        }
+        if !(MIN_GENERATED_KEY_LEN..=MAX_KEY_LEN).contains(&config.link_key_length) {
+            return Err(format!(
+                "LINK_KEY_LENGTH must be between {} and {}",
+                MIN_GENERATED_KEY_LEN, MAX_KEY_LEN
+            ));
+        }
+
+        Ok(config)
     }
 }
@@ -0,0 +1,380 @@
+use crate::app_state::AppState;
+use crate::checksum;
+use crate::error::AppError;
+use crate::models::File;
+use crate::range::{format_http_date, if_range_matches, parse_range, ByteRange, RangeRequest};
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use futures::{stream, Stream, StreamExt};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use std::str::FromStr;
+
+pub const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// The width of `files.content_type`.
+const MAX_CONTENT_TYPE_LEN: usize = 255;
+
+/// Everything except RFC 5987 `attr-char`.
+const RFC5987_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'!')
+    .remove(b'#')
+    .remove(b'$')
+    .remove(b'&')
+    .remove(b'+')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'^')
+    .remove(b'_')
+    .remove(b'`')
+    .remove(b'|')
+    .remove(b'~');
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disposition {
+    Inline,
+    Attachment,
+}
+
+impl Disposition {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Disposition::Inline => "inline",
+            Disposition::Attachment => "attachment",
+        }
+    }
+}
+
+impl FromStr for Disposition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "inline" => Ok(Disposition::Inline),
+            "attachment" => Ok(Disposition::Attachment),
+            other => Err(format!("Unknown disposition: {}", other)),
+        }
+    }
+}
+
+/// Changes to how a file is presented, such as those carried by a signed URL.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ContentOverrides<'a> {
+    pub disposition: Option<Disposition>,
+    pub content_type: Option<&'a str>,
+}
+
+/// Streams a file's content from storage, honouring `Range` and `If-Range`.
+pub async fn serve_file_content(
+    state: &AppState,
+    file: &File,
+    headers: &HeaderMap,
+) -> Result<Response, AppError> {
+    serve_file_content_with(state, file, headers, ContentOverrides::default()).await
+}
+
+/// Like [`serve_file_content`], with the content type or disposition
+/// replaced. Active content is still forced to download.
+pub async fn serve_file_content_with(
+    state: &AppState,
+    file: &File,
+    headers: &HeaderMap,
+    overrides: ContentOverrides<'_>,
+) -> Result<Response, AppError> {
+    let total = file.bytes.max(0) as u64;
+    let last_modified = file.created_at;
+    let etag = file.sha256.as_deref().map(checksum::etag);
+
+    let range = match requested_range(file, headers) {
+        RangeRequest::Full => None,
+        RangeRequest::Partial(range) => Some(range),
+        RangeRequest::Unsatisfiable => {
+            return Ok((
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [
+                    (header::CONTENT_RANGE, format!("bytes */{}", total)),
+                    (header::ACCEPT_RANGES, "bytes".to_string()),
+                ],
+            )
+                .into_response());
+        }
+    };
+
+    let object = state
+        .storage_client
+        .download_stream(&file.storage_key, range)
+        .await
+        .map_err(AppError::storage)?;
+
+    let content_type = overrides.content_type.unwrap_or(&file.content_type);
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_str(content_type)
+            .unwrap_or_else(|_| HeaderValue::from_static(DEFAULT_CONTENT_TYPE)),
+    );
+    let disposition = if is_active_content(content_type) {
+        Disposition::Attachment
+    } else {
+        overrides.disposition.unwrap_or(Disposition::Inline)
+    };
+    if let Ok(value) =
+        HeaderValue::from_str(&content_disposition(disposition.as_str(), &file.filename))
+    {
+        response_headers.insert(header::CONTENT_DISPOSITION, value);
+    }
+    response_headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    response_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if let Ok(value) = HeaderValue::from_str(&format_http_date(last_modified)) {
+        response_headers.insert(header::LAST_MODIFIED, value);
+    }
+    if let Some(value) = etag.and_then(|etag| HeaderValue::from_str(&etag).ok()) {
+        response_headers.insert(header::ETAG, value);
+    }
+    if let Some(value) = file
+        .sha256
+        .as_deref()
+        .and_then(checksum::digest_header)
+        .and_then(|digest| HeaderValue::from_str(&digest).ok())
+    {
+        response_headers.insert("Digest", value);
+    }
+
+    let (status, content_length, body) = match range {
+        Some(range) => {
+            if let Ok(value) = HeaderValue::from_str(&range.content_range(total)) {
+                response_headers.insert(header::CONTENT_RANGE, value);
+            }
+
+            let body = if object.partial {
+                Body::from_stream(object.into_stream())
+            } else {
+                // Storage ignored the range, so cut it out of the full object.
+                Body::from_stream(slice_stream(object.into_stream(), range))
+            };
+
+            (StatusCode::PARTIAL_CONTENT, range.length(), body)
+        }
+        None => {
+            let length = object.content_length.unwrap_or(total);
+            (
+                StatusCode::OK,
+                length,
+                Body::from_stream(object.into_stream()),
+            )
+        }
+    };
+
+    response_headers.insert(header::CONTENT_LENGTH, HeaderValue::from(content_length));
+
+    Ok((status, response_headers, body).into_response())
+}
+
+/// The part of the file a request asks for, honouring `If-Range`.
+fn requested_range(file: &File, headers: &HeaderMap) -> RangeRequest {
+    let etag = file.sha256.as_deref().map(checksum::etag);
+
+    headers
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .filter(|_| {
+            headers
+                .get(header::IF_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .is_none_or(|if_range| if_range_matches(if_range, etag.as_deref(), file.created_at))
+        })
+        .map_or(RangeRequest::Full, |h| {
+            parse_range(h, file.bytes.max(0) as u64)
+        })
+}
+
+/// Picks the content type to store for an upload.
+///
+/// A well-formed, specific type from the multipart part header wins, as long
+/// as it fits the column; otherwise the leading bytes are sniffed for a known
+/// file signature.
+pub fn resolve_content_type(declared: Option<&str>, head: &[u8]) -> String {
+    let declared = declared
+        .and_then(|ct| ct.parse::<mime::Mime>().ok())
+        .filter(|ct| *ct != mime::APPLICATION_OCTET_STREAM)
+        .map(|ct| ct.to_string())
+        .filter(|ct| ct.len() <= MAX_CONTENT_TYPE_LEN);
+
+    if let Some(ct) = declared {
+        return ct;
+    }
+
+    infer::get(head)
+        .map(|kind| kind.mime_type().to_string())
+        .unwrap_or_else(|| DEFAULT_CONTENT_TYPE.to_string())
+}
+
+/// Builds a `Content-Disposition` value with an ASCII `filename` fallback and
+/// an RFC 5987 encoded `filename*` carrying the original UTF-8 name.
+pub fn content_disposition(disposition: &str, filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| {
+            if (c.is_ascii_graphic() || c == ' ') && c != '"' && c != '\\' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    format!(
+        "{}; filename=\"{}\"; filename*=UTF-8''{}",
+        disposition,
+        fallback,
+        utf8_percent_encode(filename, RFC5987_ENCODE_SET)
+    )
+}
+
+/// Types a browser would execute or render as a document on our origin.
+fn is_active_content(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    matches!(
+        essence.as_str(),
+        "text/html" | "application/xhtml+xml" | "image/svg+xml" | "text/xml" | "application/xml"
+    ) || essence.contains("javascript")
+}
+
+/// Restricts a stream of chunks to the bytes covered by `range`.
+fn slice_stream<S, E>(inner: S, range: ByteRange) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+{
+    let state = (inner.boxed(), range.start, range.length());
+
+    stream::unfold(state, |(mut inner, mut skip, remaining)| async move {
+        if remaining == 0 {
+            return None;
+        }
+
+        loop {
+            match inner.next().await? {
+                Err(e) => return Some((Err(e), (inner, 0, 0))),
+                Ok(chunk) => {
+                    let chunk_len = chunk.len() as u64;
+                    if skip >= chunk_len {
+                        skip -= chunk_len;
+                        continue;
+                    }
+
+                    let chunk = chunk.slice(skip as usize..);
+                    let take = remaining.min(chunk.len() as u64);
+                    let chunk = chunk.slice(..take as usize);
+
+                    return Some((Ok(chunk), (inner, 0, remaining - take)));
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::TryStreamExt;
+
+    async fn collect_slice(chunks: Vec<&'static str>, range: ByteRange) -> Vec<u8> {
+        let inner = stream::iter(
+            chunks
+                .into_iter()
+                .map(|c| Ok::<_, std::io::Error>(Bytes::from(c))),
+        );
+        let chunks: Vec<Bytes> = slice_stream(inner, range).try_collect().await.unwrap();
+        chunks.concat()
+    }
+
+    #[tokio::test]
+    async fn test_slice_stream_across_chunks() {
+        let sliced = collect_slice(vec!["abc", "def", "ghi"], ByteRange { start: 2, end: 6 }).await;
+        assert_eq!(sliced, b"cdefg");
+    }
+
+    #[tokio::test]
+    async fn test_slice_stream_within_chunk() {
+        let sliced = collect_slice(vec!["abcdefghi"], ByteRange { start: 3, end: 4 }).await;
+        assert_eq!(sliced, b"de");
+    }
+
+    #[test]
+    fn test_resolve_content_type_prefers_declared() {
+        assert_eq!(
+            resolve_content_type(Some("text/plain; charset=utf-8"), b"hello"),
+            "text/plain; charset=utf-8"
+        );
+    }
+
+    #[test]
+    fn test_resolve_content_type_sniffs_generic_uploads() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+        assert_eq!(resolve_content_type(None, png), "image/png");
+        assert_eq!(
+            resolve_content_type(Some("application/octet-stream"), png),
+            "image/png"
+        );
+        assert_eq!(resolve_content_type(Some("not a mime"), png), "image/png");
+        assert_eq!(resolve_content_type(None, b"plain"), DEFAULT_CONTENT_TYPE);
+    }
+
+    #[test]
+    fn test_resolve_content_type_sniffs_when_declared_is_too_long() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        let declared = format!("text/plain; note={}", "a".repeat(MAX_CONTENT_TYPE_LEN));
+
+        assert_eq!(resolve_content_type(Some(&declared), png), "image/png");
+        assert_eq!(
+            resolve_content_type(Some(&declared), b"plain"),
+            DEFAULT_CONTENT_TYPE
+        );
+    }
+
+    #[test]
+    fn test_content_disposition_ascii() {
+        assert_eq!(
+            content_disposition("inline", "report.pdf"),
+            "inline; filename=\"report.pdf\"; filename*=UTF-8''report.pdf"
+        );
+    }
+
+    #[test]
+    fn test_content_disposition_encodes_unicode_and_quotes() {
+        assert_eq!(
+            content_disposition("attachment", "résumé \"final\".pdf"),
+            "attachment; filename=\"r_sum_ _final_.pdf\"; \
+             filename*=UTF-8''r%C3%A9sum%C3%A9%20%22final%22.pdf"
+        );
+    }
+
+    #[test]
+    fn test_active_content_detection() {
+        assert!(is_active_content("text/html; charset=utf-8"));
+        assert!(is_active_content("image/svg+xml"));
+        assert!(is_active_content("application/javascript"));
+        assert!(!is_active_content("application/pdf"));
+        assert!(!is_active_content("video/mp4"));
+    }
+
+    #[tokio::test]
+    async fn test_slice_stream_suffix() {
+        let sliced = collect_slice(vec!["abc", "def"], ByteRange { start: 4, end: 5 }).await;
+        assert_eq!(sliced, b"ef");
+    }
+}
@@ -1,14 +1,52 @@
+use crate::config::Config;
+use crate::error::AppError;
 use diesel::prelude::*;
 use diesel::r2d2::{self, ConnectionManager};
+use std::time::Duration;
 
 pub type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
 
 #[allow(dead_code)]
 pub type DbConnection = r2d2::PooledConnection<ConnectionManager<PgConnection>>;
 
-pub fn create_pool(database_url: &str) -> Result<DbPool, r2d2::PoolError> {
-    let manager = ConnectionManager::<PgConnection>::new(database_url);
-    r2d2::Pool::builder().build(manager)
+/// Builds the connection pool from the `DB_POOL_*` settings. An idle
+/// timeout of 0 keeps idle connections open indefinitely.
+pub fn create_pool(config: &Config) -> Result<DbPool, r2d2::PoolError> {
+    let manager = ConnectionManager::<PgConnection>::new(&config.database_url);
+    r2d2::Pool::builder()
+        .max_size(config.db_pool_max_size)
+        .min_idle(config.db_pool_min_idle)
+        .connection_timeout(Duration::from_secs(config.db_pool_timeout_secs))
+        .idle_timeout(
+            (config.db_pool_idle_timeout_secs > 0)
+                .then(|| Duration::from_secs(config.db_pool_idle_timeout_secs)),
+        )
+        .build(manager)
+}
+
+/// Runs `f` with a pooled connection on the blocking thread pool. Diesel
+/// and r2d2 are synchronous, so waiting for a connection or a query must
+/// not happen on a runtime worker.
+pub async fn with_conn<T, F>(pool: &DbPool, f: F) -> Result<T, AppError>
+where
+    F: FnOnce(&mut PgConnection) -> Result<T, AppError> + Send + 'static,
+    T: Send + 'static,
+{
+    let pool = pool.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut conn = pool.get().map_err(AppError::database)?;
+        f(&mut conn)
+    })
+    .await
+    .map_err(AppError::internal)?
+}
+
+/// Escapes the wildcards of a `LIKE` pattern so `value` matches literally.
+pub fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
 }
 
 pub fn run_migrations(conn: &mut PgConnection) -> anyhow::Result<()> {
@@ -20,3 +58,14 @@ pub fn run_migrations(conn: &mut PgConnection) -> anyhow::Result<()> {
         .map_err(|e| anyhow::anyhow!("Migration error: {}", e))?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_like() {
+        assert_eq!(escape_like("tenant_1/"), "tenant\\_1/");
+        assert_eq!(escape_like("100%"), "100\\%");
+    }
+}
@@ -0,0 +1,76 @@
+use crate::app_state::AppState;
+use crate::error::AppError;
+use crate::models::PendingDeletion;
+
+/// Deletes scheduled objects from storage, clearing each record once its
+/// object is gone. Failures are noted on the record and left for a retry.
+///
+/// Returns how many records were cleared.
+pub async fn process(state: &AppState, pending: Vec<PendingDeletion>) -> usize {
+    let mut cleared = 0;
+
+    for deletion in pending {
+        let result = state.storage_client.delete(&deletion.storage_key).await;
+
+        let error = result.err().map(|e| {
+            tracing::warn!(
+                "Failed to delete object {}, will retry: {}",
+                deletion.storage_key,
+                e
+            );
+            e.to_string()
+        });
+
+        let deletions = &state.repos.deletions;
+        let outcome = match error {
+            None => deletions.clear(deletion.oid).await,
+            Some(error) => deletions
+                .record_attempt(deletion.oid, error)
+                .await
+                .map(|_| false),
+        };
+
+        match outcome {
+            Ok(true) => cleared += 1,
+            Ok(false) => {}
+            Err(e) => tracing::warn!(
+                "Failed to update pending deletion for {}: {}",
+                deletion.storage_key,
+                e
+            ),
+        }
+    }
+
+    cleared
+}
+
+/// Retries the oldest outstanding deletions, at most `limit` of them.
+pub async fn retry_pending(state: &AppState, limit: i64) -> Result<usize, AppError> {
+    let pending = state.repos.deletions.oldest(limit).await?;
+
+    Ok(process(state, pending).await)
+}
+
+/// Removes an object that never got a database row, such as an upload whose
+/// transaction failed. If storage refuses, the object is recorded for a retry.
+pub async fn discard(state: &AppState, storage_key: &str) {
+    let Err(e) = state.storage_client.delete(storage_key).await else {
+        return;
+    };
+
+    tracing::warn!("Failed to delete abandoned upload {}: {}", storage_key, e);
+
+    let recorded = state
+        .repos
+        .deletions
+        .record_failed(storage_key, e.to_string())
+        .await;
+
+    if let Err(err) = recorded {
+        tracing::error!(
+            "Orphaned object {} could not be recorded for deletion: {}",
+            storage_key,
+            err
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use crate::quota::QuotaExceeded;
+use crate::request_id;
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::{json, Map, Value};
+use std::fmt::Display;
+
+/// Errors returned by handlers, rendered as a JSON envelope:
+///
+/// ```json
+/// { "error": { "code": "invalid_parameter", "message": "...", "param": "after", "request_id": "req_..." } }
+/// ```
+///
+/// `code` is stable and documented in the README; `message` is for humans and
+/// may change. Server-side failures carry the underlying error, which is
+/// logged but never sent to the client.
+#[derive(Debug)]
+pub enum AppError {
+    BadRequest(String),
+    InvalidParam {
+        param: &'static str,
+        message: String,
+    },
+    MissingParam(&'static str),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound,
+    Conflict(String),
+    Gone(String),
+    PayloadTooLarge(String),
+    QuotaExceeded(QuotaExceeded),
+    RateLimited {
+        retry_after_secs: u64,
+    },
+    DatabaseError(String),
+    StorageError(String),
+    InternalError(String),
+}
+
+impl AppError {
+    pub fn invalid_param(param: &'static str, message: impl Into<String>) -> Self {
+        AppError::InvalidParam {
+            param,
+            message: message.into(),
+        }
+    }
+
+    /// For use with `map_err`, keeping the underlying error for the logs.
+    pub fn database(err: impl Display) -> Self {
+        AppError::DatabaseError(err.to_string())
+    }
+
+    pub fn storage(err: impl Display) -> Self {
+        AppError::StorageError(err.to_string())
+    }
+
+    pub fn internal(err: impl Display) -> Self {
+        AppError::InternalError(err.to_string())
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) | AppError::InvalidParam { .. } | AppError::MissingParam(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Gone(_) => StatusCode::GONE,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::QuotaExceeded(quota) => quota.kind.status(),
+            AppError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::DatabaseError(_) | AppError::StorageError(_) | AppError::InternalError(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    /// The stable, machine-readable error code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::BadRequest(_) => "invalid_request",
+            AppError::InvalidParam { .. } => "invalid_parameter",
+            AppError::MissingParam(_) => "missing_parameter",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::NotFound => "not_found",
+            AppError::Conflict(_) => "conflict",
+            AppError::Gone(_) => "gone",
+            AppError::PayloadTooLarge(_) => "payload_too_large",
+            AppError::QuotaExceeded(_) => "quota_exceeded",
+            AppError::RateLimited { .. } => "rate_limited",
+            AppError::DatabaseError(_) => "database_error",
+            AppError::StorageError(_) => "storage_error",
+            AppError::InternalError(_) => "internal_error",
+        }
+    }
+
+    pub fn param(&self) -> Option<&str> {
+        match self {
+            AppError::InvalidParam { param, .. } | AppError::MissingParam(param) => Some(param),
+            AppError::QuotaExceeded(quota) => Some(quota.kind.code()),
+            _ => None,
+        }
+    }
+
+    /// The message shown to the client.
+    pub fn message(&self) -> String {
+        match self {
+            AppError::BadRequest(msg)
+            | AppError::InvalidParam { message: msg, .. }
+            | AppError::Unauthorized(msg)
+            | AppError::Forbidden(msg)
+            | AppError::Conflict(msg)
+            | AppError::Gone(msg)
+            | AppError::PayloadTooLarge(msg) => msg.clone(),
+            AppError::MissingParam(param) => format!("Missing {}", param),
+            AppError::NotFound => "Not found".to_string(),
+            AppError::QuotaExceeded(quota) => quota.message(),
+            AppError::RateLimited { retry_after_secs } => {
+                format!("Too many requests, retry in {} seconds", retry_after_secs)
+            }
+            AppError::DatabaseError(_) => "Database error".to_string(),
+            AppError::StorageError(_) => "Storage error".to_string(),
+            AppError::InternalError(_) => "Internal error".to_string(),
+        }
+    }
+
+    /// Logs the error with its underlying cause; server errors at error level.
+    pub fn log(&self) {
+        let request_id = request_id::current();
+        if self.status().is_server_error() {
+            tracing::error!(request_id = request_id.as_deref(), "{}", self);
+        } else {
+            tracing::debug!(request_id = request_id.as_deref(), "{}", self);
+        }
+    }
+}
+
+impl Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::DatabaseError(source)
+            | AppError::StorageError(source)
+            | AppError::InternalError(source) => write!(f, "{}: {}", self.code(), source),
+            _ => write!(f, "{}: {}", self.code(), self.message()),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let request_id = request_id::current();
+        self.log();
+
+        let mut error = Map::new();
+        error.insert("code".to_string(), json!(self.code()));
+        error.insert("message".to_string(), json!(self.message()));
+        error.insert("param".to_string(), json!(self.param()));
+        error.insert("request_id".to_string(), json!(request_id));
+
+        if let AppError::QuotaExceeded(quota) = &self {
+            error.insert("limit".to_string(), json!(quota.limit));
+            error.insert("current".to_string(), json!(quota.current));
+            error.insert("requested".to_string(), json!(quota.requested));
+        }
+
+        let mut response = (status, Json(json!({ "error": Value::Object(error) }))).into_response();
+
+        if let AppError::RateLimited { retry_after_secs } = self {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after_secs));
+        }
+
+        response
+    }
+}
+
+impl From<diesel::result::Error> for AppError {
+    fn from(err: diesel::result::Error) -> Self {
+        AppError::database(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quota::QuotaKind;
+
+    async fn body_json(response: Response) -> Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_invalid_param_envelope() {
+        let response = AppError::invalid_param("after", "Invalid after id").into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["code"], "invalid_parameter");
+        assert_eq!(body["error"]["param"], "after");
+        assert_eq!(body["error"]["message"], "Invalid after id");
+        assert!(body["error"]["request_id"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_hide_source() {
+        let response = AppError::database("relation \"files\" does not exist").into_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["code"], "database_error");
+        assert_eq!(body["error"]["message"], "Database error");
+    }
+
+    #[tokio::test]
+    async fn test_quota_exceeded_details() {
+        let response = AppError::QuotaExceeded(QuotaExceeded {
+            kind: QuotaKind::FileCount,
+            limit: 2,
+            current: 2,
+            requested: 1,
+        })
+        .into_response();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let body = body_json(response).await;
+        assert_eq!(body["error"]["code"], "quota_exceeded");
+        assert_eq!(body["error"]["param"], "max_file_count");
+        assert_eq!(body["error"]["limit"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_sets_retry_after() {
+        let response = AppError::RateLimited {
+            retry_after_secs: 30,
+        }
+        .into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "30");
+    }
+}
@@ -0,0 +1,139 @@
+//! Axum's extractors, with rejections turned into [`AppError`]s so that
+//! malformed requests get the JSON error envelope like every other error.
+
+use crate::error::AppError;
+use axum::{
+    async_trait,
+    extract::{FromRequest, FromRequestParts, Request},
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+pub struct Query<T>(pub T);
+
+pub struct Path<T>(pub T);
+
+/// A JSON request body, or a JSON response.
+pub struct Json<T>(pub T);
+
+pub struct Form<T>(pub T);
+
+/// `param` names the part of the request that could not be read.
+fn rejection(param: &'static str, status: StatusCode, message: String) -> AppError {
+    if status == StatusCode::PAYLOAD_TOO_LARGE {
+        AppError::PayloadTooLarge(message)
+    } else if status.is_server_error() {
+        AppError::internal(message)
+    } else {
+        AppError::invalid_param(param, message)
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, AppError> {
+        axum::extract::Query::from_request_parts(parts, state)
+            .await
+            .map(|axum::extract::Query(value)| Query(value))
+            .map_err(|e| rejection("query", e.status(), e.body_text()))
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, AppError> {
+        axum::extract::Path::from_request_parts(parts, state)
+            .await
+            .map(|axum::extract::Path(value)| Path(value))
+            .map_err(|e| rejection("path", e.status(), e.body_text()))
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Json<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, AppError> {
+        axum::Json::from_request(req, state)
+            .await
+            .map(|axum::Json(value)| Json(value))
+            .map_err(|e| rejection("body", e.status(), e.body_text()))
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Form<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, AppError> {
+        axum::Form::from_request(req, state)
+            .await
+            .map(|axum::Form(value)| Form(value))
+            .map_err(|e| rejection("body", e.status(), e.body_text()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    struct Payload {
+        #[allow(dead_code)]
+        limit: i64,
+    }
+
+    #[tokio::test]
+    async fn test_rejections_are_app_errors() {
+        let request = Request::builder()
+            .uri("/?limit=ten")
+            .body(Body::empty())
+            .unwrap();
+        let (mut parts, _) = request.into_parts();
+        let err = Query::<Payload>::from_request_parts(&mut parts, &())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), "invalid_parameter");
+        assert_eq!(err.param(), Some("query"));
+
+        let request = Request::builder()
+            .header("content-type", "application/json")
+            .body(Body::from("{"))
+            .unwrap();
+        let err = Json::<Payload>::from_request(request, &())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.param(), Some("body"));
+    }
+}
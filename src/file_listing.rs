@@ -0,0 +1,454 @@
+use crate::app_state::AppState;
+use crate::db::escape_like;
+use crate::error::AppError;
+use crate::models::{FileRecord, FileRecordSource, ListFilesQuery, PaginationResponse};
+use crate::pagination::{self, CursorKey, PageParams};
+use crate::schema::{files, purposes};
+use chrono::{NaiveDateTime, Utc};
+use diesel::dsl::sql;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Bool, Text};
+use std::str::FromStr;
+
+type BoxedPredicate<QS> = Box<dyn BoxableExpression<QS, Pg, SqlType = Bool>>;
+
+/// Filenames sort by byte value, as [`FileKey`] does in memory, rather than
+/// by whatever collation the database was created with.
+const FILENAME_BYTEWISE: &str = r#"files.filename COLLATE "C""#;
+
+type BoxedFileQuery<'a> = diesel::dsl::IntoBoxed<
+    'a,
+    diesel::dsl::Select<FileRecordSource, diesel::dsl::AsSelect<FileRecord, Pg>>,
+    Pg,
+>;
+
+/// The orders a file listing can be sorted in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileSort {
+    CreatedAt,
+    Bytes,
+    Filename,
+}
+
+impl FromStr for FileSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "created_at" => Ok(Self::CreatedAt),
+            "bytes" => Ok(Self::Bytes),
+            "filename" => Ok(Self::Filename),
+            _ => Err("sort must be created_at, bytes or filename".to_string()),
+        }
+    }
+}
+
+/// Where a file sits in a listing. Snowflake oids are handed out in creation
+/// order, so sorting by `created_at` is sorting by `oid`; the other sorts
+/// break ties on `oid`. Keys of the same sort order like their columns.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FileKey {
+    CreatedAt(i64),
+    Bytes(i64, i64),
+    Filename(String, i64),
+}
+
+impl FileKey {
+    pub fn of(sort: FileSort, record: &FileRecord) -> Self {
+        let file = &record.file;
+        match sort {
+            FileSort::CreatedAt => Self::CreatedAt(file.oid),
+            FileSort::Bytes => Self::Bytes(file.bytes, file.oid),
+            FileSort::Filename => Self::Filename(file.filename.clone(), file.oid),
+        }
+    }
+
+    /// Reads a key back from a cursor, rejecting cursors of another sort.
+    fn decode(sort: FileSort, bytes: &[u8]) -> Option<Self> {
+        let (tag, rest) = bytes.split_first()?;
+        match (sort, tag) {
+            (FileSort::CreatedAt, 0) => pagination::decode_oid(rest).map(Self::CreatedAt),
+            (FileSort::Bytes, 1) => {
+                let (size, oid) = rest.split_at_checked(8)?;
+                Some(Self::Bytes(
+                    pagination::decode_oid(size)?,
+                    pagination::decode_oid(oid)?,
+                ))
+            }
+            (FileSort::Filename, 2) => {
+                let (oid, name) = rest.split_at_checked(8)?;
+                Some(Self::Filename(
+                    String::from_utf8(name.to_vec()).ok()?,
+                    pagination::decode_oid(oid)?,
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Compares the sort columns of a row against this key with `op`. Row
+    /// comparisons order tuples lexicographically, which is exactly the
+    /// order of `ORDER BY <column>, oid`.
+    fn compare<QS>(&self, op: &str) -> BoxedPredicate<QS> {
+        match self {
+            Self::CreatedAt(oid) => {
+                Box::new(sql::<Bool>(&format!("files.oid {} ", op)).bind::<BigInt, _>(*oid))
+            }
+            Self::Bytes(bytes, oid) => Box::new(
+                sql::<Bool>(&format!("(files.bytes, files.oid) {} (", op))
+                    .bind::<BigInt, _>(*bytes)
+                    .sql(", ")
+                    .bind::<BigInt, _>(*oid)
+                    .sql(")"),
+            ),
+            Self::Filename(filename, oid) => Box::new(
+                sql::<Bool>(&format!("({}, files.oid) {} (", FILENAME_BYTEWISE, op))
+                    .bind::<Text, _>(filename.clone())
+                    .sql(", ")
+                    .bind::<BigInt, _>(*oid)
+                    .sql(")"),
+            ),
+        }
+    }
+}
+
+impl CursorKey for FileKey {
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::CreatedAt(oid) => [&[0][..], &oid.to_be_bytes()].concat(),
+            Self::Bytes(bytes, oid) => {
+                [&[1][..], &bytes.to_be_bytes(), &oid.to_be_bytes()].concat()
+            }
+            Self::Filename(filename, oid) => {
+                [&[2][..], &oid.to_be_bytes(), filename.as_bytes()].concat()
+            }
+        }
+    }
+}
+
+/// The filters of a file listing, validated.
+pub struct Filters {
+    /// Live listings leave out files past their expiry as of this time.
+    now: NaiveDateTime,
+    tenant_oid: Option<i64>,
+    deleted: bool,
+    purpose: Option<String>,
+    filename_prefix: Option<String>,
+    filename_contains: Option<String>,
+    content_type: Option<String>,
+    min_created_at: Option<NaiveDateTime>,
+    max_created_at: Option<NaiveDateTime>,
+    min_updated_at: Option<NaiveDateTime>,
+    max_updated_at: Option<NaiveDateTime>,
+    min_bytes: Option<i64>,
+    max_bytes: Option<i64>,
+}
+
+impl Filters {
+    fn parse(query: &ListFilesQuery, tenant_oid: Option<i64>) -> Result<Self, AppError> {
+        let filters = Self {
+            now: Utc::now().naive_utc(),
+            tenant_oid,
+            deleted: query.deleted.unwrap_or(false),
+            purpose: query.purpose.clone(),
+            filename_prefix: query.filename_prefix.clone(),
+            filename_contains: query.filename_contains.clone(),
+            content_type: query.content_type.clone(),
+            min_created_at: timestamp("min_created_at", query.min_created_at)?,
+            max_created_at: timestamp("max_created_at", query.max_created_at)?,
+            min_updated_at: timestamp("min_updated_at", query.min_updated_at)?,
+            max_updated_at: timestamp("max_updated_at", query.max_updated_at)?,
+            min_bytes: query.min_bytes,
+            max_bytes: query.max_bytes,
+        };
+        ordered(
+            "min_created_at",
+            filters.min_created_at,
+            filters.max_created_at,
+        )?;
+        ordered(
+            "min_updated_at",
+            filters.min_updated_at,
+            filters.max_updated_at,
+        )?;
+        ordered("min_bytes", filters.min_bytes, filters.max_bytes)?;
+        Ok(filters)
+    }
+
+    fn query(&self) -> BoxedFileQuery<'_> {
+        let mut query = FileRecord::query().into_boxed();
+        if self.deleted {
+            query = query.filter(files::deleted_at.is_not_null());
+        } else {
+            query = query.filter(files::deleted_at.is_null()).filter(
+                files::expires_at
+                    .is_null()
+                    .or(files::expires_at.gt(self.now)),
+            );
+        }
+        if let Some(tenant_oid) = self.tenant_oid {
+            query = query.filter(files::tenant_oid.eq(tenant_oid));
+        }
+        if let Some(purpose) = &self.purpose {
+            query = query.filter(purposes::slug.eq(purpose));
+        }
+        if let Some(prefix) = &self.filename_prefix {
+            query = query.filter(files::filename.like(format!("{}%", escape_like(prefix))));
+        }
+        if let Some(needle) = &self.filename_contains {
+            query = query.filter(files::filename.ilike(format!("%{}%", escape_like(needle))));
+        }
+        if let Some(content_type) = &self.content_type {
+            query = match content_type.strip_suffix("/*") {
+                Some(top_level) => {
+                    query.filter(files::content_type.like(format!("{}/%", escape_like(top_level))))
+                }
+                None => query.filter(files::content_type.eq(content_type)),
+            };
+        }
+        if let Some(min) = self.min_created_at {
+            query = query.filter(files::created_at.ge(min));
+        }
+        if let Some(max) = self.max_created_at {
+            query = query.filter(files::created_at.le(max));
+        }
+        if let Some(min) = self.min_updated_at {
+            query = query.filter(files::updated_at.ge(min));
+        }
+        if let Some(max) = self.max_updated_at {
+            query = query.filter(files::updated_at.le(max));
+        }
+        if let Some(min) = self.min_bytes {
+            query = query.filter(files::bytes.ge(min));
+        }
+        if let Some(max) = self.max_bytes {
+            query = query.filter(files::bytes.le(max));
+        }
+        query
+    }
+
+    /// Evaluates the filters of [`Filters::query`] on a loaded record.
+    pub fn matches(&self, record: &FileRecord) -> bool {
+        fn within<T: PartialOrd>(value: T, min: Option<T>, max: Option<T>) -> bool {
+            min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max)
+        }
+
+        let file = &record.file;
+
+        let listed = if self.deleted {
+            file.deleted_at.is_some()
+        } else {
+            file.is_live(self.now)
+        };
+
+        listed
+            && self.tenant_oid.is_none_or(|oid| file.tenant_oid == oid)
+            && self.purpose.as_ref().is_none_or(|p| record.purpose == *p)
+            && self
+                .filename_prefix
+                .as_ref()
+                .is_none_or(|prefix| file.filename.starts_with(prefix.as_str()))
+            && self.filename_contains.as_ref().is_none_or(|needle| {
+                file.filename
+                    .to_lowercase()
+                    .contains(&needle.to_lowercase())
+            })
+            && self.content_type.as_ref().is_none_or(|content_type| {
+                match content_type.strip_suffix("/*") {
+                    Some(top_level) => file
+                        .content_type
+                        .strip_prefix(top_level)
+                        .is_some_and(|rest| rest.starts_with('/')),
+                    None => file.content_type == *content_type,
+                }
+            })
+            && within(file.created_at, self.min_created_at, self.max_created_at)
+            && within(file.updated_at, self.min_updated_at, self.max_updated_at)
+            && within(file.bytes, self.min_bytes, self.max_bytes)
+    }
+}
+
+/// Rejects a lower bound above its upper bound, which could match nothing.
+fn ordered<T: PartialOrd>(
+    param: &'static str,
+    min: Option<T>,
+    max: Option<T>,
+) -> Result<(), AppError> {
+    match (min, max) {
+        (Some(min), Some(max)) if min > max => Err(AppError::invalid_param(
+            param,
+            format!("{} must not be greater than max_{}", param, &param[4..]),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn timestamp(param: &'static str, value: Option<i64>) -> Result<Option<NaiveDateTime>, AppError> {
+    value
+        .map(|ts| {
+            chrono::DateTime::from_timestamp(ts, 0)
+                .map(|dt| dt.naive_utc())
+                .ok_or_else(|| AppError::invalid_param(param, format!("Invalid {}", param)))
+        })
+        .transpose()
+}
+
+/// One page of a file listing.
+pub struct FilePage {
+    pub files: Vec<FileRecord>,
+    pub pagination: PaginationResponse,
+    pub total_count: Option<i64>,
+}
+
+/// A validated file listing request.
+pub struct Listing {
+    pub sort: FileSort,
+    pub page: PageParams<FileKey>,
+    pub filters: Filters,
+    pub include_total: bool,
+}
+
+impl Listing {
+    /// Restricts the listing to one tenant when `tenant_oid` is set.
+    /// `query.tenant_id` is left to the caller to resolve.
+    pub fn parse(query: &ListFilesQuery, tenant_oid: Option<i64>) -> Result<Self, AppError> {
+        let sort = query
+            .sort
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(|e: String| AppError::invalid_param("sort", e))?
+            .unwrap_or(FileSort::CreatedAt);
+
+        let page = PageParams::parse_with(
+            query.limit,
+            query.order.as_deref(),
+            query.after.as_deref(),
+            query.before.as_deref(),
+            |bytes| FileKey::decode(sort, bytes),
+        )?;
+
+        Ok(Self {
+            sort,
+            page,
+            filters: Filters::parse(query, tenant_oid)?,
+            include_total: query.include_total.unwrap_or(false),
+        })
+    }
+}
+
+/// Lists the files matching `query` through the file repository.
+pub async fn list(
+    state: &AppState,
+    query: &ListFilesQuery,
+    tenant_oid: Option<i64>,
+) -> Result<FilePage, AppError> {
+    let listing = Listing::parse(query, tenant_oid)?;
+
+    if let Some(slug) = &query.purpose {
+        state
+            .repos
+            .purposes
+            .find_by_slug(slug)
+            .await?
+            .ok_or_else(|| AppError::invalid_param("purpose", "Invalid purpose"))?;
+    }
+
+    state.repos.files.list(listing).await
+}
+
+/// Runs a listing against Postgres.
+pub fn load(conn: &mut PgConnection, listing: &Listing) -> Result<FilePage, AppError> {
+    let (sort, filters) = (listing.sort, &listing.filters);
+
+    let (files, pagination) = pagination::paginate_by(
+        &listing.page,
+        |record: &FileRecord| FileKey::of(sort, record),
+        |range, descending, limit| {
+            let mut base_query = filters.query();
+            if let Some(gt) = &range.gt {
+                base_query =
+                    base_query.filter(gt.key.compare(if gt.inclusive { ">=" } else { ">" }));
+            }
+            if let Some(lt) = &range.lt {
+                base_query =
+                    base_query.filter(lt.key.compare(if lt.inclusive { "<=" } else { "<" }));
+            }
+            base_query = match (sort, descending) {
+                (FileSort::CreatedAt, false) => base_query.order(files::oid.asc()),
+                (FileSort::CreatedAt, true) => base_query.order(files::oid.desc()),
+                (FileSort::Bytes, false) => {
+                    base_query.order((files::bytes.asc(), files::oid.asc()))
+                }
+                (FileSort::Bytes, true) => {
+                    base_query.order((files::bytes.desc(), files::oid.desc()))
+                }
+                (FileSort::Filename, false) => {
+                    base_query.order((sql::<Text>(FILENAME_BYTEWISE).asc(), files::oid.asc()))
+                }
+                (FileSort::Filename, true) => {
+                    base_query.order((sql::<Text>(FILENAME_BYTEWISE).desc(), files::oid.desc()))
+                }
+            };
+            base_query
+                .limit(limit)
+                .load(conn)
+                .map_err(AppError::database)
+        },
+    )?;
+
+    let total_count = if listing.include_total {
+        Some(
+            filters
+                .query()
+                .count()
+                .get_result(conn)
+                .map_err(AppError::database)?,
+        )
+    } else {
+        None
+    };
+
+    Ok(FilePage {
+        files,
+        pagination,
+        total_count,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sort_parsing() {
+        assert_eq!("created_at".parse(), Ok(FileSort::CreatedAt));
+        assert_eq!("bytes".parse(), Ok(FileSort::Bytes));
+        assert_eq!("filename".parse(), Ok(FileSort::Filename));
+        assert!("size".parse::<FileSort>().is_err());
+    }
+
+    #[test]
+    fn test_key_roundtrip() {
+        let keys = [
+            (FileSort::CreatedAt, FileKey::CreatedAt(42)),
+            (FileSort::Bytes, FileKey::Bytes(1_024, 42)),
+            (
+                FileSort::Filename,
+                FileKey::Filename("report 2026.pdf".to_string(), 42),
+            ),
+        ];
+        for (sort, key) in keys {
+            assert_eq!(FileKey::decode(sort, &key.to_bytes()), Some(key));
+        }
+    }
+
+    #[test]
+    fn test_key_of_another_sort_is_rejected() {
+        let key = FileKey::Bytes(1_024, 42).to_bytes();
+        assert_eq!(FileKey::decode(FileSort::Filename, &key), None);
+        assert_eq!(FileKey::decode(FileSort::CreatedAt, &key), None);
+        assert_eq!(FileKey::decode(FileSort::Bytes, &key[..5]), None);
+    }
+}
@@ -0,0 +1,201 @@
+use crate::app_state::AppState;
+use crate::config::{Config, MAX_DURATION_SECS};
+use crate::deletions;
+use crate::error::AppError;
+use crate::repository::Due;
+use chrono::{Duration, NaiveDateTime, Utc};
+use std::str::FromStr;
+use std::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+/// A default lifetime for files of one purpose, configured as `<purpose>=<seconds>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PurposeTtl {
+    pub purpose: String,
+    pub ttl_secs: i64,
+}
+
+impl FromStr for PurposeTtl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (purpose, ttl) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Purpose TTLs must look like <purpose>=<seconds>: {}", s))?;
+
+        let ttl_secs = ttl
+            .trim()
+            .parse()
+            .ok()
+            .filter(|secs: &i64| (1..=MAX_DURATION_SECS).contains(secs))
+            .ok_or_else(|| format!("Invalid TTL for purpose {}: {}", purpose, ttl))?;
+
+        Ok(Self {
+            purpose: purpose.trim().to_string(),
+            ttl_secs,
+        })
+    }
+}
+
+/// Reads an `expires_at` given as a Unix timestamp, which must lie in the future.
+pub fn parse_expires_at(timestamp: i64, now: NaiveDateTime) -> Result<NaiveDateTime, AppError> {
+    let expires_at = chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|dt| dt.naive_utc())
+        .ok_or_else(|| AppError::invalid_param("expires_at", "Invalid expires_at"))?;
+
+    if expires_at <= now {
+        return Err(AppError::invalid_param(
+            "expires_at",
+            "expires_at must be in the future",
+        ));
+    }
+
+    Ok(expires_at)
+}
+
+/// The expiry of a new file whose upload did not set one.
+pub fn default_expires_at(
+    config: &Config,
+    purpose: &str,
+    now: NaiveDateTime,
+) -> Option<NaiveDateTime> {
+    config
+        .purpose_default_ttls
+        .iter()
+        .find(|ttl| ttl.purpose == purpose)
+        .map(|ttl| now + Duration::seconds(ttl.ttl_secs))
+}
+
+/// What one reaping pass removed.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ReapReport {
+    pub files: usize,
+    pub bytes: i64,
+    pub links: i64,
+    /// How many of `files` were purged from the trash.
+    pub purged: usize,
+}
+
+/// Deletes expired files and purges the trash, `FILE_REAP_BATCH_SIZE` files
+/// at a time.
+///
+/// Each file is removed like `DELETE /files/:file_id?permanent=true`: the row,
+/// its links, its blob reference and the tenant counters go in one
+/// transaction, and the object is deleted from storage afterwards. A file that
+/// was restored or whose expiry was moved since it was selected is left alone.
+/// Stops between batches once `shutdown` is cancelled.
+pub async fn reap(state: &AppState, shutdown: &CancellationToken) -> Result<ReapReport, AppError> {
+    let now = Utc::now().naive_utc();
+    let due = Due {
+        now,
+        purge_before: now - Duration::seconds(state.config.file_trash_retention_secs),
+    };
+    let batch_size = state.config.file_reap_batch_size;
+    let mut report = ReapReport::default();
+
+    while !shutdown.is_cancelled() {
+        let expired = state.repos.files.find_due(due, batch_size).await?;
+
+        let mut scheduled = Vec::new();
+        for file in &expired {
+            let Some(removed) = state
+                .repos
+                .files
+                .remove_permanently(file.oid, Some(due))
+                .await?
+            else {
+                continue;
+            };
+
+            report.files += 1;
+            report.bytes += removed.file.bytes;
+            report.links += removed.links;
+            if removed.file.deleted_at.is_some() {
+                report.purged += 1;
+            }
+            scheduled.extend(removed.scheduled);
+        }
+
+        deletions::process(state, scheduled).await;
+
+        if (expired.len() as i64) < batch_size {
+            break;
+        }
+    }
+
+    Ok(report)
+}
+
+/// Reaps expired and trashed files every `interval` until `shutdown` is cancelled.
+pub async fn run_periodically(
+    state: AppState,
+    interval: std::time::Duration,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // The first tick completes immediately; wait a full interval after startup.
+    ticker.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                tracing::info!("File reaper stopped");
+                return;
+            }
+            _ = ticker.tick() => {}
+        }
+
+        let started = Instant::now();
+        match reap(&state, &shutdown).await {
+            Ok(report) if report.files > 0 => tracing::info!(
+                files = report.files,
+                bytes = report.bytes,
+                links = report.links,
+                purged = report.purged,
+                elapsed_ms = started.elapsed().as_millis() as u64,
+                "Reaped expired and trashed files"
+            ),
+            Ok(_) => tracing::debug!("No expired or trashed files to reap"),
+            Err(e) => tracing::warn!("File reaping failed: {:?}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+
+    #[test]
+    fn test_purpose_ttl_parsing() {
+        assert_eq!(
+            "user-upload=604800".parse(),
+            Ok(PurposeTtl {
+                purpose: "user-upload".to_string(),
+                ttl_secs: 604_800,
+            })
+        );
+        assert!("user-upload".parse::<PurposeTtl>().is_err());
+        assert!("user-upload=0".parse::<PurposeTtl>().is_err());
+        assert!(format!("user-upload={}", MAX_DURATION_SECS + 1)
+            .parse::<PurposeTtl>()
+            .is_err());
+        assert!("user-upload=soon".parse::<PurposeTtl>().is_err());
+    }
+
+    #[test]
+    fn test_parse_expires_at() {
+        let now = Utc::now().naive_utc();
+        let future = now.and_utc().timestamp() + 60;
+        assert_eq!(
+            parse_expires_at(future, now).unwrap().and_utc().timestamp(),
+            future
+        );
+
+        let err = parse_expires_at(now.and_utc().timestamp() - 1, now).unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(err.param(), Some("expires_at"));
+        assert!(parse_expires_at(i64::MAX, now).is_err());
+    }
+}
@@ -1,155 +1,223 @@
 use crate::app_state::AppState;
-use crate::handlers_public::AppError;
+use crate::auth;
+use crate::checksum::{self, ContentHasher};
+use crate::content::Disposition;
+use crate::deletions;
+use crate::error::AppError;
+use crate::extract::{Json, Path, Query};
+use crate::file_listing;
+use crate::file_reaper;
+use crate::links::{self, LinkState};
 use crate::models::*;
-use crate::schema::*;
-use axum::{
-    extract::{Path, Query, State},
-    Json,
-};
+use crate::pagination::PageParams;
+use crate::reconcile::{self, ReconcileMode};
+use crate::repository::LinkFilter;
+use crate::signed_urls::{self, SignedUrlClaims};
+use axum::extract::State;
 use chrono::{Duration, Utc};
-use diesel::prelude::*;
+use futures::StreamExt;
 
+const MAX_LINK_PASSWORD_LEN: usize = 256;
+
+/// Moves a file to the trash, or with `?permanent=true` deletes it for good.
+///
+/// Trashed files are hidden from every endpoint except `GET /files?deleted=true`
+/// and keep counting towards their tenant's quota until they are purged.
 pub async fn delete_file(
     State(state): State<AppState>,
     Path(file_id): Path<String>,
+    Query(query): Query<DeleteFileQuery>,
 ) -> Result<Json<FileResponse>, AppError> {
-    let mut conn = state.db_pool.get().map_err(|_| AppError::DatabaseError)?;
-
-    let file: File = files::table
-        .filter(files::id.eq(&file_id))
-        .first(&mut conn)
-        .map_err(|_| AppError::NotFound)?;
+    let permanent = query.permanent.unwrap_or(false);
+
+    let record = find_file(&state, &file_id)
+        .await?
+        .filter(|record| permanent || record.file.deleted_at.is_none())
+        .ok_or(AppError::NotFound)?;
+
+    if !permanent {
+        let file = state
+            .repos
+            .files
+            .trash(record.file.oid)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        return Ok(Json(FileRecord { file, ..record }.into()));
+    }
 
-    let tenant: Tenant = tenants::table
-        .find(file.tenant_oid)
-        .first(&mut conn)
-        .map_err(|_| AppError::DatabaseError)?;
+    // The row, the counters and the deletion record commit together; the
+    // object itself is removed once the transaction is durable.
+    let removed = state
+        .repos
+        .files
+        .remove_permanently(record.file.oid, None)
+        .await?
+        .ok_or(AppError::NotFound)?;
 
-    let purpose: Purpose = purposes::table
-        .find(file.purpose_oid)
-        .first(&mut conn)
-        .map_err(|_| AppError::DatabaseError)?;
+    deletions::process(&state, removed.scheduled).await;
 
-    state
-        .storage_client
-        .delete(&file.storage_key)
-        .await
-        .map_err(|e| AppError::StorageError(e.to_string()))?;
-
-    diesel::delete(files::table.find(file.oid))
-        .execute(&mut conn)
-        .map_err(|_| AppError::DatabaseError)?;
-
-    diesel::update(tenants::table.find(tenant.oid))
-        .set((
-            tenants::total_files_bytes.eq(tenants::total_files_bytes - file.bytes),
-            tenants::file_count.eq(tenants::file_count - 1),
-            tenants::updated_at.eq(Utc::now().naive_utc()),
-        ))
-        .execute(&mut conn)
-        .map_err(|_| AppError::DatabaseError)?;
-
-    Ok(Json(FileResponse {
-        id: file.id,
-        object: "file".to_string(),
-        bytes: file.bytes,
-        created_at: file.created_at.and_utc().timestamp(),
-        updated_at: file.updated_at.and_utc().timestamp(),
-        filename: file.filename,
-        purpose: purpose.slug,
-        tenant_id: Some(tenant.id),
-    }))
+    Ok(Json(record.into()))
 }
 
-pub async fn update_file(
+/// Takes a file out of the trash. Restoring a file that is not in the trash
+/// changes nothing.
+pub async fn restore_file(
     State(state): State<AppState>,
     Path(file_id): Path<String>,
-    Json(payload): Json<UpdateFileRequest>,
 ) -> Result<Json<FileResponse>, AppError> {
-    let mut conn = state.db_pool.get().map_err(|_| AppError::DatabaseError)?;
+    let record = find_file(&state, &file_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
 
-    let file: File = files::table
-        .filter(files::id.eq(&file_id))
-        .first(&mut conn)
-        .map_err(|_| AppError::NotFound)?;
+    if record.file.deleted_at.is_none() {
+        return Ok(Json(record.into()));
+    }
 
-    let mut purpose_oid = file.purpose_oid;
+    // The purge job may have removed the file in the meantime.
+    let file = state
+        .repos
+        .files
+        .restore(record.file.oid)
+        .await?
+        .ok_or(AppError::NotFound)?;
 
-    if let Some(ref purpose_slug) = payload.purpose {
-        let purpose: Purpose = purposes::table
-            .filter(purposes::slug.eq(purpose_slug))
-            .first(&mut conn)
-            .map_err(|_| AppError::BadRequest(format!("Invalid purpose: {}", purpose_slug)))?;
-        purpose_oid = purpose.oid;
-    }
+    Ok(Json(FileRecord { file, ..record }.into()))
+}
+
+pub async fn update_file(
+    State(state): State<AppState>,
+    Path(file_id): Path<String>,
+    Json(payload): Json<UpdateFileRequest>,
+) -> Result<Json<FileResponse>, AppError> {
+    let expires_at = payload
+        .expires_at
+        .map(|ts| {
+            ts.map(|ts| file_reaper::parse_expires_at(ts, Utc::now().naive_utc()))
+                .transpose()
+        })
+        .transpose()?;
+
+    let record = find_live_file(&state, &file_id).await?;
+
+    let purpose_oid = match &payload.purpose {
+        Some(purpose_slug) => {
+            let purpose = state
+                .repos
+                .purposes
+                .find_by_slug(purpose_slug)
+                .await?
+                .ok_or_else(|| {
+                    AppError::invalid_param("purpose", format!("Invalid purpose: {}", purpose_slug))
+                })?;
+            Some(purpose.oid)
+        }
+        None => None,
+    };
 
     let update = UpdateFile {
         filename: payload.filename,
-        purpose_oid: if payload.purpose.is_some() {
-            Some(purpose_oid)
-        } else {
-            None
-        },
+        purpose_oid,
+        expires_at,
         updated_at: Utc::now().naive_utc(),
     };
 
-    let updated_file: File = diesel::update(files::table.find(file.oid))
-        .set(&update)
-        .get_result(&mut conn)
-        .map_err(|_| AppError::DatabaseError)?;
-
-    let tenant: Tenant = tenants::table
-        .find(updated_file.tenant_oid)
-        .first(&mut conn)
-        .map_err(|_| AppError::DatabaseError)?;
-
-    let purpose: Purpose = purposes::table
-        .find(updated_file.purpose_oid)
-        .first(&mut conn)
-        .map_err(|_| AppError::DatabaseError)?;
-
-    Ok(Json(FileResponse {
-        id: updated_file.id,
-        object: "file".to_string(),
-        bytes: updated_file.bytes,
-        created_at: updated_file.created_at.and_utc().timestamp(),
-        updated_at: updated_file.updated_at.and_utc().timestamp(),
-        filename: updated_file.filename,
-        purpose: purpose.slug,
-        tenant_id: Some(tenant.id),
-    }))
+    let file = state
+        .repos
+        .files
+        .update(record.file.oid, update)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(Json(
+        FileRecord {
+            file,
+            purpose: payload.purpose.unwrap_or(record.purpose),
+            ..record
+        }
+        .into(),
+    ))
 }
 
 pub async fn get_file_private(
     State(state): State<AppState>,
     Path(file_id): Path<String>,
 ) -> Result<Json<FileResponse>, AppError> {
-    let mut conn = state.db_pool.get().map_err(|_| AppError::DatabaseError)?;
-
-    let file: File = files::table
-        .filter(files::id.eq(&file_id))
-        .first(&mut conn)
-        .map_err(|_| AppError::NotFound)?;
-
-    let tenant: Tenant = tenants::table
-        .find(file.tenant_oid)
-        .first(&mut conn)
-        .map_err(|_| AppError::DatabaseError)?;
-
-    let purpose: Purpose = purposes::table
-        .find(file.purpose_oid)
-        .first(&mut conn)
-        .map_err(|_| AppError::DatabaseError)?;
-
-    Ok(Json(FileResponse {
-        id: file.id,
-        object: "file".to_string(),
-        bytes: file.bytes,
-        created_at: file.created_at.and_utc().timestamp(),
-        updated_at: file.updated_at.and_utc().timestamp(),
-        filename: file.filename,
-        purpose: purpose.slug,
-        tenant_id: Some(tenant.id),
+    let record = find_live_file(&state, &file_id).await?;
+
+    Ok(Json(record.into()))
+}
+
+/// Loads a file by id, trashed or not.
+async fn find_file(state: &AppState, file_id: &str) -> Result<Option<FileRecord>, AppError> {
+    state.repos.files.find_by_id(file_id).await
+}
+
+/// Loads a file by id; trashed and expired files are not found.
+async fn find_live_file(state: &AppState, file_id: &str) -> Result<FileRecord, AppError> {
+    find_file(state, file_id)
+        .await?
+        .filter(|record| record.file.is_live(Utc::now().naive_utc()))
+        .ok_or(AppError::NotFound)
+}
+
+pub async fn verify_file(
+    State(state): State<AppState>,
+    Path(file_id): Path<String>,
+) -> Result<Json<FileVerificationResponse>, AppError> {
+    let file = find_live_file(&state, &file_id).await?.file;
+
+    let object = state
+        .storage_client
+        .download_stream(&file.storage_key, None)
+        .await
+        .map_err(AppError::storage)?;
+
+    let mut hasher = ContentHasher::new(&[]);
+    let mut actual_bytes: i64 = 0;
+    let mut stream = std::pin::pin!(object.into_stream());
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(AppError::storage)?;
+        actual_bytes += chunk.len() as i64;
+        hasher.update(&chunk);
+    }
+
+    let actual = hasher.finalize().sha256;
+
+    // Files uploaded before checksums existed get their hash recorded on first verification.
+    let backfilled = file.sha256.is_none() && actual_bytes == file.bytes;
+    if backfilled {
+        state
+            .repos
+            .files
+            .set_sha256(file.oid, actual.clone())
+            .await?;
+    }
+
+    let valid = match file.sha256.as_deref() {
+        _ if actual_bytes != file.bytes => Some(false),
+        Some(expected) => Some(expected == actual),
+        None => None,
+    };
+
+    if valid == Some(false) {
+        tracing::warn!(
+            "Integrity check failed for file {} (storage key {})",
+            file.id,
+            file.storage_key
+        );
+    }
+
+    Ok(Json(FileVerificationResponse {
+        object: "file_verification".to_string(),
+        file_id: file.id,
+        algorithm: checksum::SHA256.to_string(),
+        expected: file.sha256,
+        actual,
+        expected_bytes: file.bytes,
+        actual_bytes,
+        valid,
+        backfilled,
     }))
 }
 
@@ -157,93 +225,322 @@ pub async fn list_files(
     State(state): State<AppState>,
     Query(query): Query<ListFilesQuery>,
 ) -> Result<Json<ListFilesResponse>, AppError> {
-    let mut conn = state.db_pool.get().map_err(|_| AppError::DatabaseError)?;
+    let tenant_oid = tenant_param(&state, query.tenant_id.as_deref()).await?;
+    let page = file_listing::list(&state, &query, tenant_oid).await?;
+
+    Ok(Json(ListFilesResponse {
+        items: page.files.into_iter().map(FileResponse::from).collect(),
+        pagination: page.pagination,
+        total_count: page.total_count,
+    }))
+}
+
+/// Resolves the `tenant_id` parameter of a listing to the tenant's oid.
+async fn tenant_param(state: &AppState, tenant_id: Option<&str>) -> Result<Option<i64>, AppError> {
+    let Some(tenant_id) = tenant_id else {
+        return Ok(None);
+    };
+
+    let tenant = state
+        .repos
+        .tenants
+        .find_by_id(tenant_id)
+        .await?
+        .ok_or_else(|| AppError::invalid_param("tenant_id", "Invalid tenant_id"))?;
+    Ok(Some(tenant.oid))
+}
+
+pub async fn create_tenant(
+    State(state): State<AppState>,
+    Json(payload): Json<CreateTenantRequest>,
+) -> Result<Json<TenantResponse>, AppError> {
+    let name = validate_tenant_name(&payload.name)?;
+    validate_quota(payload.max_total_bytes)?;
+    validate_quota(payload.max_file_count)?;
+    validate_quota(payload.max_file_size_bytes)?;
+
+    let tenant_oid = state.snowflake_gen.generate().map_err(AppError::internal)?;
+    let tenant_id = match payload.id {
+        Some(id) => validate_tenant_id(&id)?,
+        None => crate::snowflake::generate_prefixed_id("tenant", tenant_oid),
+    };
+
+    let new_tenant = NewTenant {
+        oid: tenant_oid,
+        id: tenant_id,
+        name,
+        max_total_bytes: payload.max_total_bytes,
+        max_file_count: payload.max_file_count,
+        max_file_size_bytes: payload.max_file_size_bytes,
+    };
+
+    let tenant = state.repos.tenants.create(new_tenant).await?;
+
+    Ok(Json(tenant_response(tenant)))
+}
 
-    let limit = query.limit.unwrap_or(10).clamp(1, 100);
-    let order = query.order.unwrap_or_else(|| "desc".to_string());
+pub async fn get_tenant(
+    State(state): State<AppState>,
+    Path(tenant_id): Path<String>,
+) -> Result<Json<TenantResponse>, AppError> {
+    let tenant = state
+        .repos
+        .tenants
+        .find_by_id(&tenant_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(Json(tenant_response(tenant)))
+}
 
-    let mut base_query = files::table.into_boxed();
+pub async fn list_tenants(
+    State(state): State<AppState>,
+    Query(query): Query<ListTenantsQuery>,
+) -> Result<Json<ListTenantsResponse>, AppError> {
+    let page = PageParams::parse(
+        query.limit,
+        query.order.as_deref(),
+        query.after.as_deref(),
+        query.before.as_deref(),
+    )?;
+
+    let (tenants_list, pagination) = state.repos.tenants.list(page).await?;
+
+    Ok(Json(ListTenantsResponse {
+        items: tenants_list.into_iter().map(tenant_response).collect(),
+        pagination,
+    }))
+}
 
-    if let Some(tenant_id_str) = &query.tenant_id {
-        let tenant: Tenant = tenants::table
-            .filter(tenants::id.eq(tenant_id_str))
-            .first(&mut conn)
-            .map_err(|_| AppError::BadRequest("Invalid tenant_id".to_string()))?;
-        base_query = base_query.filter(files::tenant_oid.eq(tenant.oid));
+pub async fn update_tenant(
+    State(state): State<AppState>,
+    Path(tenant_id): Path<String>,
+    Json(payload): Json<UpdateTenantRequest>,
+) -> Result<Json<TenantResponse>, AppError> {
+    let name = payload
+        .name
+        .as_deref()
+        .map(validate_tenant_name)
+        .transpose()?;
+    for limit in [
+        payload.max_total_bytes,
+        payload.max_file_count,
+        payload.max_file_size_bytes,
+    ] {
+        validate_quota(limit.flatten())?;
     }
 
-    if let Some(after_id) = &query.after {
-        let after_file: File = files::table
-            .filter(files::id.eq(after_id))
-            .first(&mut conn)
-            .map_err(|_| AppError::BadRequest("Invalid after id".to_string()))?;
-        if order == "asc" {
-            base_query = base_query.filter(files::oid.gt(after_file.oid));
-        } else {
-            base_query = base_query.filter(files::oid.lt(after_file.oid));
-        }
+    let update = UpdateTenant {
+        name,
+        max_total_bytes: payload.max_total_bytes,
+        max_file_count: payload.max_file_count,
+        max_file_size_bytes: payload.max_file_size_bytes,
+        updated_at: Utc::now().naive_utc(),
+    };
+
+    let tenant = state
+        .repos
+        .tenants
+        .update(&tenant_id, update)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(Json(tenant_response(tenant)))
+}
+
+/// Deletes a tenant together with its files, links, API keys and stored objects.
+///
+/// The objects are scheduled for deletion in the same transaction that removes
+/// the rows, so storage failures are retried later instead of leaving orphans.
+pub async fn delete_tenant(
+    State(state): State<AppState>,
+    Path(tenant_id): Path<String>,
+) -> Result<Json<TenantResponse>, AppError> {
+    let (tenant, scheduled) = state
+        .repos
+        .tenants
+        .delete(&tenant_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    deletions::process(&state, scheduled).await;
+
+    Ok(Json(tenant_response(tenant)))
+}
+
+fn tenant_response(tenant: Tenant) -> TenantResponse {
+    TenantResponse {
+        id: tenant.id,
+        object: "tenant".to_string(),
+        name: tenant.name,
+        total_files_bytes: tenant.total_files_bytes,
+        file_count: tenant.file_count,
+        max_total_bytes: tenant.max_total_bytes,
+        max_file_count: tenant.max_file_count,
+        max_file_size_bytes: tenant.max_file_size_bytes,
+        created_at: tenant.created_at.and_utc().timestamp(),
+        updated_at: tenant.updated_at.and_utc().timestamp(),
     }
+}
 
-    if let Some(before_id) = &query.before {
-        let before_file: File = files::table
-            .filter(files::id.eq(before_id))
-            .first(&mut conn)
-            .map_err(|_| AppError::BadRequest("Invalid before id".to_string()))?;
-        if order == "asc" {
-            base_query = base_query.filter(files::oid.lt(before_file.oid));
-        } else {
-            base_query = base_query.filter(files::oid.gt(before_file.oid));
-        }
+fn validate_tenant_name(value: &str) -> Result<String, AppError> {
+    let value = value.trim();
+
+    if value.is_empty() || value.len() > 255 {
+        return Err(AppError::BadRequest(
+            "Tenant name must be between 1 and 255 characters".to_string(),
+        ));
+    }
+
+    Ok(value.to_string())
+}
+
+fn validate_tenant_id(value: &str) -> Result<String, AppError> {
+    let value = value.trim();
+
+    if !Tenant::is_valid_id(value) {
+        return Err(AppError::invalid_param("id", auth::INVALID_TENANT_ID));
     }
 
-    if order == "asc" {
-        base_query = base_query.order(files::oid.asc());
-    } else {
-        base_query = base_query.order(files::oid.desc());
+    Ok(value.to_string())
+}
+
+fn validate_quota(limit: Option<i64>) -> Result<(), AppError> {
+    match limit {
+        Some(limit) if limit < 0 => Err(AppError::BadRequest(
+            "Quota limits must not be negative".to_string(),
+        )),
+        _ => Ok(()),
     }
+}
 
-    let files_list: Vec<File> = base_query
-        .limit(limit + 1)
-        .load(&mut conn)
-        .map_err(|_| AppError::DatabaseError)?;
-
-    let has_more = files_list.len() as i64 > limit;
-    let files_to_return: Vec<File> = files_list.into_iter().take(limit as usize).collect();
-
-    let mut file_responses = Vec::new();
-
-    for file in &files_to_return {
-        let tenant: Tenant = tenants::table
-            .find(file.tenant_oid)
-            .first(&mut conn)
-            .map_err(|_| AppError::DatabaseError)?;
-
-        let purpose: Purpose = purposes::table
-            .find(file.purpose_oid)
-            .first(&mut conn)
-            .map_err(|_| AppError::DatabaseError)?;
-
-        file_responses.push(FileResponse {
-            id: file.id.clone(),
-            object: "file".to_string(),
-            bytes: file.bytes,
-            created_at: file.created_at.and_utc().timestamp(),
-            updated_at: file.updated_at.and_utc().timestamp(),
-            filename: file.filename.clone(),
-            purpose: purpose.slug,
-            tenant_id: Some(tenant.id),
-        });
+/// Compares storage with the database; `mode=fix` also repairs what it finds.
+pub async fn reconcile_storage(
+    State(state): State<AppState>,
+    Query(query): Query<ReconcileQuery>,
+) -> Result<Json<ReconciliationResponse>, AppError> {
+    let mode: ReconcileMode = query
+        .mode
+        .as_deref()
+        .unwrap_or("dry_run")
+        .parse()
+        .map_err(|e: String| AppError::invalid_param("mode", e))?;
+
+    let report = reconcile::run(&state, mode, query.tenant_id.as_deref()).await?;
+
+    Ok(Json(report))
+}
+
+pub async fn create_api_key(
+    State(state): State<AppState>,
+    Json(payload): Json<CreateApiKeyRequest>,
+) -> Result<Json<ApiKeyResponse>, AppError> {
+    let api_key_oid = state.snowflake_gen.generate().map_err(AppError::internal)?;
+    let api_key_id = crate::snowflake::generate_prefixed_id("apikey", api_key_oid);
+
+    let (key, lookup, key_hash) = auth::generate_api_key();
+
+    let tenant = state
+        .repos
+        .tenants
+        .find_by_id(&payload.tenant_id)
+        .await?
+        .ok_or_else(|| AppError::invalid_param("tenant_id", "Invalid tenant_id"))?;
+
+    let api_key = state
+        .repos
+        .api_keys
+        .create(NewApiKey {
+            oid: api_key_oid,
+            id: api_key_id,
+            tenant_oid: tenant.oid,
+            name: payload.name,
+            lookup,
+            key_hash,
+        })
+        .await?;
+
+    Ok(Json(api_key_response(api_key, tenant.id, Some(key))))
+}
+
+pub async fn revoke_api_key(
+    State(state): State<AppState>,
+    Path(api_key_id): Path<String>,
+) -> Result<Json<ApiKeyResponse>, AppError> {
+    let api_key = state
+        .repos
+        .api_keys
+        .revoke(&api_key_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    let tenant = state
+        .repos
+        .tenants
+        .find(api_key.tenant_oid)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(Json(api_key_response(api_key, tenant.id, None)))
+}
+
+fn api_key_response(api_key: ApiKey, tenant_id: String, key: Option<String>) -> ApiKeyResponse {
+    ApiKeyResponse {
+        id: api_key.id,
+        object: "api_key".to_string(),
+        tenant_id,
+        name: api_key.name,
+        key,
+        created_at: api_key.created_at.and_utc().timestamp(),
+        last_used_at: api_key.last_used_at.map(|t| t.and_utc().timestamp()),
+        revoked_at: api_key.revoked_at.map(|t| t.and_utc().timestamp()),
+    }
+}
+
+/// Mints a signed URL for a file. Nothing is stored; the URL is checked
+/// against the configured keys when it is used.
+pub async fn create_signed_url(
+    State(state): State<AppState>,
+    Path(file_id): Path<String>,
+    Json(payload): Json<CreateSignedUrlRequest>,
+) -> Result<Json<SignedUrlResponse>, AppError> {
+    if payload.expires_in <= 0 || payload.expires_in > signed_urls::MAX_TTL_SECS {
+        return Err(AppError::invalid_param(
+            "expires_in",
+            format!(
+                "expires_in must be between 1 and {} seconds",
+                signed_urls::MAX_TTL_SECS
+            ),
+        ));
     }
 
-    let has_more_after = if order == "desc" { has_more } else { false };
-    let has_more_before = if order == "asc" { has_more } else { false };
+    let disposition = payload
+        .disposition
+        .map(|d| d.parse::<Disposition>())
+        .transpose()
+        .map_err(|e| AppError::invalid_param("disposition", e))?;
 
-    Ok(Json(ListFilesResponse {
-        items: file_responses,
-        pagination: PaginationResponse {
-            has_more_before,
-            has_more_after,
-        },
+    if let Some(content_type) = &payload.content_type {
+        content_type
+            .parse::<mime::Mime>()
+            .map_err(|_| AppError::invalid_param("content_type", "Invalid content_type"))?;
+    }
+
+    let file = find_live_file(&state, &file_id).await?.file;
+
+    let claims = SignedUrlClaims {
+        file_id: file.id,
+        expires: Utc::now().timestamp() + payload.expires_in,
+        disposition,
+        content_type: payload.content_type,
+    };
+    let path = claims.sign(&state.config.url_signing_keys)?;
+
+    Ok(Json(SignedUrlResponse {
+        object: "signed_url".to_string(),
+        file_id: claims.file_id,
+        url: format!("{}{}", state.config.public_base_url, path),
+        expires_at: claims.expires,
     }))
 }
 
@@ -251,31 +548,81 @@ pub async fn create_link(
     State(state): State<AppState>,
     Json(payload): Json<CreateLinkRequest>,
 ) -> Result<Json<FileLinkResponse>, AppError> {
-    let mut conn = state.db_pool.get().map_err(|_| AppError::DatabaseError)?;
+    let file = find_file(&state, &payload.file_id)
+        .await?
+        .filter(|record| record.file.is_live(Utc::now().naive_utc()))
+        .ok_or_else(|| AppError::invalid_param("file_id", "Invalid file_id"))?
+        .file;
 
-    let file: File = files::table
-        .filter(files::id.eq(&payload.file_id))
-        .first(&mut conn)
-        .map_err(|_| AppError::BadRequest("Invalid file_id".to_string()))?;
-
-    let link_oid = state
-        .snowflake_gen
-        .generate()
-        .map_err(|_| AppError::InternalError)?;
+    let link_oid = state.snowflake_gen.generate().map_err(AppError::internal)?;
     let link_id = crate::snowflake::generate_prefixed_id("link", link_oid);
 
-    let key = if let Some(custom_key) = payload.key {
-        custom_key
-    } else {
-        use rand::Rng;
-        rand::thread_rng()
-            .sample_iter(&rand::distributions::Alphanumeric)
-            .take(16)
-            .map(char::from)
-            .collect()
+    let key = match payload.key {
+        Some(custom_key) => {
+            links::validate_key(&custom_key)?;
+            custom_key
+        }
+        None => links::generate_key(state.config.link_key_length),
     };
 
-    let expires_at = Utc::now().naive_utc() + Duration::seconds(payload.expires_in);
+    let (min_ttl, max_ttl) = (
+        state.config.link_min_ttl_secs,
+        state.config.link_max_ttl_secs,
+    );
+    if payload.expires_in < min_ttl || payload.expires_in > max_ttl {
+        return Err(AppError::invalid_param(
+            "expires_in",
+            format!(
+                "expires_in must be between {} and {} seconds",
+                min_ttl, max_ttl
+            ),
+        ));
+    }
+
+    let expires_at = Duration::try_seconds(payload.expires_in)
+        .and_then(|ttl| Utc::now().naive_utc().checked_add_signed(ttl))
+        .ok_or_else(|| AppError::invalid_param("expires_in", "Invalid expires_in"))?;
+
+    let starts_at = payload
+        .starts_at
+        .map(|ts| {
+            chrono::DateTime::from_timestamp(ts, 0)
+                .map(|dt| dt.naive_utc())
+                .ok_or_else(|| AppError::invalid_param("starts_at", "Invalid starts_at"))
+        })
+        .transpose()?;
+    if starts_at.is_some_and(|starts_at| starts_at >= expires_at) {
+        return Err(AppError::invalid_param(
+            "starts_at",
+            "starts_at must be before the link expires",
+        ));
+    }
+
+    if payload.max_downloads.is_some_and(|max| max < 1) {
+        return Err(AppError::invalid_param(
+            "max_downloads",
+            "max_downloads must be at least 1",
+        ));
+    }
+
+    let password_hash = match payload.password {
+        Some(password) => {
+            if password.is_empty() || password.len() > MAX_LINK_PASSWORD_LEN {
+                return Err(AppError::invalid_param(
+                    "password",
+                    format!(
+                        "password must be between 1 and {} bytes",
+                        MAX_LINK_PASSWORD_LEN
+                    ),
+                ));
+            }
+            let hash = tokio::task::spawn_blocking(move || links::hash_password(&password))
+                .await
+                .map_err(AppError::internal)??;
+            Some(hash)
+        }
+        None => None,
+    };
 
     let new_link = NewFileLink {
         oid: link_oid,
@@ -283,75 +630,161 @@ pub async fn create_link(
         file_oid: file.oid,
         key: key.clone(),
         expires_at,
+        starts_at,
+        max_downloads: payload.max_downloads,
+        password_hash,
     };
 
-    let link: FileLink = diesel::insert_into(file_links::table)
-        .values(&new_link)
-        .get_result(&mut conn)
-        .map_err(|_| AppError::DatabaseError)?;
+    let link = state.repos.links.create(new_link).await?;
 
-    Ok(Json(FileLinkResponse {
-        id: link.id,
-        object: "file_link".to_string(),
-        file_id: file.id,
-        key: link.key,
-        expires_at: link.expires_at.and_utc().timestamp(),
-        created_at: link.created_at.and_utc().timestamp(),
-    }))
+    Ok(Json(link_response(link, file.id)))
 }
 
 pub async fn get_link(
     State(state): State<AppState>,
     Path(link_id): Path<String>,
 ) -> Result<Json<FileLinkResponse>, AppError> {
-    let mut conn = state.db_pool.get().map_err(|_| AppError::DatabaseError)?;
+    let (link, file_id) = find_link(&state, &link_id).await?;
 
-    let link: FileLink = file_links::table
-        .filter(file_links::id.eq(&link_id))
-        .first(&mut conn)
-        .map_err(|_| AppError::NotFound)?;
+    Ok(Json(link_response(link, file_id)))
+}
 
-    let file: File = files::table
-        .find(link.file_oid)
-        .first(&mut conn)
-        .map_err(|_| AppError::DatabaseError)?;
+pub async fn delete_link(
+    State(state): State<AppState>,
+    Path(link_id): Path<String>,
+) -> Result<Json<FileLinkResponse>, AppError> {
+    let (link, file_id) = find_link(&state, &link_id).await?;
 
-    Ok(Json(FileLinkResponse {
-        id: link.id,
-        object: "file_link".to_string(),
+    state.repos.links.delete(link.oid).await?;
+
+    Ok(Json(link_response(link, file_id)))
+}
+
+/// Loads a link by id, together with the id of its file.
+async fn find_link(state: &AppState, link_id: &str) -> Result<(FileLink, String), AppError> {
+    state
+        .repos
+        .links
+        .find_by_id(link_id)
+        .await?
+        .ok_or(AppError::NotFound)
+}
+
+pub async fn list_links(
+    State(state): State<AppState>,
+    Query(query): Query<ListLinksQuery>,
+) -> Result<Json<ListLinksResponse>, AppError> {
+    let file_oid = match &query.file_id {
+        Some(file_id) => Some(
+            find_file(&state, file_id)
+                .await?
+                .ok_or_else(|| AppError::invalid_param("file_id", "Invalid file_id"))?
+                .file
+                .oid,
+        ),
+        None => None,
+    };
+
+    load_links(&state, &query, file_oid).await.map(Json)
+}
+
+pub async fn list_file_links(
+    State(state): State<AppState>,
+    Path(file_id): Path<String>,
+    Query(query): Query<ListLinksQuery>,
+) -> Result<Json<ListLinksResponse>, AppError> {
+    let file = find_file(&state, &file_id)
+        .await?
+        .ok_or(AppError::NotFound)?
+        .file;
+
+    load_links(&state, &query, Some(file.oid)).await.map(Json)
+}
+
+/// Lists links newest first by default, paginated like `list_files`.
+async fn load_links(
+    state: &AppState,
+    query: &ListLinksQuery,
+    file_oid: Option<i64>,
+) -> Result<ListLinksResponse, AppError> {
+    let page = PageParams::parse(
+        query.limit,
+        query.order.as_deref(),
+        query.after.as_deref(),
+        query.before.as_deref(),
+    )?;
+
+    let filter = LinkFilter {
+        file_oid,
+        tenant_oid: tenant_param(state, query.tenant_id.as_deref()).await?,
+        active: query.active,
+        now: Utc::now().naive_utc(),
+    };
+
+    let (links, pagination) = state.repos.links.list(filter, page).await?;
+
+    Ok(ListLinksResponse {
+        items: links
+            .into_iter()
+            .map(|(link, file_id)| link_response(link, file_id))
+            .collect(),
+        pagination,
+    })
+}
+
+/// Revokes every link of a file that is not revoked yet.
+pub async fn revoke_file_links(
+    State(state): State<AppState>,
+    Path(file_id): Path<String>,
+) -> Result<Json<RevokeLinksResponse>, AppError> {
+    let file = find_file(&state, &file_id)
+        .await?
+        .ok_or(AppError::NotFound)?
+        .file;
+
+    let revoked = state.repos.links.revoke_all(file.oid).await?;
+
+    Ok(Json(RevokeLinksResponse {
+        object: "link_revocation".to_string(),
         file_id: file.id,
-        key: link.key,
-        expires_at: link.expires_at.and_utc().timestamp(),
-        created_at: link.created_at.and_utc().timestamp(),
+        revoked: revoked as i64,
     }))
 }
 
-pub async fn delete_link(
+/// Stops a link from serving the file while keeping it around, so visitors
+/// get a 410 rather than a 404. Revoking twice keeps the first timestamp.
+pub async fn revoke_link(
     State(state): State<AppState>,
     Path(link_id): Path<String>,
 ) -> Result<Json<FileLinkResponse>, AppError> {
-    let mut conn = state.db_pool.get().map_err(|_| AppError::DatabaseError)?;
+    let (link, file_id) = find_link(&state, &link_id).await?;
 
-    let link: FileLink = file_links::table
-        .filter(file_links::id.eq(&link_id))
-        .first(&mut conn)
-        .map_err(|_| AppError::NotFound)?;
+    let link = state
+        .repos
+        .links
+        .revoke(link.oid)
+        .await?
+        .ok_or(AppError::NotFound)?;
 
-    let file: File = files::table
-        .find(link.file_oid)
-        .first(&mut conn)
-        .map_err(|_| AppError::DatabaseError)?;
-
-    diesel::delete(file_links::table.find(link.oid))
-        .execute(&mut conn)
-        .map_err(|_| AppError::DatabaseError)?;
+    Ok(Json(link_response(link, file_id)))
+}
 
-    Ok(Json(FileLinkResponse {
+fn link_response(link: FileLink, file_id: String) -> FileLinkResponse {
+    FileLinkResponse {
+        state: LinkState::of(&link, Utc::now().naive_utc())
+            .as_str()
+            .to_string(),
         id: link.id,
         object: "file_link".to_string(),
-        file_id: file.id,
+        file_id,
         key: link.key,
         expires_at: link.expires_at.and_utc().timestamp(),
         created_at: link.created_at.and_utc().timestamp(),
-    }))
+        starts_at: link.starts_at.map(|t| t.and_utc().timestamp()),
+        revoked_at: link.revoked_at.map(|t| t.and_utc().timestamp()),
+        max_downloads: link.max_downloads,
+        download_count: link.download_count,
+        last_accessed_at: link.last_accessed_at.map(|t| t.and_utc().timestamp()),
+        password_protected: link.password_hash.is_some(),
+    }
 }
@@ -1,257 +1,483 @@
 use crate::app_state::AppState;
+use crate::auth::AuthenticatedTenant;
+use crate::checksum::{Checksums, ContentHasher};
+use crate::deletions;
+use crate::error::AppError;
+use crate::extract::{Json, Path, Query};
+use crate::file_listing;
+use crate::file_reaper;
 use crate::models::*;
-use crate::schema::*;
+use crate::quota::{QuotaKind, TenantQuota};
+use crate::repository::{NewUpload, UploadBlob};
 use axum::{
-    extract::{Multipart, Path, State},
+    extract::{
+        multipart::{Field, MultipartError},
+        Multipart, State,
+    },
     http::{HeaderMap, StatusCode},
-    response::{IntoResponse, Response},
-    Json,
+    response::Response,
 };
 use bytes::Bytes;
 use chrono::Utc;
-use diesel::prelude::*;
+use futures::{channel::mpsc, SinkExt};
+
+const UPLOAD_CHANNEL_CAPACITY: usize = 8;
+
+/// The body limit is off for uploads, so the form fields other than the file
+/// carry their own.
+const MAX_TEXT_FIELD_BYTES: usize = 1024;
 
 pub async fn upload_file(
     State(state): State<AppState>,
-    headers: HeaderMap,
+    AuthenticatedTenant(tenant): AuthenticatedTenant,
     mut multipart: Multipart,
 ) -> Result<Json<FileResponse>, AppError> {
-    let tenant_id = headers
-        .get("X-Tenant-ID")
-        .and_then(|v| v.to_str().ok())
-        .ok_or_else(|| AppError::BadRequest("Missing X-Tenant-ID header".to_string()))?;
-
-    let mut file_data: Option<Vec<u8>> = None;
-    let mut filename: Option<String> = None;
-    let mut purpose_slug: Option<String> = None;
-
-    while let Some(field) = multipart
-        .next_field()
-        .await
-        .map_err(|e| AppError::BadRequest(format!("Failed to read multipart field: {}", e)))?
-    {
-        let field_name = field.name().unwrap_or("").to_string();
-
-        match field_name.as_str() {
-            "file" => {
-                let field_filename = field.file_name().map(|s| s.to_string());
-                filename = field_filename;
+    let file_oid = state.snowflake_gen.generate().map_err(AppError::internal)?;
+    let file_id = crate::snowflake::generate_prefixed_id("file", file_oid);
+    let storage_key = format!("{}/{}", tenant.id, file_id);
 
-                let data = field.bytes().await.map_err(|e| {
-                    AppError::BadRequest(format!("Failed to read file data: {}", e))
-                })?;
+    let quota = TenantQuota::for_tenant(&tenant, &state.config);
+    quota
+        .check_file_count(&tenant)
+        .map_err(AppError::QuotaExceeded)?;
 
-                if data.len() as i64 > state.config.max_file_size_bytes {
-                    return Err(AppError::BadRequest(format!(
-                        "File size exceeds maximum of {} bytes",
-                        state.config.max_file_size_bytes
-                    )));
-                }
+    let mut upload = UploadFields::default();
 
-                file_data = Some(data.to_vec());
-            }
-            "purpose" => {
-                let text = field.text().await.map_err(|e| {
-                    AppError::BadRequest(format!("Failed to read purpose field: {}", e))
-                })?;
-                purpose_slug = Some(text);
-            }
-            _ => {}
+    if let Err(err) = read_upload_fields(
+        &state,
+        &mut multipart,
+        &tenant,
+        &quota,
+        &storage_key,
+        &mut upload,
+    )
+    .await
+    {
+        if upload.uploaded() {
+            deletions::discard(&state, &storage_key).await;
         }
+        return Err(err);
     }
 
-    let file_data = file_data.ok_or_else(|| AppError::BadRequest("Missing file".to_string()))?;
-    let filename = filename.ok_or_else(|| AppError::BadRequest("Missing filename".to_string()))?;
-    let purpose_slug =
-        purpose_slug.ok_or_else(|| AppError::BadRequest("Missing purpose".to_string()))?;
-
-    let mut conn = state.db_pool.get().map_err(|_| AppError::DatabaseError)?;
+    let uploaded = upload.uploaded();
 
-    let tenant = get_or_create_tenant(&mut conn, tenant_id, &state)?;
+    let object = upload.object.take().ok_or(AppError::MissingParam("file"))?;
 
-    let purpose: Purpose = purposes::table
-        .filter(purposes::slug.eq(&purpose_slug))
-        .first(&mut conn)
-        .map_err(|_| AppError::BadRequest(format!("Invalid purpose: {}", purpose_slug)))?;
-
-    let file_oid = state
-        .snowflake_gen
-        .generate()
-        .map_err(|_| AppError::InternalError)?;
-    let file_id = crate::snowflake::generate_prefixed_id("file", file_oid);
-    let storage_key = format!("{}/{}", tenant.id, file_id);
+    let pending = PendingUpload {
+        file_oid,
+        file_id,
+        storage_key: storage_key.clone(),
+        object,
+    };
 
-    state
-        .storage_client
-        .upload(&storage_key, Bytes::from(file_data.clone()), None)
-        .await
-        .map_err(|e| AppError::StorageError(e.to_string()))?;
-
-    let new_file = NewFile {
-        oid: file_oid,
-        id: file_id.clone(),
-        tenant_oid: tenant.oid,
-        filename: filename.clone(),
-        purpose_oid: purpose.oid,
-        bytes: file_data.len() as i64,
-        storage_key,
+    let (file, purpose) = match record_upload(&state, &tenant, quota, upload, pending).await {
+        Ok(recorded) => recorded,
+        Err(err) => {
+            if uploaded {
+                deletions::discard(&state, &storage_key).await;
+            }
+            return Err(err);
+        }
     };
 
-    let file: File = diesel::insert_into(files::table)
-        .values(&new_file)
-        .get_result(&mut conn)
-        .map_err(|_| AppError::DatabaseError)?;
-
-    diesel::update(tenants::table.find(tenant.oid))
-        .set((
-            tenants::total_files_bytes.eq(tenants::total_files_bytes + file.bytes),
-            tenants::file_count.eq(tenants::file_count + 1),
-            tenants::updated_at.eq(Utc::now().naive_utc()),
-        ))
-        .execute(&mut conn)
-        .map_err(|_| AppError::DatabaseError)?;
-
-    Ok(Json(FileResponse {
-        id: file.id,
-        object: "file".to_string(),
-        bytes: file.bytes,
-        created_at: file.created_at.and_utc().timestamp(),
-        updated_at: file.updated_at.and_utc().timestamp(),
-        filename: file.filename,
+    // Identical content was already stored, so this upload is redundant.
+    if uploaded && file.storage_key != storage_key {
+        deletions::discard(&state, &storage_key).await;
+    }
+
+    Ok(Json(FileResponse::for_tenant(FileRecord {
+        file,
+        tenant_id: tenant.id,
         purpose: purpose.slug,
-        tenant_id: None,
-    }))
+    })))
 }
 
 pub async fn get_file(
     State(state): State<AppState>,
-    headers: HeaderMap,
+    AuthenticatedTenant(tenant): AuthenticatedTenant,
     Path(file_id): Path<String>,
 ) -> Result<Json<FileResponse>, AppError> {
-    let tenant_id = headers
-        .get("X-Tenant-ID")
-        .and_then(|v| v.to_str().ok())
-        .ok_or_else(|| AppError::BadRequest("Missing X-Tenant-ID header".to_string()))?;
-
-    let mut conn = state.db_pool.get().map_err(|_| AppError::DatabaseError)?;
-
-    let tenant = get_or_create_tenant(&mut conn, tenant_id, &state)?;
-
-    let file: File = files::table
-        .filter(files::id.eq(&file_id))
-        .filter(files::tenant_oid.eq(tenant.oid))
-        .first(&mut conn)
-        .map_err(|_| AppError::NotFound)?;
-
-    let purpose: Purpose = purposes::table
-        .find(file.purpose_oid)
-        .first(&mut conn)
-        .map_err(|_| AppError::DatabaseError)?;
-
-    Ok(Json(FileResponse {
-        id: file.id,
-        object: "file".to_string(),
-        bytes: file.bytes,
-        created_at: file.created_at.and_utc().timestamp(),
-        updated_at: file.updated_at.and_utc().timestamp(),
-        filename: file.filename,
-        purpose: purpose.slug,
-        tenant_id: None,
+    let record = find_tenant_file(&state, &tenant, &file_id).await?;
+
+    Ok(Json(FileResponse::for_tenant(record)))
+}
+
+/// Lists the authenticated tenant's files. Takes the filters of the admin
+/// listing, except that the tenant is implied and the trash is never shown.
+pub async fn list_files(
+    State(state): State<AppState>,
+    AuthenticatedTenant(tenant): AuthenticatedTenant,
+    Query(query): Query<ListFilesQuery>,
+) -> Result<Json<ListFilesResponse>, AppError> {
+    if query.tenant_id.is_some() {
+        return Err(AppError::invalid_param(
+            "tenant_id",
+            "tenant_id is implied by the API key",
+        ));
+    }
+    if query.deleted.is_some() {
+        return Err(AppError::invalid_param(
+            "deleted",
+            "Deleted files cannot be listed",
+        ));
+    }
+
+    let page = file_listing::list(&state, &query, Some(tenant.oid)).await?;
+
+    Ok(Json(ListFilesResponse {
+        items: page
+            .files
+            .into_iter()
+            .map(FileResponse::for_tenant)
+            .collect(),
+        pagination: page.pagination,
+        total_count: page.total_count,
     }))
 }
 
 pub async fn get_file_content(
     State(state): State<AppState>,
+    AuthenticatedTenant(tenant): AuthenticatedTenant,
     headers: HeaderMap,
     Path(file_id): Path<String>,
 ) -> Result<Response, AppError> {
-    let tenant_id = headers
-        .get("X-Tenant-ID")
-        .and_then(|v| v.to_str().ok())
-        .ok_or_else(|| AppError::BadRequest("Missing X-Tenant-ID header".to_string()))?;
+    let record = find_tenant_file(&state, &tenant, &file_id).await?;
 
-    let mut conn = state.db_pool.get().map_err(|_| AppError::DatabaseError)?;
+    crate::content::serve_file_content(&state, &record.file, &headers).await
+}
+
+/// Loads a live file of `tenant`. Other tenants' files are not found.
+async fn find_tenant_file(
+    state: &AppState,
+    tenant: &Tenant,
+    file_id: &str,
+) -> Result<FileRecord, AppError> {
+    state
+        .repos
+        .files
+        .find_by_id(file_id)
+        .await?
+        .filter(|record| {
+            record.file.is_live(Utc::now().naive_utc()) && record.file.tenant_oid == tenant.oid
+        })
+        .ok_or(AppError::NotFound)
+}
 
-    let tenant = get_or_create_tenant(&mut conn, tenant_id, &state)?;
+#[derive(Default)]
+struct UploadFields {
+    object: Option<StoredObject>,
+    filename: Option<String>,
+    purpose_slug: Option<String>,
+    claimed_sha256: Option<String>,
+    expires_at: Option<i64>,
+    /// Existing blob the content was matched against instead of being uploaded.
+    reused_blob: Option<Blob>,
+}
 
-    let file: File = files::table
-        .filter(files::id.eq(&file_id))
-        .filter(files::tenant_oid.eq(tenant.oid))
-        .first(&mut conn)
-        .map_err(|_| AppError::NotFound)?;
+impl UploadFields {
+    fn uploaded(&self) -> bool {
+        self.object.is_some() && self.reused_blob.is_none()
+    }
+}
 
-    let content = state
-        .storage_client
-        .download(&file.storage_key)
+/// Reads the multipart body, streaming the `file` field straight to storage.
+///
+/// With deduplication enabled, a `sha256` field sent before the file lets the
+/// upload skip storage entirely when the tenant already holds that content.
+async fn read_upload_fields(
+    state: &AppState,
+    multipart: &mut Multipart,
+    tenant: &Tenant,
+    quota: &TenantQuota,
+    storage_key: &str,
+    upload: &mut UploadFields,
+) -> Result<(), AppError> {
+    while let Some(field) = multipart
+        .next_field()
         .await
-        .map_err(|e| AppError::StorageError(e.to_string()))?;
+        .map_err(|e| multipart_error("Failed to read multipart field", e))?
+    {
+        let field_name = field.name().unwrap_or("").to_string();
 
-    Ok((
-        StatusCode::OK,
-        [("Content-Type", "application/octet-stream")],
-        content,
-    )
-        .into_response())
+        match field_name.as_str() {
+            "file" => {
+                if upload.object.is_some() {
+                    return Err(AppError::BadRequest(
+                        "Only one file may be uploaded per request".to_string(),
+                    ));
+                }
+
+                upload.filename = field.file_name().map(|s| s.to_string());
+
+                upload.reused_blob = match (&upload.claimed_sha256, state.config.dedup_enabled) {
+                    (Some(sha256), true) => state.repos.files.find_blob(tenant.oid, sha256).await?,
+                    _ => None,
+                };
+
+                let destination = match upload.reused_blob {
+                    Some(_) => None,
+                    None => Some(storage_key),
+                };
+                let limit = quota.upload_limit(tenant);
+                let object = stream_field_to_storage(state, field, destination, limit)
+                    .await
+                    .map_err(|err| match err {
+                        UploadError::TooLarge(kind, bytes) => {
+                            AppError::QuotaExceeded(quota.exceeded(tenant, kind, bytes))
+                        }
+                        UploadError::Failed(err) => err,
+                    })?;
+                upload.object = Some(object);
+            }
+            "sha256" => {
+                let text = read_text_field(field, "sha256").await?;
+                upload.claimed_sha256 = Some(text.trim().to_ascii_lowercase());
+            }
+            "expires_at" => {
+                let text = read_text_field(field, "expires_at").await?;
+                let timestamp = text.trim().parse().map_err(|_| {
+                    AppError::invalid_param("expires_at", "expires_at must be a Unix timestamp")
+                })?;
+                upload.expires_at = Some(timestamp);
+            }
+            "purpose" => {
+                let text = read_text_field(field, "purpose").await?;
+                upload.purpose_slug = Some(text);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+struct PendingUpload {
+    file_oid: i64,
+    file_id: String,
+    storage_key: String,
+    object: StoredObject,
+}
+
+/// Records a stored upload in the database.
+///
+/// Charging the quota, taking the blob reference and inserting the file row
+/// commit together, so a failure at any step leaves no trace in the database
+/// and the caller only has to discard the uploaded object.
+async fn record_upload(
+    state: &AppState,
+    tenant: &Tenant,
+    quota: TenantQuota,
+    upload: UploadFields,
+    pending: PendingUpload,
+) -> Result<(File, Purpose), AppError> {
+    let (filename, purpose) =
+        validate_upload_fields(state, upload.filename, upload.purpose_slug).await?;
+    let object = pending.object;
+
+    let now = Utc::now().naive_utc();
+    let expires_at = match upload.expires_at {
+        Some(timestamp) => Some(file_reaper::parse_expires_at(timestamp, now)?),
+        None => file_reaper::default_expires_at(&state.config, &purpose.slug, now),
+    };
+
+    let blob = if state.config.dedup_enabled {
+        Some(match upload.reused_blob {
+            Some(blob) => {
+                if blob.sha256 != object.checksums.sha256 || blob.bytes != object.bytes {
+                    return Err(AppError::BadRequest(
+                        "Uploaded content does not match the provided sha256".to_string(),
+                    ));
+                }
+                UploadBlob::Reuse(blob)
+            }
+            None => UploadBlob::Share(state.snowflake_gen.generate().map_err(AppError::internal)?),
+        })
+    } else {
+        None
+    };
+
+    let file = state
+        .repos
+        .files
+        .record_upload(NewUpload {
+            file: NewFile {
+                oid: pending.file_oid,
+                id: pending.file_id,
+                tenant_oid: tenant.oid,
+                filename,
+                purpose_oid: purpose.oid,
+                bytes: object.bytes,
+                storage_key: pending.storage_key,
+                content_type: object.content_type,
+                sha256: Some(object.checksums.sha256),
+                md5: object.checksums.md5,
+                crc32c: object.checksums.crc32c,
+                blob_oid: None,
+                expires_at,
+            },
+            quota,
+            blob,
+        })
+        .await?;
+
+    Ok((file, purpose))
 }
 
-fn get_or_create_tenant(
-    conn: &mut PgConnection,
-    tenant_id_str: &str,
+async fn validate_upload_fields(
     state: &AppState,
-) -> Result<Tenant, AppError> {
-    let existing: Option<Tenant> = tenants::table
-        .filter(tenants::id.eq(tenant_id_str))
-        .first(conn)
-        .optional()
-        .map_err(|_| AppError::DatabaseError)?;
-
-    if let Some(tenant) = existing {
-        return Ok(tenant);
+    filename: Option<String>,
+    purpose_slug: Option<String>,
+) -> Result<(String, Purpose), AppError> {
+    let filename = filename.ok_or(AppError::MissingParam("filename"))?;
+    let purpose_slug = purpose_slug.ok_or(AppError::MissingParam("purpose"))?;
+
+    let purpose = state
+        .repos
+        .purposes
+        .find_by_slug(&purpose_slug)
+        .await?
+        .ok_or_else(|| {
+            AppError::invalid_param("purpose", format!("Invalid purpose: {}", purpose_slug))
+        })?;
+
+    Ok((filename, purpose))
+}
+
+struct StoredObject {
+    bytes: i64,
+    content_type: String,
+    checksums: Checksums,
+}
+
+enum UploadError {
+    /// The field grew past the byte limit; carries the bytes read so far.
+    TooLarge(QuotaKind, i64),
+    Failed(AppError),
+}
+
+impl From<AppError> for UploadError {
+    fn from(err: AppError) -> Self {
+        UploadError::Failed(err)
     }
+}
+
+/// Pipes a multipart field into a streaming storage upload, chunk by chunk.
+///
+/// Bytes are counted as they arrive and the upload is aborted as soon as
+/// `limit` is exceeded. The content type comes from the part header, falling
+/// back to sniffing the first chunk, and checksums are computed over the
+/// bytes as they stream through.
+async fn stream_field_to_storage(
+    state: &AppState,
+    mut field: Field<'_>,
+    storage_key: Option<&str>,
+    limit: (i64, QuotaKind),
+) -> Result<StoredObject, UploadError> {
+    let (max_bytes, limit_kind) = limit;
+
+    let declared_type = field.content_type().map(|s| s.to_string());
+    let mut pending = field.chunk().await.map_err(read_file_error)?;
+    let content_type = crate::content::resolve_content_type(
+        declared_type.as_deref(),
+        pending.as_deref().unwrap_or_default(),
+    );
+
+    let (mut tx, rx) = mpsc::channel::<Result<Bytes, std::io::Error>>(UPLOAD_CHANNEL_CAPACITY);
+
+    // Without a destination the content is only measured and hashed.
+    let upload = storage_key.map(|key| {
+        let storage_client = state.storage_client.clone();
+        let key = key.to_string();
+        let upload_type = content_type.clone();
+        tokio::spawn(async move {
+            storage_client
+                .upload_stream(&key, rx, Some(&upload_type))
+                .await
+        })
+    });
+
+    let mut total_bytes: i64 = 0;
+    let mut hasher = ContentHasher::new(&state.config.extra_checksums);
+
+    let read_result = loop {
+        let chunk = match pending.take() {
+            Some(chunk) => chunk,
+            None => match field.chunk().await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(read_file_error(e).into()),
+            },
+        };
+
+        total_bytes += chunk.len() as i64;
+
+        if total_bytes > max_bytes {
+            break Err(UploadError::TooLarge(limit_kind, total_bytes));
+        }
 
-    let tenant_oid = state
-        .snowflake_gen
-        .generate()
-        .map_err(|_| AppError::InternalError)?;
-    let tenant_id = crate::snowflake::generate_prefixed_id("tenant", tenant_oid);
+        hasher.update(&chunk);
 
-    let new_tenant = NewTenant {
-        oid: tenant_oid,
-        id: tenant_id,
-        name: tenant_id_str.to_string(),
+        if upload.is_some() && tx.send(Ok(chunk)).await.is_err() {
+            // The upload ended early; its result explains why.
+            break Ok(());
+        }
     };
 
-    diesel::insert_into(tenants::table)
-        .values(&new_tenant)
-        .get_result(conn)
-        .map_err(|_| AppError::DatabaseError)
+    if let Err(err) = read_result {
+        if let (Some(upload), Some(key)) = (upload, storage_key) {
+            let _ = tx.send(Err(std::io::Error::other("upload aborted"))).await;
+            drop(tx);
+            let _ = upload.await;
+            deletions::discard(state, key).await;
+        }
+        return Err(err);
+    }
+
+    drop(tx);
+
+    if let Some(upload) = upload {
+        upload
+            .await
+            .map_err(AppError::internal)?
+            .map_err(AppError::storage)?;
+    }
+
+    Ok(StoredObject {
+        bytes: total_bytes,
+        content_type,
+        checksums: hasher.finalize(),
+    })
 }
 
-#[derive(Debug)]
-pub enum AppError {
-    BadRequest(String),
-    NotFound,
-    DatabaseError,
-    StorageError(String),
-    InternalError,
+async fn read_text_field(mut field: Field<'_>, name: &'static str) -> Result<String, AppError> {
+    let mut buf = Vec::new();
+    while let Some(chunk) = field
+        .chunk()
+        .await
+        .map_err(|e| multipart_error(&format!("Failed to read {} field", name), e))?
+    {
+        if buf.len() + chunk.len() > MAX_TEXT_FIELD_BYTES {
+            return Err(AppError::invalid_param(
+                name,
+                format!("{} must be at most {} bytes", name, MAX_TEXT_FIELD_BYTES),
+            ));
+        }
+        buf.extend_from_slice(&chunk);
+    }
+
+    String::from_utf8(buf)
+        .map_err(|_| AppError::invalid_param(name, format!("{} must be valid UTF-8", name)))
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let (status, message) = match self {
-            AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
-            AppError::NotFound => (StatusCode::NOT_FOUND, "Not found".to_string()),
-            AppError::DatabaseError => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Database error".to_string(),
-            ),
-            AppError::StorageError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
-            AppError::InternalError => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Internal error".to_string(),
-            ),
-        };
+fn read_file_error(e: MultipartError) -> AppError {
+    multipart_error("Failed to read file data", e)
+}
 
-        (status, message).into_response()
+fn multipart_error(context: &str, e: MultipartError) -> AppError {
+    let message = format!("{}: {}", context, e);
+    if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        AppError::PayloadTooLarge(message)
+    } else {
+        AppError::BadRequest(message)
     }
 }
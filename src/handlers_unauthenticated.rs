@@ -1,45 +1,134 @@
 use crate::app_state::AppState;
-use crate::handlers_public::AppError;
+use crate::content::{self, ContentOverrides};
+use crate::error::AppError;
+use crate::extract::{Form, Path, Query};
+use crate::links::{self, LinkState};
 use crate::models::*;
-use crate::schema::*;
+use crate::signed_urls::{SignedUrlClaims, SignedUrlQuery};
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
 };
 use chrono::Utc;
-use diesel::prelude::*;
 
 pub async fn get_file_by_link(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Path(link_key): Path<String>,
+) -> Response {
+    let password = links::basic_auth_password(&headers);
+    let result = serve_link(&state, &headers, &link_key, password).await;
+    link_response(&state, &headers, result)
+}
+
+/// Receives the password form shown to browsers for protected links.
+pub async fn submit_link_password(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(link_key): Path<String>,
+    Form(form): Form<LinkPasswordForm>,
+) -> Response {
+    let result = serve_link(&state, &headers, &link_key, Some(form.password)).await;
+    link_response(&state, &headers, result)
+}
+
+/// Browsers get HTML pages, and a password form where one is needed; API
+/// clients get the JSON error and a Basic challenge.
+fn link_response(
+    state: &AppState,
+    headers: &HeaderMap,
+    result: Result<Response, AppError>,
+) -> Response {
+    let err = match result {
+        Ok(response) => return response,
+        Err(err) => err,
+    };
+
+    let brand_name = state.config.brand_name.as_deref();
+    let unauthorized = err.status() == StatusCode::UNAUTHORIZED;
+
+    match (links::wants_html(headers), unauthorized) {
+        (true, true) => links::password_page(brand_name, err),
+        (true, false) => links::error_page(brand_name, err),
+        (false, _) => {
+            let mut response = err.into_response();
+            if unauthorized {
+                response.headers_mut().insert(
+                    header::WWW_AUTHENTICATE,
+                    HeaderValue::from_static("Basic realm=\"Share link\", charset=\"UTF-8\""),
+                );
+            }
+            response
+        }
+    }
+}
+
+async fn serve_link(
+    state: &AppState,
+    headers: &HeaderMap,
+    link_key: &str,
+    password: Option<String>,
 ) -> Result<Response, AppError> {
-    let mut conn = state.db_pool.get().map_err(|_| AppError::DatabaseError)?;
+    let file_link = state
+        .repos
+        .links
+        .find_by_key(link_key)
+        .await?
+        .ok_or(AppError::NotFound)?;
 
-    let file_link: FileLink = file_links::table
-        .filter(file_links::key.eq(&link_key))
-        .first(&mut conn)
-        .map_err(|_| AppError::NotFound)?;
+    LinkState::of(&file_link, Utc::now().naive_utc()).check()?;
 
-    if file_link.expires_at < Utc::now().naive_utc() {
-        return Err(AppError::BadRequest("Link expired".to_string()));
+    links::check_password(state, &file_link, password).await?;
+
+    let file = live_file(state.repos.files.find(file_link.file_oid).await?)?;
+
+    // Every response with content counts, ranged or not, so a limited link
+    // cannot be drained a range at a time.
+    let link_oid = file_link.oid;
+    state
+        .repos
+        .links
+        .record_download(link_oid)
+        .await?
+        .ok_or_else(links::exhausted)?;
+
+    let served = content::serve_file_content(state, &file, headers).await;
+    if !matches!(&served, Ok(response) if response.status().is_success()) {
+        if let Err(e) = state.repos.links.release_download(link_oid).await {
+            tracing::warn!("Failed to release download of link {}: {}", link_oid, e);
+        }
     }
+    served
+}
+
+fn live_file(record: Option<FileRecord>) -> Result<File, AppError> {
+    record
+        .map(|record| record.file)
+        .filter(|file| file.is_live(Utc::now().naive_utc()))
+        .ok_or(AppError::NotFound)
+}
+
+/// Serves a file through a signed URL. The signature stands in for a link
+/// row, so nothing is looked up but the file itself.
+pub async fn get_file_by_signed_url(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(file_id): Path<String>,
+    Query(query): Query<SignedUrlQuery>,
+) -> Result<Response, AppError> {
+    let claims = SignedUrlClaims::verify(
+        &state.config.url_signing_keys,
+        file_id,
+        query,
+        Utc::now().timestamp(),
+    )?;
+
+    let file = live_file(state.repos.files.find_by_id(&claims.file_id).await?)?;
 
-    let file: File = files::table
-        .find(file_link.file_oid)
-        .first(&mut conn)
-        .map_err(|_| AppError::NotFound)?;
-
-    let content = state
-        .storage_client
-        .download(&file.storage_key)
-        .await
-        .map_err(|e| AppError::StorageError(e.to_string()))?;
-
-    Ok((
-        StatusCode::OK,
-        [("Content-Type", "application/octet-stream")],
-        content,
-    )
-        .into_response())
+    let overrides = ContentOverrides {
+        disposition: claims.disposition,
+        content_type: claims.content_type.as_deref(),
+    };
+    content::serve_file_content_with(&state, &file, &headers, overrides).await
 }
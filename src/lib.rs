@@ -1,11 +1,29 @@
 pub mod app_state;
+pub mod auth;
+pub mod blobs;
+pub mod checksum;
 pub mod config;
+pub mod content;
 pub mod db;
+pub mod deletions;
+pub mod error;
+pub mod extract;
+pub mod file_listing;
+pub mod file_reaper;
 pub mod handlers_private;
 pub mod handlers_public;
 pub mod handlers_unauthenticated;
+pub mod link_sweeper;
+pub mod links;
 pub mod models;
+pub mod pagination;
+pub mod quota;
+pub mod range;
+pub mod reconcile;
+pub mod repository;
+pub mod request_id;
 pub mod schema;
+pub mod signed_urls;
 pub mod snowflake;
 pub mod startup;
 pub mod storage;
@@ -0,0 +1,79 @@
+use crate::app_state::AppState;
+use crate::error::AppError;
+use chrono::{Duration, Utc};
+use std::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+/// What one sweep removed.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SweepReport {
+    pub deleted: usize,
+    pub batches: usize,
+}
+
+/// Deletes links whose `expires_at` is more than `LINK_SWEEP_GRACE_SECS` in
+/// the past, `LINK_SWEEP_BATCH_SIZE` rows at a time so no single statement
+/// holds locks for long.
+///
+/// The grace period keeps recently expired links around, so visitors still
+/// get `410 Gone` rather than `404` for a while. Stops between batches once
+/// `shutdown` is cancelled.
+pub async fn sweep(
+    state: &AppState,
+    shutdown: &CancellationToken,
+) -> Result<SweepReport, AppError> {
+    let cutoff = Utc::now().naive_utc() - Duration::seconds(state.config.link_sweep_grace_secs);
+    let batch_size = state.config.link_sweep_batch_size;
+    let mut report = SweepReport::default();
+
+    while !shutdown.is_cancelled() {
+        let (selected, deleted) = state.repos.links.delete_expired(cutoff, batch_size).await?;
+
+        if selected == 0 {
+            break;
+        }
+
+        report.deleted += deleted;
+        report.batches += 1;
+        if (selected as i64) < batch_size {
+            break;
+        }
+        tokio::task::yield_now().await;
+    }
+
+    Ok(report)
+}
+
+/// Sweeps expired links every `interval` until `shutdown` is cancelled.
+pub async fn run_periodically(
+    state: AppState,
+    interval: std::time::Duration,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // The first tick completes immediately; wait a full interval after startup.
+    ticker.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                tracing::info!("Link sweeper stopped");
+                return;
+            }
+            _ = ticker.tick() => {}
+        }
+
+        let started = Instant::now();
+        match sweep(&state, &shutdown).await {
+            Ok(report) if report.deleted > 0 => tracing::info!(
+                deleted = report.deleted,
+                batches = report.batches,
+                elapsed_ms = started.elapsed().as_millis() as u64,
+                "Swept expired links"
+            ),
+            Ok(_) => tracing::debug!("No expired links to sweep"),
+            Err(e) => tracing::warn!("Link sweep failed: {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,447 @@
+use crate::app_state::AppState;
+use crate::error::AppError;
+use crate::models::FileLink;
+use crate::repository::PasswordAttempt;
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{Html, IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{Duration, NaiveDateTime, Utc};
+
+/// Where a share link is in its lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    Active,
+    /// `starts_at` is still in the future.
+    NotYetValid,
+    Expired,
+    Revoked,
+    /// The link has served all the downloads it allows.
+    Exhausted,
+}
+
+impl LinkState {
+    /// Revocation wins over everything else, and a link that has expired
+    /// stays expired even if it never became valid.
+    pub fn of(link: &FileLink, now: NaiveDateTime) -> Self {
+        if link.revoked_at.is_some() {
+            LinkState::Revoked
+        } else if link.expires_at < now {
+            LinkState::Expired
+        } else if link
+            .max_downloads
+            .is_some_and(|max| link.download_count >= max)
+        {
+            LinkState::Exhausted
+        } else if link.starts_at.is_some_and(|starts_at| starts_at > now) {
+            LinkState::NotYetValid
+        } else {
+            LinkState::Active
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkState::Active => "active",
+            LinkState::NotYetValid => "not_yet_valid",
+            LinkState::Expired => "expired",
+            LinkState::Revoked => "revoked",
+            LinkState::Exhausted => "exhausted",
+        }
+    }
+
+    /// The error a download through a link in this state fails with.
+    pub fn check(&self) -> Result<(), AppError> {
+        match self {
+            LinkState::Active => Ok(()),
+            LinkState::NotYetValid => Err(AppError::Forbidden("Link is not yet valid".to_string())),
+            LinkState::Expired => Err(AppError::Gone("Link expired".to_string())),
+            LinkState::Revoked => Err(AppError::Gone("Link revoked".to_string())),
+            LinkState::Exhausted => Err(exhausted()),
+        }
+    }
+}
+
+/// Bounds for custom link keys; generated keys are at least `MIN_GENERATED_KEY_LEN`.
+pub const MIN_KEY_LEN: usize = 4;
+pub const MAX_KEY_LEN: usize = 128;
+pub const MIN_GENERATED_KEY_LEN: usize = 16;
+
+/// The most `LINK_MAX_TTL_SECS` may be set to, ten years.
+pub const MAX_LINK_TTL_SECS: i64 = 10 * 365 * 24 * 60 * 60;
+
+/// Keys that would be confusing or collide with paths next to `/f/` on a
+/// public deployment. Compared case-insensitively.
+const RESERVED_KEYS: &[&str] = &[
+    "admin", "api", "assets", "favicon", "health", "login", "logout", "robots", "static",
+];
+
+/// Checks a caller-chosen key: ASCII letters, digits, `-` and `_`, between
+/// `MIN_KEY_LEN` and `MAX_KEY_LEN` characters, and not a reserved word.
+pub fn validate_key(key: &str) -> Result<(), AppError> {
+    if key.len() < MIN_KEY_LEN || key.len() > MAX_KEY_LEN {
+        return Err(AppError::invalid_param(
+            "key",
+            format!(
+                "key must be between {} and {} characters",
+                MIN_KEY_LEN, MAX_KEY_LEN
+            ),
+        ));
+    }
+    if !key
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+    {
+        return Err(AppError::invalid_param(
+            "key",
+            "key may only contain letters, digits, '-' and '_'",
+        ));
+    }
+    if RESERVED_KEYS.iter().any(|r| r.eq_ignore_ascii_case(key)) {
+        return Err(AppError::invalid_param("key", "key is reserved"));
+    }
+    Ok(())
+}
+
+/// A random alphanumeric key drawn from the operating system's CSPRNG.
+pub fn generate_key(len: usize) -> String {
+    use rand::Rng;
+    OsRng
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+/// The error for a link whose last download was taken concurrently.
+pub fn exhausted() -> AppError {
+    AppError::Gone("Link has reached its download limit".to_string())
+}
+
+const PASSWORD_REQUIRED: &str = "Password required";
+const INCORRECT_PASSWORD: &str = "Incorrect password";
+
+/// Hashes a link password with Argon2 and a random salt.
+pub fn hash_password(password: &str) -> Result<String, AppError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(AppError::internal)
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+/// The password from an `Authorization: Basic` header; the user name is ignored.
+pub fn basic_auth_password(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, encoded) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+
+    let decoded = STANDARD.decode(encoded.trim()).ok()?;
+    let credentials = String::from_utf8(decoded).ok()?;
+    let (_, password) = credentials.split_once(':')?;
+    Some(password.to_string())
+}
+
+/// Checks the password of a protected link, counting attempts against it.
+///
+/// Each attempt is counted before the password is verified, so guesses sent
+/// at once cannot all slip past the limit; the right password clears the
+/// count again. After `LINK_PASSWORD_MAX_ATTEMPTS` wrong guesses the link
+/// refuses every attempt, right or wrong, for `LINK_PASSWORD_LOCKOUT_SECS`.
+pub async fn check_password(
+    state: &AppState,
+    link: &FileLink,
+    password: Option<String>,
+) -> Result<(), AppError> {
+    let Some(hash) = link.password_hash.clone() else {
+        return Ok(());
+    };
+
+    if let Some(locked_until) = link.password_locked_until {
+        check_lock(locked_until)?;
+    }
+
+    let password = password.ok_or_else(|| AppError::Unauthorized(PASSWORD_REQUIRED.to_string()))?;
+
+    let links = &state.repos.links;
+    let attempt = links
+        .reserve_password_attempt(
+            link.oid,
+            state.config.link_password_max_attempts,
+            Duration::seconds(state.config.link_password_lockout_secs),
+        )
+        .await?;
+    if let PasswordAttempt::Locked(locked_until) = attempt {
+        check_lock(locked_until)?;
+    }
+
+    let valid = tokio::task::spawn_blocking(move || verify_password(&password, &hash))
+        .await
+        .map_err(AppError::internal)?;
+
+    if !valid {
+        return Err(AppError::Unauthorized(INCORRECT_PASSWORD.to_string()));
+    }
+
+    links.reset_failed_attempts(link.oid).await
+}
+
+fn check_lock(locked_until: NaiveDateTime) -> Result<(), AppError> {
+    let now = Utc::now().naive_utc();
+    if locked_until > now {
+        return Err(AppError::RateLimited {
+            retry_after_secs: (locked_until - now).num_seconds().max(1) as u64,
+        });
+    }
+    Ok(())
+}
+
+/// Whether the client is a browser that would rather see a page than JSON.
+pub fn wants_html(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_type| {
+            let mut parts = media_type.split(';').map(str::trim);
+            let is_html = parts.next() == Some("text/html");
+            // `q=0`, however written, rules the type out.
+            let quality = parts
+                .filter_map(|param| param.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+                .map_or(1.0, |(_, value)| value.trim().parse::<f32>().unwrap_or(0.0));
+            is_html && quality > 0.0
+        })
+}
+
+/// Renders a link error as a small HTML page, titled with `BRAND_NAME` if set.
+pub fn error_page(brand_name: Option<&str>, err: AppError) -> Response {
+    err.log();
+
+    let status = err.status();
+    let heading = match status {
+        StatusCode::NOT_FOUND => "Link not found",
+        StatusCode::GONE => "Link no longer available",
+        StatusCode::FORBIDDEN => "Link not available yet",
+        StatusCode::TOO_MANY_REQUESTS => "Too many attempts",
+        s if s.is_client_error() => "Request failed",
+        _ => "Something went wrong",
+    };
+    let content = format!("<p>{}</p>", escape_html(&err.message()));
+
+    let mut response = page(brand_name, status, heading, &content);
+    if let AppError::RateLimited { retry_after_secs } = err {
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(retry_after_secs));
+    }
+    response
+}
+
+/// Asks for the password of a protected link with a form that posts back
+/// to the link URL.
+pub fn password_page(brand_name: Option<&str>, err: AppError) -> Response {
+    err.log();
+
+    let notice = match err.message().as_str() {
+        INCORRECT_PASSWORD => format!("<p class=\"error\">{}</p>", INCORRECT_PASSWORD),
+        _ => String::new(),
+    };
+    let content = format!(
+        r#"{notice}<form method="post">
+<label for="password">Password</label>
+<input type="password" id="password" name="password" required autofocus>
+<button type="submit">Download</button>
+</form>"#
+    );
+
+    page(
+        brand_name,
+        StatusCode::UNAUTHORIZED,
+        "This link is password protected",
+        &content,
+    )
+}
+
+fn page(brand_name: Option<&str>, status: StatusCode, heading: &str, content: &str) -> Response {
+    let title = match brand_name {
+        Some(brand) => format!("{} · {}", heading, escape_html(brand)),
+        None => heading.to_string(),
+    };
+    let footer = brand_name
+        .map(|brand| format!("<footer>{}</footer>", escape_html(brand)))
+        .unwrap_or_default();
+
+    let body = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<style>body{{font-family:system-ui,sans-serif;max-width:32rem;margin:4rem auto;padding:0 1rem;color:#222}}h1{{font-size:1.5rem}}input,button{{font:inherit;margin:.5rem 0;display:block}}.error{{color:#b00}}footer{{margin-top:2rem;color:#777;font-size:.875rem}}</style>
+</head>
+<body>
+<h1>{heading}</h1>
+{content}
+{footer}
+</body>
+</html>
+"#
+    );
+
+    (status, Html(body)).into_response()
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(expires_in: i64, starts_in: Option<i64>, revoked: bool) -> (FileLink, NaiveDateTime) {
+        let now = Utc::now().naive_utc();
+        let link = FileLink {
+            oid: 1,
+            id: "link_1".to_string(),
+            file_oid: 1,
+            key: "key".to_string(),
+            expires_at: now + Duration::seconds(expires_in),
+            created_at: now,
+            starts_at: starts_in.map(|s| now + Duration::seconds(s)),
+            revoked_at: revoked.then_some(now),
+            max_downloads: None,
+            download_count: 0,
+            last_accessed_at: None,
+            password_hash: None,
+            failed_password_attempts: 0,
+            password_locked_until: None,
+        };
+        (link, now)
+    }
+
+    #[test]
+    fn test_link_states() {
+        let (active, now) = link(60, Some(-10), false);
+        assert_eq!(LinkState::of(&active, now), LinkState::Active);
+
+        let (pending, now) = link(60, Some(10), false);
+        assert_eq!(LinkState::of(&pending, now), LinkState::NotYetValid);
+
+        let (expired, now) = link(-1, Some(10), false);
+        assert_eq!(LinkState::of(&expired, now), LinkState::Expired);
+
+        let (revoked, now) = link(-1, None, true);
+        assert_eq!(LinkState::of(&revoked, now), LinkState::Revoked);
+
+        let (mut used, now) = link(60, None, false);
+        used.max_downloads = Some(1);
+        assert_eq!(LinkState::of(&used, now), LinkState::Active);
+        used.download_count = 1;
+        assert_eq!(LinkState::of(&used, now), LinkState::Exhausted);
+    }
+
+    #[test]
+    fn test_link_state_status_codes() {
+        let status = |state: LinkState| state.check().unwrap_err().status();
+        assert!(LinkState::Active.check().is_ok());
+        assert_eq!(status(LinkState::NotYetValid), StatusCode::FORBIDDEN);
+        assert_eq!(status(LinkState::Expired), StatusCode::GONE);
+        assert_eq!(status(LinkState::Revoked), StatusCode::GONE);
+        assert_eq!(status(LinkState::Exhausted), StatusCode::GONE);
+    }
+
+    #[test]
+    fn test_validate_key() {
+        assert!(validate_key("quarterly-report_2026").is_ok());
+        assert!(validate_key(&"a".repeat(MAX_KEY_LEN)).is_ok());
+
+        for key in ["", "abc", "has space", "a/b", "dots.txt", "ключ", "Admin"] {
+            let err = validate_key(key).unwrap_err();
+            assert_eq!(err.param(), Some("key"), "{:?}", key);
+        }
+        assert!(validate_key(&"a".repeat(MAX_KEY_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_generate_key() {
+        let key = generate_key(32);
+        assert_eq!(key.len(), 32);
+        assert!(validate_key(&key).is_ok());
+        assert_ne!(key, generate_key(32));
+    }
+
+    #[test]
+    fn test_wants_html() {
+        let accept = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT, value.parse().unwrap());
+            wants_html(&headers)
+        };
+
+        assert!(accept("text/html,application/xhtml+xml,*/*;q=0.8"));
+        assert!(!accept("application/json"));
+        assert!(!accept("text/html;q=0"));
+        assert!(!accept("text/html; q=0.0"));
+        assert!(!accept("text/html;Q=0.000"));
+        assert!(accept("text/html;q=0.5"));
+        assert!(!wants_html(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn test_password_hash_roundtrip() {
+        let hash = hash_password("hunter2").unwrap();
+        assert!(hash.starts_with("$argon2"));
+        assert!(verify_password("hunter2", &hash));
+        assert!(!verify_password("hunter3", &hash));
+        assert!(!verify_password("hunter2", "not-a-hash"));
+    }
+
+    #[test]
+    fn test_basic_auth_password() {
+        let mut headers = HeaderMap::new();
+        let credentials = STANDARD.encode("anyone:pa:ss");
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Basic {}", credentials).parse().unwrap(),
+        );
+        assert_eq!(basic_auth_password(&headers).as_deref(), Some("pa:ss"));
+
+        headers.insert(header::AUTHORIZATION, "Bearer token".parse().unwrap());
+        assert_eq!(basic_auth_password(&headers), None);
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(escape_html("<Acme & Co>"), "&lt;Acme &amp; Co&gt;");
+    }
+}
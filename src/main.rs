@@ -1,11 +1,29 @@
 mod app_state;
+mod auth;
+mod blobs;
+mod checksum;
 mod config;
+mod content;
 mod db;
+mod deletions;
+mod error;
+mod extract;
+mod file_listing;
+mod file_reaper;
 mod handlers_private;
 mod handlers_public;
 mod handlers_unauthenticated;
+mod link_sweeper;
+mod links;
 mod models;
+mod pagination;
+mod quota;
+mod range;
+mod reconcile;
+mod repository;
+mod request_id;
 mod schema;
+mod signed_urls;
 mod snowflake;
 mod startup;
 mod storage;
@@ -14,14 +32,18 @@ mod storage;
 mod test_utils;
 
 use app_state::AppState;
+use auth::AuthChain;
 use axum::{
+    extract::DefaultBodyLimit,
     http::Method,
+    middleware,
     routing::{delete, get, post, put},
     Router,
 };
 use config::Config;
 use snowflake::SnowflakeGeneratorWrapper;
 use storage::ObjectStorageClient;
+use tokio_util::sync::CancellationToken;
 use tower_http::cors::{Any, CorsLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
@@ -39,23 +61,57 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let config = Config::from_env()?;
 
-    let db_pool = db::create_pool(&config.database_url)?;
+    let db_pool = db::create_pool(&config)?;
 
     let mut conn = db_pool.get()?;
     db::run_migrations(&mut conn)?;
     tracing::info!("Database migrations completed");
+    drop(conn);
 
     let snowflake_gen = SnowflakeGeneratorWrapper::new(config.worker_id, config.datacenter_id)?;
 
-    startup::upsert_purposes(&mut conn, &snowflake_gen, &config.allowed_purposes)?;
-    tracing::info!("Purposes upserted");
-
     let storage_client = ObjectStorageClient::new(
         config.storage_base_url.clone(),
         config.storage_bucket.clone(),
     );
 
-    let state = AppState::new(db_pool, storage_client, snowflake_gen, config.clone());
+    let auth = AuthChain::from_config(&config)?;
+
+    let state = AppState::new(db_pool, storage_client, snowflake_gen, auth, config.clone());
+
+    startup::upsert_purposes(
+        state.repos.purposes.as_ref(),
+        &state.snowflake_gen,
+        &config.allowed_purposes,
+    )
+    .await?;
+    tracing::info!("Purposes upserted");
+
+    let shutdown = CancellationToken::new();
+
+    let reconciler = (config.reconcile_interval_secs > 0).then(|| {
+        tokio::spawn(reconcile::run_periodically(
+            state.clone(),
+            std::time::Duration::from_secs(config.reconcile_interval_secs),
+            shutdown.clone(),
+        ))
+    });
+
+    let link_sweeper = (config.link_sweep_interval_secs > 0).then(|| {
+        tokio::spawn(link_sweeper::run_periodically(
+            state.clone(),
+            std::time::Duration::from_secs(config.link_sweep_interval_secs),
+            shutdown.clone(),
+        ))
+    });
+
+    let file_reaper = (config.file_reap_interval_secs > 0).then(|| {
+        tokio::spawn(file_reaper::run_periodically(
+            state.clone(),
+            std::time::Duration::from_secs(config.file_reap_interval_secs),
+            shutdown.clone(),
+        ))
+    });
 
     let cors = CorsLayer::new()
         .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
@@ -63,7 +119,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .allow_origin(Any);
 
     let public_app = Router::new()
-        .route("/files", post(handlers_public::upload_file))
+        .route(
+            "/files",
+            // The upload handler enforces MAX_FILE_SIZE_BYTES while streaming.
+            post(handlers_public::upload_file).layer(DefaultBodyLimit::disable()),
+        )
+        .route("/files", get(handlers_public::list_files))
         .route("/files/:file_id", get(handlers_public::get_file))
         .route(
             "/files/:file_id/content",
@@ -73,18 +134,67 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             "/f/:link_key",
             get(handlers_unauthenticated::get_file_by_link),
         )
+        .route(
+            "/f/:link_key",
+            post(handlers_unauthenticated::submit_link_password),
+        )
+        .route(
+            "/s/:file_id",
+            get(handlers_unauthenticated::get_file_by_signed_url),
+        )
         .layer(cors.clone())
+        .layer(middleware::from_fn(request_id::assign))
         .with_state(state.clone());
 
     let private_app = Router::new()
         .route("/files/:file_id", delete(handlers_private::delete_file))
         .route("/files/:file_id", put(handlers_private::update_file))
         .route("/files/:file_id", get(handlers_private::get_file_private))
+        .route(
+            "/files/:file_id/verify",
+            post(handlers_private::verify_file),
+        )
+        .route(
+            "/files/:file_id/restore",
+            post(handlers_private::restore_file),
+        )
+        .route(
+            "/files/:file_id/signed-urls",
+            post(handlers_private::create_signed_url),
+        )
+        .route(
+            "/files/:file_id/links",
+            get(handlers_private::list_file_links),
+        )
+        .route(
+            "/files/:file_id/links",
+            delete(handlers_private::revoke_file_links),
+        )
         .route("/files", get(handlers_private::list_files))
+        .route("/tenants", post(handlers_private::create_tenant))
+        .route("/tenants", get(handlers_private::list_tenants))
+        .route("/tenants/:tenant_id", get(handlers_private::get_tenant))
+        .route("/tenants/:tenant_id", put(handlers_private::update_tenant))
+        .route(
+            "/tenants/:tenant_id",
+            delete(handlers_private::delete_tenant),
+        )
+        .route("/reconcile", post(handlers_private::reconcile_storage))
+        .route("/api-keys", post(handlers_private::create_api_key))
+        .route(
+            "/api-keys/:api_key_id",
+            delete(handlers_private::revoke_api_key),
+        )
         .route("/links", post(handlers_private::create_link))
+        .route("/links", get(handlers_private::list_links))
         .route("/links/:link_id", get(handlers_private::get_link))
         .route("/links/:link_id", delete(handlers_private::delete_link))
+        .route(
+            "/links/:link_id/revoke",
+            post(handlers_private::revoke_link),
+        )
         .layer(cors)
+        .layer(middleware::from_fn(request_id::assign))
         .with_state(state);
 
     let public_addr = format!("{}:{}", config.public_host, config.public_port);
@@ -96,19 +206,68 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let public_listener = tokio::net::TcpListener::bind(&public_addr).await?;
     let private_listener = tokio::net::TcpListener::bind(&private_addr).await?;
 
-    let public_serve = tokio::spawn(async move { axum::serve(public_listener, public_app).await });
+    let public_shutdown = shutdown.clone();
+    let public_serve = tokio::spawn(async move {
+        axum::serve(public_listener, public_app)
+            .with_graceful_shutdown(public_shutdown.cancelled_owned())
+            .await
+    });
 
-    let private_serve =
-        tokio::spawn(async move { axum::serve(private_listener, private_app).await });
+    let private_shutdown = shutdown.clone();
+    let private_serve = tokio::spawn(async move {
+        axum::serve(private_listener, private_app)
+            .with_graceful_shutdown(private_shutdown.cancelled_owned())
+            .await
+    });
 
-    tokio::select! {
-        result = public_serve => {
-            result??;
-        }
-        result = private_serve => {
-            result??;
-        }
+    let signal_shutdown = shutdown.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        tracing::info!("Shutting down");
+        signal_shutdown.cancel();
+    });
+
+    let result = tokio::select! {
+        result = public_serve => result,
+        result = private_serve => result,
+    };
+
+    shutdown.cancel();
+    if let Some(reconciler) = reconciler {
+        reconciler.await?;
+    }
+    if let Some(link_sweeper) = link_sweeper {
+        link_sweeper.await?;
+    }
+    if let Some(file_reaper) = file_reaper {
+        file_reaper.await?;
     }
 
+    result??;
+
     Ok(())
 }
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
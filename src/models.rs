@@ -2,7 +2,9 @@ use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 
-#[derive(Queryable, Selectable, Identifiable, Debug)]
+pub const MAX_TENANT_ID_LEN: usize = 255;
+
+#[derive(Queryable, Selectable, Identifiable, Clone, Debug)]
 #[diesel(table_name = crate::schema::tenants)]
 #[diesel(primary_key(oid))]
 pub struct Tenant {
@@ -13,6 +15,9 @@ pub struct Tenant {
     pub updated_at: NaiveDateTime,
     pub total_files_bytes: i64,
     pub file_count: i64,
+    pub max_total_bytes: Option<i64>,
+    pub max_file_count: Option<i64>,
+    pub max_file_size_bytes: Option<i64>,
 }
 
 #[derive(Insertable)]
@@ -21,9 +26,23 @@ pub struct NewTenant {
     pub oid: i64,
     pub id: String,
     pub name: String,
+    pub max_total_bytes: Option<i64>,
+    pub max_file_count: Option<i64>,
+    pub max_file_size_bytes: Option<i64>,
+}
+
+/// Quota fields use `Some(None)` to clear an override back to the default.
+#[derive(AsChangeset)]
+#[diesel(table_name = crate::schema::tenants)]
+pub struct UpdateTenant {
+    pub name: Option<String>,
+    pub max_total_bytes: Option<Option<i64>>,
+    pub max_file_count: Option<Option<i64>>,
+    pub max_file_size_bytes: Option<Option<i64>>,
+    pub updated_at: NaiveDateTime,
 }
 
-#[derive(Queryable, Selectable, Identifiable, Debug)]
+#[derive(Queryable, Selectable, Identifiable, Clone, Debug)]
 #[diesel(table_name = crate::schema::purposes)]
 #[diesel(primary_key(oid))]
 pub struct Purpose {
@@ -40,7 +59,7 @@ pub struct NewPurpose {
     pub slug: String,
 }
 
-#[derive(Queryable, Selectable, Identifiable, Associations, Debug)]
+#[derive(Queryable, Selectable, Identifiable, Associations, Clone, Debug)]
 #[diesel(table_name = crate::schema::files)]
 #[diesel(belongs_to(Tenant, foreign_key = tenant_oid))]
 #[diesel(belongs_to(Purpose, foreign_key = purpose_oid))]
@@ -55,6 +74,65 @@ pub struct File {
     pub storage_key: String,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub content_type: String,
+    pub sha256: Option<String>,
+    pub md5: Option<String>,
+    pub crc32c: Option<String>,
+    pub blob_oid: Option<i64>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub deleted_at: Option<NaiveDateTime>,
+}
+
+/// A file together with the tenant id and purpose slug its responses show.
+#[derive(Queryable, Selectable, Clone, Debug)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct FileRecord {
+    #[diesel(embed)]
+    pub file: File,
+    #[diesel(select_expression = crate::schema::tenants::id)]
+    pub tenant_id: String,
+    #[diesel(select_expression = crate::schema::purposes::slug)]
+    pub purpose: String,
+}
+
+/// The `files` table joined with `tenants` and `purposes`.
+pub type FileRecordSource = diesel::dsl::InnerJoin<
+    diesel::dsl::InnerJoin<crate::schema::files::table, crate::schema::tenants::table>,
+    crate::schema::purposes::table,
+>;
+
+impl Tenant {
+    /// Tenant ids prefix the storage keys of their files, so they are kept to
+    /// characters that need no escaping in a key or a URL.
+    pub fn is_valid_id(id: &str) -> bool {
+        (1..=MAX_TENANT_ID_LEN).contains(&id.len())
+            && id
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+    }
+}
+
+impl File {
+    /// Whether the file can be read: out of the trash and not past its
+    /// expiry, even if the reaper has yet to delete it.
+    pub fn is_live(&self, now: NaiveDateTime) -> bool {
+        self.deleted_at.is_none() && self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+}
+
+impl FileRecord {
+    /// Files joined with their tenant and purpose, so a record loads in a
+    /// single query. Filter it on `files` columns.
+    pub fn query(
+    ) -> diesel::dsl::Select<FileRecordSource, diesel::dsl::AsSelect<FileRecord, diesel::pg::Pg>>
+    {
+        use crate::schema::{files, purposes, tenants};
+
+        files::table
+            .inner_join(tenants::table)
+            .inner_join(purposes::table)
+            .select(FileRecord::as_select())
+    }
 }
 
 #[derive(Insertable)]
@@ -67,6 +145,12 @@ pub struct NewFile {
     pub purpose_oid: i64,
     pub bytes: i64,
     pub storage_key: String,
+    pub content_type: String,
+    pub sha256: Option<String>,
+    pub md5: Option<String>,
+    pub crc32c: Option<String>,
+    pub blob_oid: Option<i64>,
+    pub expires_at: Option<NaiveDateTime>,
 }
 
 #[derive(AsChangeset)]
@@ -74,10 +158,88 @@ pub struct NewFile {
 pub struct UpdateFile {
     pub filename: Option<String>,
     pub purpose_oid: Option<i64>,
+    /// `Some(None)` clears the expiry.
+    pub expires_at: Option<Option<NaiveDateTime>>,
     pub updated_at: NaiveDateTime,
 }
 
-#[derive(Queryable, Selectable, Identifiable, Associations, Debug)]
+#[derive(Queryable, Selectable, Identifiable, Associations, Debug, Clone)]
+#[diesel(table_name = crate::schema::blobs)]
+#[diesel(belongs_to(Tenant, foreign_key = tenant_oid))]
+#[diesel(primary_key(oid))]
+pub struct Blob {
+    pub oid: i64,
+    pub id: String,
+    pub tenant_oid: i64,
+    pub sha256: String,
+    pub bytes: i64,
+    pub storage_key: String,
+    pub ref_count: i64,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::blobs)]
+pub struct NewBlob {
+    pub oid: i64,
+    pub id: String,
+    pub tenant_oid: i64,
+    pub sha256: String,
+    pub bytes: i64,
+    pub storage_key: String,
+    pub ref_count: i64,
+}
+
+#[derive(Queryable, Selectable, Identifiable, Associations, Debug, Clone)]
+#[diesel(table_name = crate::schema::api_keys)]
+#[diesel(belongs_to(Tenant, foreign_key = tenant_oid))]
+#[diesel(primary_key(oid))]
+pub struct ApiKey {
+    pub oid: i64,
+    pub id: String,
+    pub tenant_oid: i64,
+    pub name: String,
+    pub lookup: String,
+    pub key_hash: String,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::api_keys)]
+pub struct NewApiKey {
+    pub oid: i64,
+    pub id: String,
+    pub tenant_oid: i64,
+    pub name: String,
+    pub lookup: String,
+    pub key_hash: String,
+}
+
+/// A storage object that no longer has a database row and still needs deleting.
+#[derive(Queryable, Selectable, Identifiable, Debug, Clone)]
+#[diesel(table_name = crate::schema::pending_deletions)]
+#[diesel(primary_key(oid))]
+pub struct PendingDeletion {
+    pub oid: i64,
+    pub storage_key: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::pending_deletions)]
+pub struct NewPendingDeletion {
+    pub oid: i64,
+    pub storage_key: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+}
+
+#[derive(Queryable, Selectable, Identifiable, Associations, Clone, Debug)]
 #[diesel(table_name = crate::schema::file_links)]
 #[diesel(belongs_to(File, foreign_key = file_oid))]
 #[diesel(primary_key(oid))]
@@ -88,6 +250,14 @@ pub struct FileLink {
     pub key: String,
     pub expires_at: NaiveDateTime,
     pub created_at: NaiveDateTime,
+    pub starts_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub max_downloads: Option<i64>,
+    pub download_count: i64,
+    pub last_accessed_at: Option<NaiveDateTime>,
+    pub password_hash: Option<String>,
+    pub failed_password_attempts: i32,
+    pub password_locked_until: Option<NaiveDateTime>,
 }
 
 #[derive(Insertable)]
@@ -98,6 +268,9 @@ pub struct NewFileLink {
     pub file_oid: i64,
     pub key: String,
     pub expires_at: NaiveDateTime,
+    pub starts_at: Option<NaiveDateTime>,
+    pub max_downloads: Option<i64>,
+    pub password_hash: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -109,8 +282,63 @@ pub struct FileResponse {
     pub updated_at: i64,
     pub filename: String,
     pub purpose: String,
+    pub content_type: String,
+    pub sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crc32c: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tenant_id: Option<String>,
+    pub expires_at: Option<i64>,
+    pub deleted_at: Option<i64>,
+}
+
+impl From<FileRecord> for FileResponse {
+    fn from(record: FileRecord) -> Self {
+        let file = record.file;
+        Self {
+            id: file.id,
+            object: "file".to_string(),
+            bytes: file.bytes,
+            created_at: file.created_at.and_utc().timestamp(),
+            updated_at: file.updated_at.and_utc().timestamp(),
+            filename: file.filename,
+            purpose: record.purpose,
+            content_type: file.content_type,
+            sha256: file.sha256,
+            md5: file.md5,
+            crc32c: file.crc32c,
+            tenant_id: Some(record.tenant_id),
+            expires_at: file.expires_at.map(|t| t.and_utc().timestamp()),
+            deleted_at: file.deleted_at.map(|t| t.and_utc().timestamp()),
+        }
+    }
+}
+
+impl FileResponse {
+    /// The file as its own tenant sees it, without the `tenant_id` the admin
+    /// API adds.
+    pub fn for_tenant(record: FileRecord) -> Self {
+        Self {
+            tenant_id: None,
+            ..record.into()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FileVerificationResponse {
+    pub object: String,
+    pub file_id: String,
+    pub algorithm: String,
+    pub expected: Option<String>,
+    pub actual: String,
+    pub expected_bytes: i64,
+    pub actual_bytes: i64,
+    /// `None` when there was no stored hash to check the content against.
+    pub valid: Option<bool>,
+    pub backfilled: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -119,20 +347,118 @@ pub struct FileLinkResponse {
     pub object: String,
     pub file_id: String,
     pub key: String,
+    pub state: String,
     pub expires_at: i64,
     pub created_at: i64,
+    pub starts_at: Option<i64>,
+    pub revoked_at: Option<i64>,
+    pub max_downloads: Option<i64>,
+    pub download_count: i64,
+    pub last_accessed_at: Option<i64>,
+    pub password_protected: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ApiKeyResponse {
+    pub id: String,
+    pub object: String,
+    pub tenant_id: String,
+    pub name: String,
+    /// The plaintext key, only returned when the key is created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+    pub revoked_at: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TenantResponse {
+    pub id: String,
+    pub object: String,
+    pub name: String,
+    pub total_files_bytes: i64,
+    pub file_count: i64,
+    /// Per-tenant overrides; `None` means the service-wide default applies.
+    pub max_total_bytes: Option<i64>,
+    pub max_file_count: Option<i64>,
+    pub max_file_size_bytes: Option<i64>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListTenantsResponse {
+    pub items: Vec<TenantResponse>,
+    pub pagination: PaginationResponse,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReconciliationResponse {
+    pub object: String,
+    pub mode: String,
+    pub tenants_checked: i64,
+    pub objects_scanned: i64,
+    pub orphans: Vec<OrphanObjectReport>,
+    /// File rows whose object is missing from storage. Reported, never fixed.
+    pub missing_objects: Vec<String>,
+    pub counter_drift: Vec<CounterDriftReport>,
+    pub pending_deletions: i64,
+    pub pending_deletions_cleared: i64,
+    pub errors: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OrphanObjectReport {
+    pub tenant_id: String,
+    pub storage_key: String,
+    pub bytes: Option<i64>,
+    pub last_modified: Option<i64>,
+    /// Objects younger than the grace period may belong to in-flight uploads.
+    pub recent: bool,
+    pub deleted: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CounterDriftReport {
+    pub tenant_id: String,
+    pub recorded_bytes: i64,
+    pub actual_bytes: i64,
+    pub recorded_count: i64,
+    pub actual_count: i64,
+    pub fixed: bool,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct ListFilesResponse {
     pub items: Vec<FileResponse>,
     pub pagination: PaginationResponse,
+    /// Files matching the filters across all pages, with `include_total=true`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_count: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListLinksResponse {
+    pub items: Vec<FileLinkResponse>,
+    pub pagination: PaginationResponse,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RevokeLinksResponse {
+    pub object: String,
+    pub file_id: String,
+    pub revoked: i64,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct PaginationResponse {
     pub has_more_before: bool,
     pub has_more_after: bool,
+    /// Pass as `before` to get the previous page; set when `has_more_before`.
+    pub prev_cursor: Option<String>,
+    /// Pass as `after` to get the next page; set when `has_more_after`.
+    pub next_cursor: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -140,17 +466,143 @@ pub struct CreateLinkRequest {
     pub expires_in: i64,
     pub file_id: String,
     pub key: Option<String>,
+    /// Unix timestamp before which the link does not serve the file.
+    pub starts_at: Option<i64>,
+    /// How many times the link may be used; unlimited when unset.
+    pub max_downloads: Option<i64>,
+    /// Required to download through the link; only its hash is stored.
+    pub password: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateSignedUrlRequest {
+    pub expires_in: i64,
+    pub disposition: Option<String>,
+    pub content_type: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SignedUrlResponse {
+    pub object: String,
+    pub file_id: String,
+    pub url: String,
+    pub expires_at: i64,
+}
+
+#[derive(Deserialize)]
+pub struct LinkPasswordForm {
+    pub password: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub tenant_id: String,
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateTenantRequest {
+    /// External identifier callers authenticate with; generated when omitted.
+    pub id: Option<String>,
+    pub name: String,
+    pub max_total_bytes: Option<i64>,
+    pub max_file_count: Option<i64>,
+    pub max_file_size_bytes: Option<i64>,
+}
+
+/// Omitted fields are left unchanged; an explicit `null` quota clears the override.
+#[derive(Deserialize)]
+pub struct UpdateTenantRequest {
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "present")]
+    pub max_total_bytes: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "present")]
+    pub max_file_count: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "present")]
+    pub max_file_size_bytes: Option<Option<i64>>,
+}
+
+/// Distinguishes a field sent as `null` from one that was left out.
+fn present<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Deserialize)]
+pub struct ReconcileQuery {
+    pub mode: Option<String>,
+    pub tenant_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ListTenantsQuery {
+    pub limit: Option<i64>,
+    pub order: Option<String>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ListLinksQuery {
+    pub file_id: Option<String>,
+    pub tenant_id: Option<String>,
+    /// Only links that can serve their file right now, or only those that cannot.
+    pub active: Option<bool>,
+    pub limit: Option<i64>,
+    pub order: Option<String>,
+    pub before: Option<String>,
+    pub after: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct UpdateFileRequest {
     pub filename: Option<String>,
     pub purpose: Option<String>,
+    /// Unix timestamp; `null` removes the expiry, leaving it out keeps it.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub expires_at: Option<Option<i64>>,
+}
+
+/// Tells an explicit `null` apart from a missing field.
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+#[derive(Deserialize)]
+pub struct DeleteFileQuery {
+    /// Skip the trash and delete the file and its object right away.
+    pub permanent: Option<bool>,
 }
 
 #[derive(Deserialize)]
 pub struct ListFilesQuery {
     pub tenant_id: Option<String>,
+    /// List the trash instead of live files.
+    pub deleted: Option<bool>,
+    /// Purpose slug.
+    pub purpose: Option<String>,
+    pub filename_prefix: Option<String>,
+    /// Case-insensitive substring of the filename.
+    pub filename_contains: Option<String>,
+    /// Exact content type, or a `type/*` wildcard.
+    pub content_type: Option<String>,
+    /// Unix timestamps; every range bound is inclusive.
+    pub min_created_at: Option<i64>,
+    pub max_created_at: Option<i64>,
+    pub min_updated_at: Option<i64>,
+    pub max_updated_at: Option<i64>,
+    pub min_bytes: Option<i64>,
+    pub max_bytes: Option<i64>,
+    /// `created_at` (default), `bytes` or `filename`.
+    pub sort: Option<String>,
+    pub include_total: Option<bool>,
     pub limit: Option<i64>,
     pub order: Option<String>,
     pub before: Option<String>,
@@ -0,0 +1,474 @@
+use crate::error::AppError;
+use crate::models::PaginationResponse;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+const DEFAULT_LIMIT: i64 = 10;
+const MAX_LIMIT: i64 = 100;
+
+/// A position in a listing: the sort key of a row, ending with its `oid` so
+/// that every position is unique.
+pub trait CursorKey: Clone {
+    fn to_bytes(&self) -> Vec<u8>;
+}
+
+impl CursorKey for i64 {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+}
+
+/// Encodes a position in a listing. Cursors are opaque to clients; they wrap
+/// the sort key of the row they point at, so resolving one needs no lookup
+/// and it stays valid after that row is deleted.
+pub fn encode_cursor<K: CursorKey>(key: K) -> String {
+    URL_SAFE_NO_PAD.encode(key.to_bytes())
+}
+
+/// Reads an `oid` back from the bytes of a cursor.
+pub fn decode_oid(bytes: &[u8]) -> Option<i64> {
+    <[u8; 8]>::try_from(bytes).ok().map(i64::from_be_bytes)
+}
+
+fn decode_cursor<K>(
+    param: &'static str,
+    cursor: &str,
+    decode: impl Fn(&[u8]) -> Option<K>,
+) -> Result<K, AppError> {
+    URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|bytes| decode(&bytes))
+        .ok_or_else(|| AppError::invalid_param(param, format!("Invalid {} cursor", param)))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+/// The `limit`, `order`, `after` and `before` parameters of a listing.
+///
+/// `after` and `before` are relative to `order`: with the default `desc`,
+/// `after` pages towards older rows. Both may be combined to list what lies
+/// between two cursors.
+#[derive(Debug)]
+pub struct PageParams<K = i64> {
+    pub limit: i64,
+    pub order: Order,
+    after: Option<K>,
+    before: Option<K>,
+}
+
+impl PageParams {
+    pub fn parse(
+        limit: Option<i64>,
+        order: Option<&str>,
+        after: Option<&str>,
+        before: Option<&str>,
+    ) -> Result<Self, AppError> {
+        Self::parse_with(limit, order, after, before, decode_oid)
+    }
+}
+
+impl<K: CursorKey> PageParams<K> {
+    /// Like [`PageParams::parse`], for listings whose cursors hold more than
+    /// an `oid`. `decode` rejects cursors minted for a different sort.
+    pub fn parse_with(
+        limit: Option<i64>,
+        order: Option<&str>,
+        after: Option<&str>,
+        before: Option<&str>,
+        decode: impl Fn(&[u8]) -> Option<K>,
+    ) -> Result<Self, AppError> {
+        let order = match order {
+            None | Some("desc") => Order::Desc,
+            Some("asc") => Order::Asc,
+            Some(_) => {
+                return Err(AppError::invalid_param(
+                    "order",
+                    "order must be asc or desc",
+                ))
+            }
+        };
+
+        Ok(Self {
+            limit: limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+            order,
+            after: after
+                .map(|c| decode_cursor("after", c, &decode))
+                .transpose()?,
+            before: before
+                .map(|c| decode_cursor("before", c, &decode))
+                .transpose()?,
+        })
+    }
+
+    /// The keys that come after `key` in the listing's order.
+    fn past(&self, key: K) -> KeyRange<K> {
+        match self.order {
+            Order::Desc => KeyRange::below(key),
+            Order::Asc => KeyRange::above(key),
+        }
+    }
+
+    /// The keys that come before `key` in the listing's order.
+    fn ahead_of(&self, key: K) -> KeyRange<K> {
+        match self.order {
+            Order::Desc => KeyRange::above(key),
+            Order::Asc => KeyRange::below(key),
+        }
+    }
+}
+
+/// One end of a [`KeyRange`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bound<K> {
+    pub key: K,
+    pub inclusive: bool,
+}
+
+/// Bounds on the sort key for a page query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyRange<K> {
+    pub gt: Option<Bound<K>>,
+    pub lt: Option<Bound<K>>,
+}
+
+impl<K> Default for KeyRange<K> {
+    fn default() -> Self {
+        Self { gt: None, lt: None }
+    }
+}
+
+impl<K> KeyRange<K> {
+    fn above(key: K) -> Self {
+        Self {
+            gt: Some(Bound {
+                key,
+                inclusive: false,
+            }),
+            lt: None,
+        }
+    }
+
+    fn below(key: K) -> Self {
+        Self {
+            gt: None,
+            lt: Some(Bound {
+                key,
+                inclusive: false,
+            }),
+        }
+    }
+
+    /// Intersects two ranges. `after` and `before` always bound opposite
+    /// sides, so no side is ever set on both.
+    fn and(self, other: Self) -> Self {
+        Self {
+            gt: self.gt.or(other.gt),
+            lt: self.lt.or(other.lt),
+        }
+    }
+
+    /// Widens the range so that it also covers its bound.
+    fn including_bound(self) -> Self {
+        let include = |bound: Bound<K>| Bound {
+            inclusive: true,
+            ..bound
+        };
+        Self {
+            gt: self.gt.map(include),
+            lt: self.lt.map(include),
+        }
+    }
+}
+
+impl<K: Ord> KeyRange<K> {
+    /// Whether `key` lies within the range, for listings that are not
+    /// backed by a query.
+    pub fn contains(&self, key: &K) -> bool {
+        let above = self.gt.as_ref().is_none_or(|b| match b.inclusive {
+            true => *key >= b.key,
+            false => *key > b.key,
+        });
+        let below = self.lt.as_ref().is_none_or(|b| match b.inclusive {
+            true => *key <= b.key,
+            false => *key < b.key,
+        });
+        above && below
+    }
+}
+
+impl KeyRange<i64> {
+    fn oids(self) -> OidRange {
+        OidRange {
+            gt: self.gt.map(|b| {
+                if b.inclusive {
+                    b.key.saturating_sub(1)
+                } else {
+                    b.key
+                }
+            }),
+            lt: self.lt.map(|b| {
+                if b.inclusive {
+                    b.key.saturating_add(1)
+                } else {
+                    b.key
+                }
+            }),
+        }
+    }
+}
+
+/// Exclusive bounds on `oid` for a page query.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OidRange {
+    pub gt: Option<i64>,
+    pub lt: Option<i64>,
+}
+
+impl OidRange {
+    pub fn contains(&self, oid: i64) -> bool {
+        self.gt.is_none_or(|gt| oid > gt) && self.lt.is_none_or(|lt| oid < lt)
+    }
+}
+
+/// Loads one page of a listing ordered by `oid`.
+///
+/// `load(range, descending, limit)` runs the listing's query restricted to
+/// `range`, sorted by `oid` in the given direction. It is called once for the
+/// page itself and at most twice more, with a limit of 1, to find out whether
+/// rows exist on either side of the page that the page query cannot see.
+pub fn paginate<T>(
+    params: &PageParams,
+    oid: impl Fn(&T) -> i64,
+    mut load: impl FnMut(OidRange, bool, i64) -> Result<Vec<T>, AppError>,
+) -> Result<(Vec<T>, PaginationResponse), AppError> {
+    paginate_by(params, oid, |range, descending, limit| {
+        load(range.oids(), descending, limit)
+    })
+}
+
+/// Loads one page of a listing ordered by an arbitrary key, like
+/// [`paginate`]. `load` receives bounds on that key instead of on `oid`.
+pub fn paginate_by<T, K: CursorKey>(
+    params: &PageParams<K>,
+    key: impl Fn(&T) -> K,
+    mut load: impl FnMut(KeyRange<K>, bool, i64) -> Result<Vec<T>, AppError>,
+) -> Result<(Vec<T>, PaginationResponse), AppError> {
+    let mut range = KeyRange::default();
+    if let Some(after) = &params.after {
+        range = range.and(params.past(after.clone()));
+    }
+    if let Some(before) = &params.before {
+        range = range.and(params.ahead_of(before.clone()));
+    }
+
+    // With only `before`, walk backwards from it and flip the page afterwards.
+    let backwards = params.after.is_none() && params.before.is_some();
+    let descending = (params.order == Order::Desc) != backwards;
+
+    let mut items = load(range, descending, params.limit + 1)?;
+    let overflow = items.len() as i64 > params.limit;
+    items.truncate(params.limit as usize);
+    if backwards {
+        items.reverse();
+    }
+
+    let mut exists = |range: KeyRange<K>| -> Result<bool, AppError> {
+        Ok(!load(range, descending, 1)?.is_empty())
+    };
+
+    let has_more_before = if backwards {
+        overflow
+    } else if let Some(after) = &params.after {
+        // Everything up to and including the `after` cursor lies before the page.
+        exists(match items.first() {
+            Some(first) => params.ahead_of(key(first)),
+            None => params.ahead_of(after.clone()).including_bound(),
+        })?
+    } else {
+        false
+    };
+
+    let has_more_after = match &params.before {
+        // The page query stopped at `before`, so it cannot tell what lies beyond.
+        Some(before) if backwards || !overflow => exists(match items.last() {
+            Some(last) => params.past(key(last)),
+            None => params.past(before.clone()).including_bound(),
+        })?,
+        _ => overflow,
+    };
+
+    let pagination = PaginationResponse {
+        has_more_before,
+        has_more_after,
+        prev_cursor: items
+            .first()
+            .filter(|_| has_more_before)
+            .map(|item| encode_cursor(key(item))),
+        next_cursor: items
+            .last()
+            .filter(|_| has_more_after)
+            .map(|item| encode_cursor(key(item))),
+    };
+
+    Ok((items, pagination))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `paginate` over the oids 1..=10.
+    fn page(
+        order: Option<&str>,
+        limit: i64,
+        after: Option<i64>,
+        before: Option<i64>,
+    ) -> (Vec<i64>, PaginationResponse) {
+        let after = after.map(encode_cursor);
+        let before = before.map(encode_cursor);
+        let params =
+            PageParams::parse(Some(limit), order, after.as_deref(), before.as_deref()).unwrap();
+
+        paginate(
+            &params,
+            |oid| *oid,
+            |range, descending, limit| {
+                let mut oids: Vec<i64> = (1..=10).filter(|oid| range.contains(*oid)).collect();
+                if descending {
+                    oids.reverse();
+                }
+                oids.truncate(limit as usize);
+                Ok(oids)
+            },
+        )
+        .unwrap()
+    }
+
+    fn flags(pagination: &PaginationResponse) -> (bool, bool) {
+        (pagination.has_more_before, pagination.has_more_after)
+    }
+
+    #[test]
+    fn test_cursor_roundtrip() {
+        let cursor = encode_cursor(7_300_000_000_000_000_000);
+        assert_eq!(
+            decode_cursor("after", &cursor, decode_oid).unwrap(),
+            7_300_000_000_000_000_000
+        );
+
+        let err = decode_cursor("after", "file_missing", decode_oid).unwrap_err();
+        assert_eq!(err.param(), Some("after"));
+        assert!(decode_cursor("before", "!!", decode_oid).is_err());
+    }
+
+    #[test]
+    fn test_invalid_order() {
+        let err = PageParams::parse(None, Some("sideways"), None, None).unwrap_err();
+        assert_eq!(err.param(), Some("order"));
+    }
+
+    #[test]
+    fn test_first_page() {
+        let (items, pagination) = page(None, 3, None, None);
+        assert_eq!(items, [10, 9, 8]);
+        assert_eq!(flags(&pagination), (false, true));
+        assert_eq!(pagination.next_cursor, Some(encode_cursor(8)));
+        assert_eq!(pagination.prev_cursor, None);
+
+        let (items, pagination) = page(Some("asc"), 3, None, None);
+        assert_eq!(items, [1, 2, 3]);
+        assert_eq!(flags(&pagination), (false, true));
+    }
+
+    #[test]
+    fn test_paging_forward_and_back() {
+        let (items, pagination) = page(None, 3, Some(8), None);
+        assert_eq!(items, [7, 6, 5]);
+        assert_eq!(flags(&pagination), (true, true));
+        assert_eq!(pagination.prev_cursor, Some(encode_cursor(7)));
+
+        let (items, pagination) = page(None, 3, None, Some(7));
+        assert_eq!(items, [10, 9, 8]);
+        assert_eq!(flags(&pagination), (false, true));
+
+        let (items, pagination) = page(None, 3, Some(3), None);
+        assert_eq!(items, [2, 1]);
+        assert_eq!(flags(&pagination), (true, false));
+        assert_eq!(pagination.next_cursor, None);
+
+        let (items, pagination) = page(Some("asc"), 3, None, Some(3));
+        assert_eq!(items, [1, 2]);
+        assert_eq!(flags(&pagination), (false, true));
+    }
+
+    #[test]
+    fn test_between_cursors() {
+        let (items, pagination) = page(None, 10, Some(8), Some(4));
+        assert_eq!(items, [7, 6, 5]);
+        assert_eq!(flags(&pagination), (true, true));
+
+        let (items, pagination) = page(None, 2, Some(8), Some(4));
+        assert_eq!(items, [7, 6]);
+        assert_eq!(flags(&pagination), (true, true));
+
+        // Nothing lies between neighbours, but rows exist on both sides.
+        let (items, pagination) = page(None, 10, Some(5), Some(4));
+        assert!(items.is_empty());
+        assert_eq!(flags(&pagination), (true, true));
+        assert_eq!(pagination.next_cursor, None);
+    }
+
+    /// A key sorting by a value that repeats, then by `oid`.
+    impl CursorKey for (i64, i64) {
+        fn to_bytes(&self) -> Vec<u8> {
+            [self.0.to_be_bytes(), self.1.to_be_bytes()].concat()
+        }
+    }
+
+    #[test]
+    fn test_paginate_by_composite_key() {
+        let rows: Vec<(i64, i64)> = vec![(1, 1), (2, 2), (2, 3), (2, 4), (3, 5)];
+        let page = |after: Option<(i64, i64)>| {
+            let params = PageParams {
+                limit: 2,
+                order: Order::Asc,
+                after,
+                before: None,
+            };
+            paginate_by(
+                &params,
+                |row: &(i64, i64)| *row,
+                |range, descending, limit| {
+                    let mut page: Vec<(i64, i64)> = rows
+                        .iter()
+                        .copied()
+                        .filter(|row| range.contains(row))
+                        .collect();
+                    if descending {
+                        page.reverse();
+                    }
+                    page.truncate(limit as usize);
+                    Ok(page)
+                },
+            )
+            .unwrap()
+        };
+
+        let (items, pagination) = page(None);
+        assert_eq!(items, [(1, 1), (2, 2)]);
+        assert_eq!(flags(&pagination), (false, true));
+
+        // Rows tied on the value are split across pages by their oid.
+        let (items, pagination) = page(Some((2, 2)));
+        assert_eq!(items, [(2, 3), (2, 4)]);
+        assert_eq!(flags(&pagination), (true, true));
+
+        let (items, pagination) = page(Some((2, 4)));
+        assert_eq!(items, [(3, 5)]);
+        assert_eq!(flags(&pagination), (true, false));
+    }
+}
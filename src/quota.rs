@@ -0,0 +1,277 @@
+use crate::config::Config;
+use crate::error::AppError;
+use crate::models::Tenant;
+use crate::schema::tenants;
+use axum::http::StatusCode;
+use chrono::Utc;
+use diesel::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaKind {
+    FileSize,
+    TotalBytes,
+    FileCount,
+}
+
+impl QuotaKind {
+    pub fn code(&self) -> &'static str {
+        match self {
+            QuotaKind::FileSize => "max_file_size_bytes",
+            QuotaKind::TotalBytes => "max_total_bytes",
+            QuotaKind::FileCount => "max_file_count",
+        }
+    }
+
+    /// Size limits are about the payload; the file count is a policy limit.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            QuotaKind::FileSize | QuotaKind::TotalBytes => StatusCode::PAYLOAD_TOO_LARGE,
+            QuotaKind::FileCount => StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaExceeded {
+    pub kind: QuotaKind,
+    pub limit: i64,
+    pub current: i64,
+    pub requested: i64,
+}
+
+impl QuotaExceeded {
+    pub fn message(&self) -> String {
+        match self.kind {
+            QuotaKind::FileSize => format!("File size exceeds maximum of {} bytes", self.limit),
+            QuotaKind::TotalBytes => format!(
+                "Upload would exceed the tenant storage quota of {} bytes",
+                self.limit
+            ),
+            QuotaKind::FileCount => {
+                format!("Tenant has reached its limit of {} files", self.limit)
+            }
+        }
+    }
+}
+
+/// The limits that apply to a tenant: its own overrides, else the config defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TenantQuota {
+    pub max_total_bytes: Option<i64>,
+    pub max_file_count: Option<i64>,
+    pub max_file_size_bytes: i64,
+}
+
+impl TenantQuota {
+    pub fn for_tenant(tenant: &Tenant, config: &Config) -> Self {
+        Self {
+            max_total_bytes: tenant.max_total_bytes.or(config.tenant_max_total_bytes),
+            max_file_count: tenant.max_file_count.or(config.tenant_max_file_count),
+            max_file_size_bytes: tenant
+                .max_file_size_bytes
+                .unwrap_or(config.max_file_size_bytes),
+        }
+    }
+
+    /// Fails fast, before any bytes are read, when the tenant cannot take another file.
+    pub fn check_file_count(&self, tenant: &Tenant) -> Result<(), QuotaExceeded> {
+        match self.max_file_count {
+            Some(limit) if tenant.file_count >= limit => Err(QuotaExceeded {
+                kind: QuotaKind::FileCount,
+                limit,
+                current: tenant.file_count,
+                requested: 1,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// The most bytes a single upload may stream, and the limit that caps it.
+    pub fn upload_limit(&self, tenant: &Tenant) -> (i64, QuotaKind) {
+        match self.max_total_bytes {
+            Some(limit) if limit - tenant.total_files_bytes < self.max_file_size_bytes => (
+                (limit - tenant.total_files_bytes).max(0),
+                QuotaKind::TotalBytes,
+            ),
+            _ => (self.max_file_size_bytes, QuotaKind::FileSize),
+        }
+    }
+
+    pub fn exceeded(&self, tenant: &Tenant, kind: QuotaKind, requested: i64) -> QuotaExceeded {
+        let (limit, current) = match kind {
+            QuotaKind::FileSize => (self.max_file_size_bytes, 0),
+            QuotaKind::TotalBytes => (
+                self.max_total_bytes.unwrap_or(i64::MAX),
+                tenant.total_files_bytes,
+            ),
+            QuotaKind::FileCount => (self.max_file_count.unwrap_or(i64::MAX), tenant.file_count),
+        };
+
+        QuotaExceeded {
+            kind,
+            limit,
+            current,
+            requested,
+        }
+    }
+
+    /// Checks that a new file of `bytes` fits beside the tenant's current files.
+    pub fn check_fits(&self, tenant: &Tenant, bytes: i64) -> Result<(), QuotaExceeded> {
+        if self
+            .max_file_count
+            .is_some_and(|limit| tenant.file_count >= limit)
+        {
+            return Err(self.exceeded(tenant, QuotaKind::FileCount, 1));
+        }
+        if self
+            .max_total_bytes
+            .is_some_and(|limit| tenant.total_files_bytes > limit.saturating_sub(bytes))
+        {
+            return Err(self.exceeded(tenant, QuotaKind::TotalBytes, bytes));
+        }
+        Ok(())
+    }
+
+    /// Charges a new file to the tenant's counters if it still fits.
+    ///
+    /// The limits are checked in the same `UPDATE` that bumps the counters, so
+    /// concurrent uploads cannot both squeeze into the last bit of quota.
+    pub fn reserve(
+        &self,
+        conn: &mut PgConnection,
+        tenant_oid: i64,
+        bytes: i64,
+    ) -> Result<(), AppError> {
+        let max_total_bytes = self.max_total_bytes.unwrap_or(i64::MAX);
+        let max_file_count = self.max_file_count.unwrap_or(i64::MAX);
+
+        let updated: Option<Tenant> = diesel::update(
+            tenants::table
+                .find(tenant_oid)
+                .filter(tenants::total_files_bytes.le(max_total_bytes.saturating_sub(bytes)))
+                .filter(tenants::file_count.lt(max_file_count)),
+        )
+        .set((
+            tenants::total_files_bytes.eq(tenants::total_files_bytes + bytes),
+            tenants::file_count.eq(tenants::file_count + 1),
+            tenants::updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .get_result(conn)
+        .optional()
+        .map_err(AppError::database)?;
+
+        if updated.is_some() {
+            return Ok(());
+        }
+
+        let tenant: Tenant = tenants::table
+            .find(tenant_oid)
+            .first(conn)
+            .map_err(AppError::database)?;
+
+        // The counters may have moved since the update; it still did not fit.
+        let exceeded = self
+            .check_fits(&tenant, bytes)
+            .err()
+            .unwrap_or_else(|| self.exceeded(&tenant, QuotaKind::TotalBytes, bytes));
+
+        Err(AppError::QuotaExceeded(exceeded))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::create_test_config;
+
+    fn tenant(total_files_bytes: i64, file_count: i64) -> Tenant {
+        let now = Utc::now().naive_utc();
+        Tenant {
+            oid: 1,
+            id: "tenant".to_string(),
+            name: "tenant".to_string(),
+            created_at: now,
+            updated_at: now,
+            total_files_bytes,
+            file_count,
+            max_total_bytes: None,
+            max_file_count: None,
+            max_file_size_bytes: None,
+        }
+    }
+
+    #[test]
+    fn test_tenant_overrides_config_defaults() {
+        let mut config = create_test_config();
+        config.tenant_max_total_bytes = Some(1000);
+        config.tenant_max_file_count = Some(10);
+
+        let mut tenant = tenant(0, 0);
+        tenant.max_file_count = Some(3);
+        tenant.max_file_size_bytes = Some(50);
+
+        assert_eq!(
+            TenantQuota::for_tenant(&tenant, &config),
+            TenantQuota {
+                max_total_bytes: Some(1000),
+                max_file_count: Some(3),
+                max_file_size_bytes: 50,
+            }
+        );
+    }
+
+    #[test]
+    fn test_upload_limit_uses_remaining_quota() {
+        let quota = TenantQuota {
+            max_total_bytes: Some(1000),
+            max_file_count: None,
+            max_file_size_bytes: 300,
+        };
+
+        assert_eq!(
+            quota.upload_limit(&tenant(100, 1)),
+            (300, QuotaKind::FileSize)
+        );
+        assert_eq!(
+            quota.upload_limit(&tenant(800, 1)),
+            (200, QuotaKind::TotalBytes)
+        );
+        assert_eq!(
+            quota.upload_limit(&tenant(1200, 1)),
+            (0, QuotaKind::TotalBytes)
+        );
+    }
+
+    #[test]
+    fn test_check_file_count() {
+        let quota = TenantQuota {
+            max_total_bytes: None,
+            max_file_count: Some(2),
+            max_file_size_bytes: 300,
+        };
+
+        assert!(quota.check_file_count(&tenant(0, 1)).is_ok());
+        assert_eq!(
+            quota.check_file_count(&tenant(0, 2)).unwrap_err().kind,
+            QuotaKind::FileCount
+        );
+    }
+
+    #[test]
+    fn test_check_fits() {
+        let quota = TenantQuota {
+            max_total_bytes: Some(1000),
+            max_file_count: Some(2),
+            max_file_size_bytes: 300,
+        };
+
+        assert!(quota.check_fits(&tenant(700, 1), 300).is_ok());
+        let exceeded = quota.check_fits(&tenant(701, 1), 300).unwrap_err();
+        assert_eq!(exceeded.kind, QuotaKind::TotalBytes);
+        assert_eq!((exceeded.current, exceeded.requested), (701, 300));
+        assert_eq!(
+            quota.check_fits(&tenant(0, 2), 1).unwrap_err().kind,
+            QuotaKind::FileCount
+        );
+    }
+}
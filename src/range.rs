@@ -0,0 +1,211 @@
+use chrono::NaiveDateTime;
+
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// An inclusive byte range within an object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn length(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    pub fn header_value(&self) -> String {
+        format!("bytes={}-{}", self.start, self.end)
+    }
+
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    Full,
+    Partial(ByteRange),
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header against an object of `total` bytes.
+///
+/// Only a single `bytes` range is supported. Malformed headers and multi-range
+/// requests are ignored, which per RFC 9110 means the full object is served.
+pub fn parse_range(header: &str, total: u64) -> RangeRequest {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+
+    if spec.contains(',') || total == 0 {
+        return RangeRequest::Full;
+    }
+
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+
+    let (start, end) = (start.trim(), end.trim());
+
+    if start.is_empty() {
+        let Ok(suffix) = end.parse::<u64>() else {
+            return RangeRequest::Full;
+        };
+        if suffix == 0 {
+            return RangeRequest::Unsatisfiable;
+        }
+        return RangeRequest::Partial(ByteRange {
+            start: total.saturating_sub(suffix),
+            end: total - 1,
+        });
+    }
+
+    let Ok(start) = start.parse::<u64>() else {
+        return RangeRequest::Full;
+    };
+
+    let end = if end.is_empty() {
+        total - 1
+    } else {
+        match end.parse::<u64>() {
+            Ok(end) if end >= start => end.min(total - 1),
+            _ => return RangeRequest::Full,
+        }
+    };
+
+    if start >= total {
+        return RangeRequest::Unsatisfiable;
+    }
+
+    RangeRequest::Partial(ByteRange { start, end })
+}
+
+/// Checks an `If-Range` precondition against the object's validators.
+///
+/// A range is only honoured when the validator still matches; otherwise the
+/// full, current representation is served.
+pub fn if_range_matches(if_range: &str, etag: Option<&str>, last_modified: NaiveDateTime) -> bool {
+    let if_range = if_range.trim();
+
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        // Weak validators never match for ranges.
+        return etag.is_some_and(|etag| !if_range.starts_with("W/") && if_range == etag);
+    }
+
+    parse_http_date(if_range).is_some_and(|date| date == truncate_to_seconds(last_modified))
+}
+
+pub fn format_http_date(datetime: NaiveDateTime) -> String {
+    datetime.format(HTTP_DATE_FORMAT).to_string()
+}
+
+fn parse_http_date(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value, HTTP_DATE_FORMAT).ok()
+}
+
+fn truncate_to_seconds(datetime: NaiveDateTime) -> NaiveDateTime {
+    chrono::DateTime::from_timestamp(datetime.and_utc().timestamp(), 0)
+        .map(|dt| dt.naive_utc())
+        .unwrap_or(datetime)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_closed_range() {
+        assert_eq!(
+            parse_range("bytes=0-99", 1000),
+            RangeRequest::Partial(ByteRange { start: 0, end: 99 })
+        );
+    }
+
+    #[test]
+    fn test_parse_open_ended_range() {
+        assert_eq!(
+            parse_range("bytes=500-", 1000),
+            RangeRequest::Partial(ByteRange {
+                start: 500,
+                end: 999
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_suffix_range() {
+        assert_eq!(
+            parse_range("bytes=-100", 1000),
+            RangeRequest::Partial(ByteRange {
+                start: 900,
+                end: 999
+            })
+        );
+        assert_eq!(
+            parse_range("bytes=-5000", 1000),
+            RangeRequest::Partial(ByteRange { start: 0, end: 999 })
+        );
+    }
+
+    #[test]
+    fn test_parse_range_end_is_clamped() {
+        assert_eq!(
+            parse_range("bytes=900-5000", 1000),
+            RangeRequest::Partial(ByteRange {
+                start: 900,
+                end: 999
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_unsatisfiable_range() {
+        assert_eq!(
+            parse_range("bytes=1000-", 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(parse_range("bytes=-0", 1000), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn test_parse_ignored_ranges() {
+        assert_eq!(parse_range("items=0-1", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=0-1,5-9", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=9-1", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=abc", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=0-1", 0), RangeRequest::Full);
+    }
+
+    #[test]
+    fn test_if_range_with_date() {
+        let last_modified =
+            NaiveDateTime::parse_from_str("2024-01-02 03:04:05", "%Y-%m-%d %H:%M:%S").unwrap();
+
+        assert!(if_range_matches(
+            "Tue, 02 Jan 2024 03:04:05 GMT",
+            None,
+            last_modified
+        ));
+        assert!(!if_range_matches(
+            "Tue, 02 Jan 2024 03:04:06 GMT",
+            None,
+            last_modified
+        ));
+    }
+
+    #[test]
+    fn test_if_range_with_etag() {
+        let last_modified = NaiveDateTime::default();
+
+        assert!(if_range_matches("\"abc\"", Some("\"abc\""), last_modified));
+        assert!(!if_range_matches("\"abc\"", Some("\"def\""), last_modified));
+        assert!(!if_range_matches(
+            "W/\"abc\"",
+            Some("\"abc\""),
+            last_modified
+        ));
+        assert!(!if_range_matches("\"abc\"", None, last_modified));
+    }
+}
@@ -0,0 +1,284 @@
+use crate::app_state::AppState;
+use crate::deletions;
+use crate::error::AppError;
+use crate::models::{CounterDriftReport, OrphanObjectReport, ReconciliationResponse, Tenant};
+use chrono::{Duration, Utc};
+use std::collections::HashSet;
+use std::str::FromStr;
+use tokio_util::sync::CancellationToken;
+
+/// How many outstanding deletions one pass retries.
+const PENDING_DELETION_BATCH: i64 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReconcileMode {
+    /// Report what is out of sync without changing anything.
+    DryRun,
+    /// Delete orphaned objects, rewrite drifted counters and retry pending deletions.
+    Fix,
+}
+
+impl ReconcileMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReconcileMode::DryRun => "dry_run",
+            ReconcileMode::Fix => "fix",
+        }
+    }
+}
+
+impl FromStr for ReconcileMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dry_run" => Ok(ReconcileMode::DryRun),
+            "fix" => Ok(ReconcileMode::Fix),
+            other => Err(format!("Unknown reconcile mode: {}", other)),
+        }
+    }
+}
+
+/// Compares storage and the database for every tenant, or just one.
+///
+/// Objects under a tenant's prefix that no file, blob or pending deletion
+/// refers to are orphans. Only orphans older than the configured grace period
+/// are deleted, since younger ones may belong to uploads still in flight.
+/// Problems with individual tenants are collected in the report rather than
+/// aborting the whole pass.
+pub async fn run(
+    state: &AppState,
+    mode: ReconcileMode,
+    tenant_id: Option<&str>,
+) -> Result<ReconciliationResponse, AppError> {
+    let tenants: Vec<Tenant> = match tenant_id {
+        Some(tenant_id) => vec![state
+            .repos
+            .tenants
+            .find_by_id(tenant_id)
+            .await?
+            .ok_or(AppError::NotFound)?],
+        None => state.repos.tenants.all().await?,
+    };
+
+    let mut report = ReconciliationResponse {
+        object: "reconciliation".to_string(),
+        mode: mode.as_str().to_string(),
+        tenants_checked: 0,
+        objects_scanned: 0,
+        orphans: Vec::new(),
+        missing_objects: Vec::new(),
+        counter_drift: Vec::new(),
+        pending_deletions: 0,
+        pending_deletions_cleared: 0,
+        errors: Vec::new(),
+    };
+
+    if mode == ReconcileMode::Fix {
+        report.pending_deletions_cleared =
+            deletions::retry_pending(state, PENDING_DELETION_BATCH).await? as i64;
+    }
+
+    let cutoff = Utc::now() - Duration::seconds(state.config.reconcile_orphan_grace_secs);
+
+    for tenant in &tenants {
+        if let Err(e) = reconcile_objects(state, tenant, mode, cutoff, &mut report).await {
+            report
+                .errors
+                .push(format!("Objects of tenant {}: {}", tenant.id, e));
+        }
+
+        match reconcile_counters(state, tenant.oid, mode).await {
+            Ok(Some(drift)) => report.counter_drift.push(drift),
+            Ok(None) => {}
+            Err(e) => report
+                .errors
+                .push(format!("Counters of tenant {}: {}", tenant.id, e)),
+        }
+
+        report.tenants_checked += 1;
+    }
+
+    report.pending_deletions = state.repos.deletions.count().await?;
+
+    Ok(report)
+}
+
+async fn reconcile_objects(
+    state: &AppState,
+    tenant: &Tenant,
+    mode: ReconcileMode,
+    cutoff: chrono::DateTime<Utc>,
+    report: &mut ReconciliationResponse,
+) -> Result<(), String> {
+    let prefix = format!("{}/", tenant.id);
+
+    // List before reading the database: an object that shows up here either
+    // has its row committed by the time we look, or is recent enough to skip.
+    let mut objects = state
+        .storage_client
+        .list(&prefix)
+        .await
+        .map_err(|e| e.to_string())?;
+    // The prefix also matches the keys of any tenant whose id extends this one.
+    objects.retain(|object| owned_by(&object.key, &tenant.id));
+    report.objects_scanned += objects.len() as i64;
+
+    let (file_keys, known_keys) = referenced_keys(state, tenant.oid, &prefix)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let listed: HashSet<&str> = objects.iter().map(|o| o.key.as_str()).collect();
+
+    for key in &file_keys {
+        if key.starts_with(&prefix) && !listed.contains(key.as_str()) {
+            report.missing_objects.push(key.clone());
+        }
+    }
+
+    for object in objects.iter().filter(|o| !known_keys.contains(&o.key)) {
+        let recent = object.last_modified.is_none_or(|t| t > cutoff);
+
+        let deleted = if mode == ReconcileMode::Fix && !recent {
+            match state.storage_client.delete(&object.key).await {
+                Ok(()) => true,
+                Err(e) => {
+                    report
+                        .errors
+                        .push(format!("Deleting orphan {}: {}", object.key, e));
+                    false
+                }
+            }
+        } else {
+            false
+        };
+
+        report.orphans.push(OrphanObjectReport {
+            tenant_id: tenant.id.clone(),
+            storage_key: object.key.clone(),
+            bytes: object.size,
+            last_modified: object.last_modified.map(|t| t.timestamp()),
+            recent,
+            deleted,
+        });
+    }
+
+    Ok(())
+}
+
+/// Whether a key is one of the tenant's: `<tenant id>/<name>`, with no further
+/// `/` in the name.
+fn owned_by(key: &str, tenant_id: &str) -> bool {
+    key.strip_prefix(tenant_id)
+        .and_then(|rest| rest.strip_prefix('/'))
+        .is_some_and(|name| !name.is_empty() && !name.contains('/'))
+}
+
+/// Returns the tenant's file keys, and every key the database still accounts for.
+async fn referenced_keys(
+    state: &AppState,
+    tenant_oid: i64,
+    prefix: &str,
+) -> Result<(HashSet<String>, HashSet<String>), AppError> {
+    let keys = state.repos.files.storage_keys(tenant_oid).await?;
+    let file_keys: HashSet<String> = keys.files.into_iter().collect();
+
+    // Already queued objects are tracked, so they are not reported twice.
+    let pending_keys = state.repos.deletions.keys_with_prefix(prefix).await?;
+
+    let mut known_keys = file_keys.clone();
+    known_keys.extend(keys.blobs);
+    known_keys.extend(pending_keys);
+
+    Ok((file_keys, known_keys))
+}
+
+/// Recomputes a tenant's counters from its files.
+async fn reconcile_counters(
+    state: &AppState,
+    tenant_oid: i64,
+    mode: ReconcileMode,
+) -> Result<Option<CounterDriftReport>, String> {
+    state
+        .repos
+        .tenants
+        .recount(tenant_oid, mode == ReconcileMode::Fix)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Runs reconciliation every `interval` until `shutdown` is cancelled.
+///
+/// Pending deletions are always retried. Orphans and counters are only
+/// rewritten when `RECONCILE_FIX` is enabled; otherwise they are just logged.
+pub async fn run_periodically(
+    state: AppState,
+    interval: std::time::Duration,
+    shutdown: CancellationToken,
+) {
+    let mode = if state.config.reconcile_fix {
+        ReconcileMode::Fix
+    } else {
+        ReconcileMode::DryRun
+    };
+
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // The first tick completes immediately; wait a full interval after startup.
+    ticker.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => {
+                tracing::info!("Reconciliation task stopped");
+                return;
+            }
+            _ = ticker.tick() => {}
+        }
+
+        if mode == ReconcileMode::DryRun {
+            if let Err(e) = deletions::retry_pending(&state, PENDING_DELETION_BATCH).await {
+                tracing::warn!("Failed to retry pending deletions: {:?}", e);
+            }
+        }
+
+        match run(&state, mode, None).await {
+            Ok(report) => {
+                tracing::info!(
+                    "Reconciliation ({}) checked {} tenants: {} orphans, {} missing objects, {} drifted counters, {} pending deletions",
+                    report.mode,
+                    report.tenants_checked,
+                    report.orphans.len(),
+                    report.missing_objects.len(),
+                    report.counter_drift.len(),
+                    report.pending_deletions,
+                );
+                for error in &report.errors {
+                    tracing::warn!("Reconciliation: {}", error);
+                }
+            }
+            Err(e) => tracing::warn!("Reconciliation failed: {:?}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode_parsing() {
+        assert_eq!("dry_run".parse(), Ok(ReconcileMode::DryRun));
+        assert_eq!("fix".parse(), Ok(ReconcileMode::Fix));
+        assert!("delete".parse::<ReconcileMode>().is_err());
+    }
+
+    #[test]
+    fn test_owned_by() {
+        assert!(owned_by("acme/file_1", "acme"));
+        assert!(!owned_by("acme/x/file_1", "acme"));
+        assert!(owned_by("acme/x/file_1", "acme/x"));
+        assert!(!owned_by("acme2/file_1", "acme"));
+        assert!(!owned_by("acme/", "acme"));
+    }
+}
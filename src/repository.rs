@@ -0,0 +1,334 @@
+//! Data access for the handlers and background jobs, behind traits so they
+//! can run against Postgres in production and an in-memory store in tests.
+
+pub mod memory;
+pub mod postgres;
+
+use crate::db::DbPool;
+use crate::error::AppError;
+use crate::file_listing::{FilePage, Listing};
+use crate::models::*;
+use crate::pagination::PageParams;
+use crate::quota::TenantQuota;
+use crate::snowflake::SnowflakeGeneratorWrapper;
+use axum::async_trait;
+use chrono::{Duration, NaiveDateTime, Utc};
+use std::sync::Arc;
+
+#[async_trait]
+pub trait FileRepository: Send + Sync {
+    /// Loads a file by id whether or not it is in the trash.
+    async fn find_by_id(&self, id: &str) -> Result<Option<FileRecord>, AppError>;
+
+    async fn find(&self, oid: i64) -> Result<Option<FileRecord>, AppError>;
+
+    /// Inserts the row as given. Uploads go through [`record_upload`](Self::record_upload)
+    /// instead, since they also charge the tenant's quota.
+    #[allow(dead_code)]
+    async fn create(&self, file: NewFile) -> Result<File, AppError>;
+
+    /// Charges an uploaded file to its tenant's quota, takes its blob
+    /// reference and inserts it, all or nothing. The returned file points at
+    /// the blob's storage key when its content was already stored.
+    async fn record_upload(&self, upload: NewUpload) -> Result<File, AppError>;
+
+    /// The tenant's blob for a content hash, if one is still referenced.
+    async fn find_blob(&self, tenant_oid: i64, sha256: &str) -> Result<Option<Blob>, AppError>;
+
+    /// Returns `None` when the file no longer exists.
+    async fn update(&self, oid: i64, update: UpdateFile) -> Result<Option<File>, AppError>;
+
+    /// Moves a file to the trash. Returns `None` when it is gone or already
+    /// trashed.
+    async fn trash(&self, oid: i64) -> Result<Option<File>, AppError>;
+
+    /// Takes a file out of the trash. Returns `None` when it is gone.
+    async fn restore(&self, oid: i64) -> Result<Option<File>, AppError>;
+
+    async fn set_sha256(&self, oid: i64, sha256: String) -> Result<(), AppError>;
+
+    async fn list(&self, listing: Listing) -> Result<FilePage, AppError>;
+
+    /// Deletes a file's row and links, drops its blob reference and
+    /// uncharges its tenant, all or nothing. The object is recorded as a
+    /// pending deletion unless a blob still shares it. With `due`, the file
+    /// is only removed if it still is, since it may have been restored or had
+    /// its expiry moved. Returns `None` when there is nothing to remove.
+    async fn remove_permanently(
+        &self,
+        oid: i64,
+        due: Option<Due>,
+    ) -> Result<Option<RemovedFile>, AppError>;
+
+    /// The oldest files that are [`Due`], at most `limit` of them.
+    async fn find_due(&self, due: Due, limit: i64) -> Result<Vec<File>, AppError>;
+
+    /// The storage keys the tenant's files and blobs refer to.
+    async fn storage_keys(&self, tenant_oid: i64) -> Result<StorageKeys, AppError>;
+}
+
+/// Files past their `expires_at`, or in the trash since before `purge_before`.
+#[derive(Debug, Clone, Copy)]
+pub struct Due {
+    pub now: NaiveDateTime,
+    pub purge_before: NaiveDateTime,
+}
+
+impl Due {
+    pub fn includes(&self, file: &File) -> bool {
+        file.expires_at.is_some_and(|t| t <= self.now)
+            || file.deleted_at.is_some_and(|t| t <= self.purge_before)
+    }
+}
+
+/// A file that was deleted for good.
+pub struct RemovedFile {
+    pub file: File,
+    /// How many links went with it.
+    pub links: i64,
+    /// Its object, unless a blob still shares it. Hand it to
+    /// [`deletions::process`](crate::deletions::process).
+    pub scheduled: Vec<PendingDeletion>,
+}
+
+/// The storage keys a tenant's rows refer to.
+pub struct StorageKeys {
+    pub files: Vec<String>,
+    pub blobs: Vec<String>,
+}
+
+/// An upload whose content is in storage, ready to be recorded.
+pub struct NewUpload {
+    /// `storage_key` is where the content was uploaded, if it was.
+    pub file: NewFile,
+    pub quota: TenantQuota,
+    /// `None` when deduplication is off and the file owns its object.
+    pub blob: Option<UploadBlob>,
+}
+
+/// The blob an upload's content ends up in.
+pub enum UploadBlob {
+    /// The tenant already held the content, which was not uploaded again.
+    Reuse(Blob),
+    /// A new blob with this oid for the uploaded content, unless the tenant
+    /// already holds the same content, in which case the upload is redundant.
+    Share(i64),
+}
+
+#[async_trait]
+pub trait TenantRepository: Send + Sync {
+    async fn find(&self, oid: i64) -> Result<Option<Tenant>, AppError>;
+
+    async fn find_by_id(&self, id: &str) -> Result<Option<Tenant>, AppError>;
+
+    /// Fails with `Conflict` when the id is taken.
+    async fn create(&self, tenant: NewTenant) -> Result<Tenant, AppError>;
+
+    /// Inserts the tenant unless its id is taken, and returns whichever
+    /// tenant holds the id afterwards.
+    async fn create_or_get(&self, tenant: NewTenant) -> Result<Tenant, AppError>;
+
+    async fn update(&self, id: &str, update: UpdateTenant) -> Result<Option<Tenant>, AppError>;
+
+    async fn list(&self, page: PageParams) -> Result<(Vec<Tenant>, PaginationResponse), AppError>;
+
+    /// Every tenant, oldest first.
+    async fn all(&self) -> Result<Vec<Tenant>, AppError>;
+
+    /// Deletes a tenant with its files, blobs, links and API keys, recording
+    /// every object they held as a pending deletion in the same step. Returns
+    /// `None` when there is no such tenant.
+    async fn delete(&self, id: &str) -> Result<Option<(Tenant, Vec<PendingDeletion>)>, AppError>;
+
+    /// Compares a tenant's counters with its files, rewriting them when `fix`
+    /// is set. Returns `None` when they agree.
+    async fn recount(&self, oid: i64, fix: bool) -> Result<Option<CounterDriftReport>, AppError>;
+}
+
+/// Which links a listing covers. `active` is judged at `now`, as by
+/// [`LinkState::of`](crate::links::LinkState::of).
+pub struct LinkFilter {
+    pub file_oid: Option<i64>,
+    pub tenant_oid: Option<i64>,
+    pub active: Option<bool>,
+    pub now: NaiveDateTime,
+}
+
+#[async_trait]
+pub trait LinkRepository: Send + Sync {
+    /// Loads a link by id, together with the id of its file.
+    async fn find_by_id(&self, id: &str) -> Result<Option<(FileLink, String)>, AppError>;
+
+    async fn find_by_key(&self, key: &str) -> Result<Option<FileLink>, AppError>;
+
+    /// Fails with `Conflict` when the key is taken.
+    async fn create(&self, link: NewFileLink) -> Result<FileLink, AppError>;
+
+    async fn delete(&self, oid: i64) -> Result<(), AppError>;
+
+    /// Stamps `revoked_at` unless the link is already revoked, so the first
+    /// revocation is kept. Returns `None` when the link is gone.
+    async fn revoke(&self, oid: i64) -> Result<Option<FileLink>, AppError>;
+
+    /// Revokes the links of a file that are not revoked yet, returning how
+    /// many were.
+    async fn revoke_all(&self, file_oid: i64) -> Result<usize, AppError>;
+
+    /// Deletes up to `limit` of the links that expired before `cutoff`,
+    /// oldest first. Returns how many were selected and how many deleted.
+    async fn delete_expired(
+        &self,
+        cutoff: NaiveDateTime,
+        limit: i64,
+    ) -> Result<(usize, usize), AppError>;
+
+    async fn list(
+        &self,
+        filter: LinkFilter,
+        page: PageParams,
+    ) -> Result<(Vec<(FileLink, String)>, PaginationResponse), AppError>;
+
+    /// Counts a download and stamps `last_accessed_at`, unless the link has
+    /// reached `max_downloads`, in which case it returns `None`. Concurrent
+    /// calls must not both take the last download.
+    async fn record_download(&self, oid: i64) -> Result<Option<FileLink>, AppError>;
+
+    /// Gives back a download that was counted but could not be served.
+    async fn release_download(&self, oid: i64) -> Result<(), AppError>;
+
+    /// Clears the attempt count and any lock, once the right password is given.
+    async fn reset_failed_attempts(&self, oid: i64) -> Result<(), AppError>;
+
+    /// Counts a password attempt before it is checked, unless the link is
+    /// locked. The `max_attempts`th attempt resets the count and locks the
+    /// link for `lockout`; a limit of zero never locks. Concurrent attempts
+    /// are counted one at a time, so at most `max_attempts` get through
+    /// before the lock.
+    async fn reserve_password_attempt(
+        &self,
+        oid: i64,
+        max_attempts: i32,
+        lockout: Duration,
+    ) -> Result<PasswordAttempt, AppError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordAttempt {
+    /// The attempt is counted and the password may be checked.
+    Reserved,
+    /// The link refuses attempts until then.
+    Locked(NaiveDateTime),
+}
+
+#[async_trait]
+pub trait PurposeRepository: Send + Sync {
+    async fn find_by_slug(&self, slug: &str) -> Result<Option<Purpose>, AppError>;
+
+    /// Fails with `Conflict` when the slug is taken.
+    async fn create(&self, purpose: NewPurpose) -> Result<Purpose, AppError>;
+}
+
+#[async_trait]
+pub trait ApiKeyRepository: Send + Sync {
+    /// Loads an unrevoked key by the lookup part of its secret.
+    async fn find_by_lookup(&self, lookup: &str) -> Result<Option<ApiKey>, AppError>;
+
+    async fn create(&self, api_key: NewApiKey) -> Result<ApiKey, AppError>;
+
+    /// Stamps `revoked_at` unless the key is already revoked. Returns `None`
+    /// when there is no such key.
+    async fn revoke(&self, id: &str) -> Result<Option<ApiKey>, AppError>;
+
+    async fn mark_used(&self, oid: i64) -> Result<(), AppError>;
+}
+
+/// Storage objects that lost their last database row and still need deleting.
+#[async_trait]
+pub trait DeletionRepository: Send + Sync {
+    /// Records an object that never got a row, after storage refused to
+    /// delete it with `error`.
+    async fn record_failed(&self, storage_key: &str, error: String) -> Result<(), AppError>;
+
+    /// Clears a record once its object is gone. Returns whether it was still there.
+    async fn clear(&self, oid: i64) -> Result<bool, AppError>;
+
+    /// Notes another failed attempt at deleting the object.
+    async fn record_attempt(&self, oid: i64, error: String) -> Result<(), AppError>;
+
+    /// The records attempted least recently, at most `limit` of them.
+    async fn oldest(&self, limit: i64) -> Result<Vec<PendingDeletion>, AppError>;
+
+    /// The storage keys of the records under `prefix`.
+    async fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, AppError>;
+
+    async fn count(&self) -> Result<i64, AppError>;
+}
+
+/// Reserves an attempt on a link with the given count and lock, returning the
+/// outcome and the link's new count and lock.
+fn reserve_attempt(
+    attempts: i32,
+    locked_until: Option<NaiveDateTime>,
+    max_attempts: i32,
+    lockout: Duration,
+) -> (PasswordAttempt, i32, Option<NaiveDateTime>) {
+    if let Some(until) = locked_until.filter(|until| *until > Utc::now().naive_utc()) {
+        return (PasswordAttempt::Locked(until), attempts, locked_until);
+    }
+
+    let (attempts, locked_until) = next_attempt(attempts, max_attempts, lockout);
+    (PasswordAttempt::Reserved, attempts, locked_until)
+}
+
+fn next_attempt(
+    attempts: i32,
+    max_attempts: i32,
+    lockout: Duration,
+) -> (i32, Option<NaiveDateTime>) {
+    let attempts = attempts + 1;
+    if max_attempts > 0 && attempts >= max_attempts {
+        (0, Some(Utc::now().naive_utc() + lockout))
+    } else {
+        (attempts, None)
+    }
+}
+
+/// The repositories the handlers work with.
+#[derive(Clone)]
+pub struct Repositories {
+    pub files: Arc<dyn FileRepository>,
+    pub tenants: Arc<dyn TenantRepository>,
+    pub links: Arc<dyn LinkRepository>,
+    pub purposes: Arc<dyn PurposeRepository>,
+    pub api_keys: Arc<dyn ApiKeyRepository>,
+    pub deletions: Arc<dyn DeletionRepository>,
+}
+
+impl Repositories {
+    /// `snowflake_gen` mints the ids of pending deletions.
+    pub fn postgres(pool: DbPool, snowflake_gen: Arc<SnowflakeGeneratorWrapper>) -> Self {
+        let repository = Arc::new(postgres::PgRepository::new(pool, snowflake_gen));
+        Self {
+            files: repository.clone(),
+            tenants: repository.clone(),
+            links: repository.clone(),
+            purposes: repository.clone(),
+            api_keys: repository.clone(),
+            deletions: repository,
+        }
+    }
+
+    /// Repositories sharing one empty in-memory store.
+    #[allow(dead_code)]
+    pub fn in_memory() -> Self {
+        let repository = Arc::new(memory::MemoryRepository::default());
+        Self {
+            files: repository.clone(),
+            tenants: repository.clone(),
+            links: repository.clone(),
+            purposes: repository.clone(),
+            api_keys: repository.clone(),
+            deletions: repository,
+        }
+    }
+}
@@ -0,0 +1,1032 @@
+use super::{
+    reserve_attempt, ApiKeyRepository, DeletionRepository, Due, FileRepository, LinkFilter,
+    LinkRepository, NewUpload, PasswordAttempt, PurposeRepository, RemovedFile, StorageKeys,
+    TenantRepository, UploadBlob,
+};
+use crate::error::AppError;
+use crate::file_listing::{FileKey, FilePage, Listing};
+use crate::links::LinkState;
+use crate::models::*;
+use crate::pagination::{self, PageParams};
+use axum::async_trait;
+use chrono::{Duration, NaiveDateTime, Utc};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+
+#[derive(Default)]
+struct Store {
+    files: BTreeMap<i64, File>,
+    tenants: BTreeMap<i64, Tenant>,
+    links: BTreeMap<i64, FileLink>,
+    purposes: BTreeMap<i64, Purpose>,
+    blobs: BTreeMap<i64, Blob>,
+    api_keys: BTreeMap<i64, ApiKey>,
+    pending_deletions: BTreeMap<i64, PendingDeletion>,
+    /// The oid of the last pending deletion, which the store numbers itself.
+    last_deletion_oid: i64,
+}
+
+impl Store {
+    /// Joins a file with its tenant and purpose, like `FileRecord::query`.
+    fn record(&self, file: &File) -> Option<FileRecord> {
+        Some(FileRecord {
+            file: file.clone(),
+            tenant_id: self.tenants.get(&file.tenant_oid)?.id.clone(),
+            purpose: self.purposes.get(&file.purpose_oid)?.slug.clone(),
+        })
+    }
+
+    fn link_mut(&mut self, oid: i64) -> Option<&mut FileLink> {
+        self.links.get_mut(&oid)
+    }
+
+    fn insert_file(&mut self, file: NewFile) -> File {
+        let now = Utc::now().naive_utc();
+        let file = File {
+            oid: file.oid,
+            id: file.id,
+            tenant_oid: file.tenant_oid,
+            filename: file.filename,
+            purpose_oid: file.purpose_oid,
+            bytes: file.bytes,
+            storage_key: file.storage_key,
+            created_at: now,
+            updated_at: now,
+            content_type: file.content_type,
+            sha256: file.sha256,
+            md5: file.md5,
+            crc32c: file.crc32c,
+            blob_oid: file.blob_oid,
+            expires_at: file.expires_at,
+            deleted_at: None,
+        };
+        self.files.insert(file.oid, file.clone());
+        file
+    }
+
+    /// Takes a reference on the blob for an upload, like `blobs::retain` and
+    /// `blobs::acquire`.
+    fn take_blob(&mut self, blob: UploadBlob, file: &NewFile) -> Result<&Blob, AppError> {
+        let blob = match blob {
+            UploadBlob::Reuse(blob) => self
+                .blobs
+                .get_mut(&blob.oid)
+                .filter(|blob| blob.ref_count > 0)
+                .ok_or_else(|| AppError::internal("blob released while being reused"))?,
+            UploadBlob::Share(blob_oid) => {
+                let sha256 = file.sha256.clone().unwrap_or_default();
+                let existing = self
+                    .blobs
+                    .values()
+                    .find(|blob| blob.tenant_oid == file.tenant_oid && blob.sha256 == sha256)
+                    .map(|blob| blob.oid);
+                self.blobs
+                    .entry(existing.unwrap_or(blob_oid))
+                    .or_insert_with(|| Blob {
+                        oid: blob_oid,
+                        id: crate::snowflake::generate_prefixed_id("blob", blob_oid),
+                        tenant_oid: file.tenant_oid,
+                        sha256,
+                        bytes: file.bytes,
+                        storage_key: file.storage_key.clone(),
+                        ref_count: 0,
+                        created_at: Utc::now().naive_utc(),
+                    })
+            }
+        };
+        blob.ref_count += 1;
+        Ok(blob)
+    }
+
+    /// Drops a reference on a blob, like `blobs::release`.
+    fn release_blob(&mut self, oid: i64) -> Option<String> {
+        let blob = self.blobs.get_mut(&oid)?;
+        blob.ref_count -= 1;
+        if blob.ref_count > 0 {
+            return None;
+        }
+        self.blobs.remove(&oid).map(|blob| blob.storage_key)
+    }
+
+    fn schedule(&mut self, storage_keys: impl IntoIterator<Item = String>) -> Vec<PendingDeletion> {
+        let now = Utc::now().naive_utc();
+        storage_keys
+            .into_iter()
+            .map(|storage_key| {
+                self.last_deletion_oid += 1;
+                let deletion = PendingDeletion {
+                    oid: self.last_deletion_oid,
+                    storage_key,
+                    attempts: 0,
+                    last_error: None,
+                    created_at: now,
+                    updated_at: now,
+                };
+                self.pending_deletions
+                    .insert(deletion.oid, deletion.clone());
+                deletion
+            })
+            .collect()
+    }
+
+    /// Removes a file with its links and uncharges its tenant, like
+    /// `remove_file` does in Postgres.
+    fn remove_file(&mut self, file: &File) -> RemovedFile {
+        self.files.remove(&file.oid);
+        let before = self.links.len();
+        self.links.retain(|_, link| link.file_oid != file.oid);
+        let links = (before - self.links.len()) as i64;
+
+        let unreferenced_key = match file.blob_oid {
+            Some(blob_oid) => self.release_blob(blob_oid),
+            None => Some(file.storage_key.clone()),
+        };
+
+        if let Some(tenant) = self.tenants.get_mut(&file.tenant_oid) {
+            tenant.total_files_bytes -= file.bytes;
+            tenant.file_count -= 1;
+            tenant.updated_at = Utc::now().naive_utc();
+        }
+
+        RemovedFile {
+            file: file.clone(),
+            links,
+            scheduled: self.schedule(unreferenced_key),
+        }
+    }
+}
+
+/// The repositories kept in plain maps, so handlers can be tested without
+/// Postgres. Unique ids, keys and slugs are enforced; foreign keys are not,
+/// but files whose tenant or purpose is missing are never returned.
+#[derive(Default)]
+pub struct MemoryRepository {
+    store: Mutex<Store>,
+}
+
+impl MemoryRepository {
+    fn store(&self) -> Result<MutexGuard<'_, Store>, AppError> {
+        self.store.lock().map_err(AppError::internal)
+    }
+}
+
+#[async_trait]
+impl FileRepository for MemoryRepository {
+    async fn find_by_id(&self, id: &str) -> Result<Option<FileRecord>, AppError> {
+        let store = self.store()?;
+        Ok(store
+            .files
+            .values()
+            .find(|file| file.id == id)
+            .and_then(|file| store.record(file)))
+    }
+
+    async fn find(&self, oid: i64) -> Result<Option<FileRecord>, AppError> {
+        let store = self.store()?;
+        Ok(store.files.get(&oid).and_then(|file| store.record(file)))
+    }
+
+    async fn create(&self, file: NewFile) -> Result<File, AppError> {
+        Ok(self.store()?.insert_file(file))
+    }
+
+    async fn record_upload(&self, upload: NewUpload) -> Result<File, AppError> {
+        let NewUpload {
+            mut file,
+            quota,
+            blob,
+        } = upload;
+
+        let mut store = self.store()?;
+        let tenant = store
+            .tenants
+            .get(&file.tenant_oid)
+            .ok_or(AppError::NotFound)?;
+        quota
+            .check_fits(tenant, file.bytes)
+            .map_err(AppError::QuotaExceeded)?;
+
+        if let Some(blob) = blob {
+            let blob = store.take_blob(blob, &file)?;
+            file.storage_key = blob.storage_key.clone();
+            file.blob_oid = Some(blob.oid);
+        }
+
+        if let Some(tenant) = store.tenants.get_mut(&file.tenant_oid) {
+            tenant.total_files_bytes += file.bytes;
+            tenant.file_count += 1;
+            tenant.updated_at = Utc::now().naive_utc();
+        }
+        Ok(store.insert_file(file))
+    }
+
+    async fn find_blob(&self, tenant_oid: i64, sha256: &str) -> Result<Option<Blob>, AppError> {
+        Ok(self
+            .store()?
+            .blobs
+            .values()
+            .find(|blob| {
+                blob.tenant_oid == tenant_oid && blob.sha256 == sha256 && blob.ref_count > 0
+            })
+            .cloned())
+    }
+
+    async fn update(&self, oid: i64, update: UpdateFile) -> Result<Option<File>, AppError> {
+        let mut store = self.store()?;
+        Ok(store.files.get_mut(&oid).map(|file| {
+            if let Some(filename) = update.filename {
+                file.filename = filename;
+            }
+            if let Some(purpose_oid) = update.purpose_oid {
+                file.purpose_oid = purpose_oid;
+            }
+            if let Some(expires_at) = update.expires_at {
+                file.expires_at = expires_at;
+            }
+            file.updated_at = update.updated_at;
+            file.clone()
+        }))
+    }
+
+    async fn trash(&self, oid: i64) -> Result<Option<File>, AppError> {
+        let mut store = self.store()?;
+        Ok(store
+            .files
+            .get_mut(&oid)
+            .filter(|file| file.deleted_at.is_none())
+            .map(|file| {
+                file.deleted_at = Some(Utc::now().naive_utc());
+                file.clone()
+            }))
+    }
+
+    async fn restore(&self, oid: i64) -> Result<Option<File>, AppError> {
+        let mut store = self.store()?;
+        Ok(store.files.get_mut(&oid).map(|file| {
+            file.deleted_at = None;
+            file.updated_at = Utc::now().naive_utc();
+            file.clone()
+        }))
+    }
+
+    async fn set_sha256(&self, oid: i64, sha256: String) -> Result<(), AppError> {
+        if let Some(file) = self.store()?.files.get_mut(&oid) {
+            file.sha256 = Some(sha256);
+        }
+        Ok(())
+    }
+
+    async fn list(&self, listing: Listing) -> Result<FilePage, AppError> {
+        let records: Vec<FileRecord> = {
+            let store = self.store()?;
+            store
+                .files
+                .values()
+                .filter_map(|file| store.record(file))
+                .filter(|record| listing.filters.matches(record))
+                .collect()
+        };
+
+        let sort = listing.sort;
+        let (files, pagination) = pagination::paginate_by(
+            &listing.page,
+            |record: &FileRecord| FileKey::of(sort, record),
+            |range, descending, limit| {
+                let mut page: Vec<FileRecord> = records
+                    .iter()
+                    .filter(|record| range.contains(&FileKey::of(sort, record)))
+                    .cloned()
+                    .collect();
+                page.sort_by_key(|record| FileKey::of(sort, record));
+                if descending {
+                    page.reverse();
+                }
+                page.truncate(limit as usize);
+                Ok(page)
+            },
+        )?;
+
+        Ok(FilePage {
+            files,
+            pagination,
+            total_count: listing.include_total.then_some(records.len() as i64),
+        })
+    }
+
+    async fn remove_permanently(
+        &self,
+        oid: i64,
+        due: Option<Due>,
+    ) -> Result<Option<RemovedFile>, AppError> {
+        let mut store = self.store()?;
+        let Some(file) = store
+            .files
+            .get(&oid)
+            .filter(|file| due.is_none_or(|due| due.includes(file)))
+            .cloned()
+        else {
+            return Ok(None);
+        };
+        Ok(Some(store.remove_file(&file)))
+    }
+
+    async fn find_due(&self, due: Due, limit: i64) -> Result<Vec<File>, AppError> {
+        Ok(self
+            .store()?
+            .files
+            .values()
+            .filter(|file| due.includes(file))
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn storage_keys(&self, tenant_oid: i64) -> Result<StorageKeys, AppError> {
+        let store = self.store()?;
+        Ok(StorageKeys {
+            files: store
+                .files
+                .values()
+                .filter(|file| file.tenant_oid == tenant_oid)
+                .map(|file| file.storage_key.clone())
+                .collect(),
+            blobs: store
+                .blobs
+                .values()
+                .filter(|blob| blob.tenant_oid == tenant_oid)
+                .map(|blob| blob.storage_key.clone())
+                .collect(),
+        })
+    }
+}
+
+#[async_trait]
+impl TenantRepository for MemoryRepository {
+    async fn find(&self, oid: i64) -> Result<Option<Tenant>, AppError> {
+        Ok(self.store()?.tenants.get(&oid).cloned())
+    }
+
+    async fn find_by_id(&self, id: &str) -> Result<Option<Tenant>, AppError> {
+        Ok(self
+            .store()?
+            .tenants
+            .values()
+            .find(|tenant| tenant.id == id)
+            .cloned())
+    }
+
+    async fn create(&self, tenant: NewTenant) -> Result<Tenant, AppError> {
+        let mut store = self.store()?;
+        if store.tenants.values().any(|t| t.id == tenant.id) {
+            return Err(AppError::Conflict("Tenant already exists".to_string()));
+        }
+
+        let now = Utc::now().naive_utc();
+        let tenant = Tenant {
+            oid: tenant.oid,
+            id: tenant.id,
+            name: tenant.name,
+            created_at: now,
+            updated_at: now,
+            total_files_bytes: 0,
+            file_count: 0,
+            max_total_bytes: tenant.max_total_bytes,
+            max_file_count: tenant.max_file_count,
+            max_file_size_bytes: tenant.max_file_size_bytes,
+        };
+        store.tenants.insert(tenant.oid, tenant.clone());
+        Ok(tenant)
+    }
+
+    async fn create_or_get(&self, tenant: NewTenant) -> Result<Tenant, AppError> {
+        if let Some(existing) = TenantRepository::find_by_id(self, &tenant.id).await? {
+            return Ok(existing);
+        }
+        TenantRepository::create(self, tenant).await
+    }
+
+    async fn update(&self, id: &str, update: UpdateTenant) -> Result<Option<Tenant>, AppError> {
+        let mut store = self.store()?;
+        Ok(store
+            .tenants
+            .values_mut()
+            .find(|tenant| tenant.id == id)
+            .map(|tenant| {
+                if let Some(name) = update.name {
+                    tenant.name = name;
+                }
+                if let Some(limit) = update.max_total_bytes {
+                    tenant.max_total_bytes = limit;
+                }
+                if let Some(limit) = update.max_file_count {
+                    tenant.max_file_count = limit;
+                }
+                if let Some(limit) = update.max_file_size_bytes {
+                    tenant.max_file_size_bytes = limit;
+                }
+                tenant.updated_at = update.updated_at;
+                tenant.clone()
+            }))
+    }
+
+    async fn list(&self, page: PageParams) -> Result<(Vec<Tenant>, PaginationResponse), AppError> {
+        let store = self.store()?;
+        pagination::paginate(
+            &page,
+            |tenant: &Tenant| tenant.oid,
+            |range, descending, limit| {
+                let matching = store.tenants.values().filter(|t| range.contains(t.oid));
+                Ok(take(matching, descending, limit))
+            },
+        )
+    }
+
+    async fn all(&self) -> Result<Vec<Tenant>, AppError> {
+        Ok(self.store()?.tenants.values().cloned().collect())
+    }
+
+    async fn delete(&self, id: &str) -> Result<Option<(Tenant, Vec<PendingDeletion>)>, AppError> {
+        let mut store = self.store()?;
+        let Some(tenant) = store.tenants.values().find(|t| t.id == id).cloned() else {
+            return Ok(None);
+        };
+
+        let files: Vec<File> = store
+            .files
+            .values()
+            .filter(|file| file.tenant_oid == tenant.oid)
+            .cloned()
+            .collect();
+        let mut storage_keys: Vec<String> = files
+            .iter()
+            .filter(|file| file.blob_oid.is_none())
+            .map(|file| file.storage_key.clone())
+            .collect();
+        storage_keys.extend(
+            store
+                .blobs
+                .values()
+                .filter(|blob| blob.tenant_oid == tenant.oid)
+                .map(|blob| blob.storage_key.clone()),
+        );
+
+        for file in &files {
+            store.files.remove(&file.oid);
+            store.links.retain(|_, link| link.file_oid != file.oid);
+        }
+        store.blobs.retain(|_, blob| blob.tenant_oid != tenant.oid);
+        store
+            .api_keys
+            .retain(|_, api_key| api_key.tenant_oid != tenant.oid);
+        store.tenants.remove(&tenant.oid);
+
+        let scheduled = store.schedule(storage_keys);
+        Ok(Some((tenant, scheduled)))
+    }
+
+    async fn recount(&self, oid: i64, fix: bool) -> Result<Option<CounterDriftReport>, AppError> {
+        let mut store = self.store()?;
+        let (actual_bytes, actual_count) = store
+            .files
+            .values()
+            .filter(|file| file.tenant_oid == oid)
+            .fold((0, 0), |(bytes, count), file| {
+                (bytes + file.bytes, count + 1)
+            });
+
+        let tenant = store.tenants.get_mut(&oid).ok_or(AppError::NotFound)?;
+        if tenant.total_files_bytes == actual_bytes && tenant.file_count == actual_count {
+            return Ok(None);
+        }
+
+        let report = CounterDriftReport {
+            tenant_id: tenant.id.clone(),
+            recorded_bytes: tenant.total_files_bytes,
+            actual_bytes,
+            recorded_count: tenant.file_count,
+            actual_count,
+            fixed: fix,
+        };
+        if fix {
+            tenant.total_files_bytes = actual_bytes;
+            tenant.file_count = actual_count;
+            tenant.updated_at = Utc::now().naive_utc();
+        }
+        Ok(Some(report))
+    }
+}
+
+/// The first `limit` items of an iterator in ascending `oid` order, taken
+/// from the back when `descending`.
+fn take<'a, T: Clone + 'a>(
+    items: impl DoubleEndedIterator<Item = &'a T>,
+    descending: bool,
+    limit: i64,
+) -> Vec<T> {
+    let limit = limit as usize;
+    if descending {
+        items.rev().take(limit).cloned().collect()
+    } else {
+        items.take(limit).cloned().collect()
+    }
+}
+
+#[async_trait]
+impl LinkRepository for MemoryRepository {
+    async fn find_by_id(&self, id: &str) -> Result<Option<(FileLink, String)>, AppError> {
+        let store = self.store()?;
+        Ok(store
+            .links
+            .values()
+            .find(|link| link.id == id)
+            .and_then(|link| {
+                let file = store.files.get(&link.file_oid)?;
+                Some((link.clone(), file.id.clone()))
+            }))
+    }
+
+    async fn find_by_key(&self, key: &str) -> Result<Option<FileLink>, AppError> {
+        Ok(self
+            .store()?
+            .links
+            .values()
+            .find(|link| link.key == key)
+            .cloned())
+    }
+
+    async fn create(&self, link: NewFileLink) -> Result<FileLink, AppError> {
+        let mut store = self.store()?;
+        if store.links.values().any(|l| l.key == link.key) {
+            return Err(AppError::Conflict("Link key already in use".to_string()));
+        }
+
+        let link = FileLink {
+            oid: link.oid,
+            id: link.id,
+            file_oid: link.file_oid,
+            key: link.key,
+            expires_at: link.expires_at,
+            created_at: Utc::now().naive_utc(),
+            starts_at: link.starts_at,
+            revoked_at: None,
+            max_downloads: link.max_downloads,
+            download_count: 0,
+            last_accessed_at: None,
+            password_hash: link.password_hash,
+            failed_password_attempts: 0,
+            password_locked_until: None,
+        };
+        store.links.insert(link.oid, link.clone());
+        Ok(link)
+    }
+
+    async fn delete(&self, oid: i64) -> Result<(), AppError> {
+        self.store()?.links.remove(&oid);
+        Ok(())
+    }
+
+    async fn revoke(&self, oid: i64) -> Result<Option<FileLink>, AppError> {
+        let mut store = self.store()?;
+        Ok(store.link_mut(oid).map(|link| {
+            link.revoked_at
+                .get_or_insert_with(|| Utc::now().naive_utc());
+            link.clone()
+        }))
+    }
+
+    async fn revoke_all(&self, file_oid: i64) -> Result<usize, AppError> {
+        let now = Utc::now().naive_utc();
+        let mut store = self.store()?;
+        let mut revoked = 0;
+        for link in store.links.values_mut() {
+            if link.file_oid == file_oid && link.revoked_at.is_none() {
+                link.revoked_at = Some(now);
+                revoked += 1;
+            }
+        }
+        Ok(revoked)
+    }
+
+    async fn delete_expired(
+        &self,
+        cutoff: NaiveDateTime,
+        limit: i64,
+    ) -> Result<(usize, usize), AppError> {
+        let mut store = self.store()?;
+        let mut expired: Vec<(NaiveDateTime, i64)> = store
+            .links
+            .values()
+            .filter(|link| link.expires_at < cutoff)
+            .map(|link| (link.expires_at, link.oid))
+            .collect();
+        expired.sort();
+        expired.truncate(limit as usize);
+
+        for (_, oid) in &expired {
+            store.links.remove(oid);
+        }
+        Ok((expired.len(), expired.len()))
+    }
+
+    async fn list(
+        &self,
+        filter: LinkFilter,
+        page: PageParams,
+    ) -> Result<(Vec<(FileLink, String)>, PaginationResponse), AppError> {
+        let store = self.store()?;
+        let links: Vec<(FileLink, String)> = store
+            .links
+            .values()
+            .filter_map(|link| {
+                let file = store.files.get(&link.file_oid)?;
+                let active = LinkState::of(link, filter.now) == LinkState::Active;
+                let matches = filter.file_oid.is_none_or(|oid| link.file_oid == oid)
+                    && filter.tenant_oid.is_none_or(|oid| file.tenant_oid == oid)
+                    && filter.active.is_none_or(|wanted| active == wanted);
+                matches.then(|| (link.clone(), file.id.clone()))
+            })
+            .collect();
+
+        pagination::paginate(
+            &page,
+            |(link, _): &(FileLink, String)| link.oid,
+            |range, descending, limit| {
+                let matching = links.iter().filter(|(link, _)| range.contains(link.oid));
+                Ok(take(matching, descending, limit))
+            },
+        )
+    }
+
+    async fn record_download(&self, oid: i64) -> Result<Option<FileLink>, AppError> {
+        let mut store = self.store()?;
+        Ok(store
+            .link_mut(oid)
+            .filter(|link| {
+                link.max_downloads
+                    .is_none_or(|max| link.download_count < max)
+            })
+            .map(|link| {
+                link.download_count += 1;
+                link.last_accessed_at = Some(Utc::now().naive_utc());
+                link.clone()
+            }))
+    }
+
+    async fn release_download(&self, oid: i64) -> Result<(), AppError> {
+        if let Some(link) = self.store()?.link_mut(oid) {
+            link.download_count = (link.download_count - 1).max(0);
+        }
+        Ok(())
+    }
+
+    async fn reset_failed_attempts(&self, oid: i64) -> Result<(), AppError> {
+        if let Some(link) = self.store()?.link_mut(oid) {
+            link.failed_password_attempts = 0;
+            link.password_locked_until = None;
+        }
+        Ok(())
+    }
+
+    async fn reserve_password_attempt(
+        &self,
+        oid: i64,
+        max_attempts: i32,
+        lockout: Duration,
+    ) -> Result<PasswordAttempt, AppError> {
+        let mut store = self.store()?;
+        let link = store.link_mut(oid).ok_or(AppError::NotFound)?;
+        let (attempt, attempts, locked_until) = reserve_attempt(
+            link.failed_password_attempts,
+            link.password_locked_until,
+            max_attempts,
+            lockout,
+        );
+        link.failed_password_attempts = attempts;
+        link.password_locked_until = locked_until;
+        Ok(attempt)
+    }
+}
+
+#[async_trait]
+impl PurposeRepository for MemoryRepository {
+    async fn find_by_slug(&self, slug: &str) -> Result<Option<Purpose>, AppError> {
+        Ok(self
+            .store()?
+            .purposes
+            .values()
+            .find(|purpose| purpose.slug == slug)
+            .cloned())
+    }
+
+    async fn create(&self, purpose: NewPurpose) -> Result<Purpose, AppError> {
+        let mut store = self.store()?;
+        if store.purposes.values().any(|p| p.slug == purpose.slug) {
+            return Err(AppError::Conflict("Purpose already exists".to_string()));
+        }
+
+        let purpose = Purpose {
+            oid: purpose.oid,
+            id: purpose.id,
+            slug: purpose.slug,
+        };
+        store.purposes.insert(purpose.oid, purpose.clone());
+        Ok(purpose)
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for MemoryRepository {
+    async fn find_by_lookup(&self, lookup: &str) -> Result<Option<ApiKey>, AppError> {
+        Ok(self
+            .store()?
+            .api_keys
+            .values()
+            .find(|api_key| api_key.lookup == lookup && api_key.revoked_at.is_none())
+            .cloned())
+    }
+
+    async fn create(&self, api_key: NewApiKey) -> Result<ApiKey, AppError> {
+        let api_key = ApiKey {
+            oid: api_key.oid,
+            id: api_key.id,
+            tenant_oid: api_key.tenant_oid,
+            name: api_key.name,
+            lookup: api_key.lookup,
+            key_hash: api_key.key_hash,
+            created_at: Utc::now().naive_utc(),
+            last_used_at: None,
+            revoked_at: None,
+        };
+        self.store()?.api_keys.insert(api_key.oid, api_key.clone());
+        Ok(api_key)
+    }
+
+    async fn revoke(&self, id: &str) -> Result<Option<ApiKey>, AppError> {
+        let mut store = self.store()?;
+        Ok(store
+            .api_keys
+            .values_mut()
+            .find(|api_key| api_key.id == id)
+            .map(|api_key| {
+                api_key
+                    .revoked_at
+                    .get_or_insert_with(|| Utc::now().naive_utc());
+                api_key.clone()
+            }))
+    }
+
+    async fn mark_used(&self, oid: i64) -> Result<(), AppError> {
+        if let Some(api_key) = self.store()?.api_keys.get_mut(&oid) {
+            api_key.last_used_at = Some(Utc::now().naive_utc());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DeletionRepository for MemoryRepository {
+    async fn record_failed(&self, storage_key: &str, error: String) -> Result<(), AppError> {
+        let mut store = self.store()?;
+        for deletion in store.schedule([storage_key.to_string()]) {
+            if let Some(deletion) = store.pending_deletions.get_mut(&deletion.oid) {
+                deletion.attempts = 1;
+                deletion.last_error = Some(error.clone());
+            }
+        }
+        Ok(())
+    }
+
+    async fn clear(&self, oid: i64) -> Result<bool, AppError> {
+        Ok(self.store()?.pending_deletions.remove(&oid).is_some())
+    }
+
+    async fn record_attempt(&self, oid: i64, error: String) -> Result<(), AppError> {
+        if let Some(deletion) = self.store()?.pending_deletions.get_mut(&oid) {
+            deletion.attempts += 1;
+            deletion.last_error = Some(error);
+            deletion.updated_at = Utc::now().naive_utc();
+        }
+        Ok(())
+    }
+
+    async fn oldest(&self, limit: i64) -> Result<Vec<PendingDeletion>, AppError> {
+        let mut pending: Vec<PendingDeletion> =
+            self.store()?.pending_deletions.values().cloned().collect();
+        pending.sort_by_key(|deletion| deletion.updated_at);
+        pending.truncate(limit as usize);
+        Ok(pending)
+    }
+
+    async fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, AppError> {
+        Ok(self
+            .store()?
+            .pending_deletions
+            .values()
+            .filter(|deletion| deletion.storage_key.starts_with(prefix))
+            .map(|deletion| deletion.storage_key.clone())
+            .collect())
+    }
+
+    async fn count(&self) -> Result<i64, AppError> {
+        Ok(self.store()?.pending_deletions.len() as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quota::TenantQuota;
+
+    fn new_tenant(oid: i64, id: &str) -> NewTenant {
+        NewTenant {
+            oid,
+            id: id.to_string(),
+            name: id.to_string(),
+            max_total_bytes: None,
+            max_file_count: None,
+            max_file_size_bytes: None,
+        }
+    }
+
+    /// An upload deduplicated by its size, so uploads of equal size share a blob.
+    fn new_upload(tenant_oid: i64, oid: i64, bytes: i64, quota: TenantQuota) -> NewUpload {
+        NewUpload {
+            file: NewFile {
+                oid,
+                id: format!("file_{}", oid),
+                tenant_oid,
+                filename: "a.txt".to_string(),
+                purpose_oid: 1,
+                bytes,
+                storage_key: format!("acme/file_{}", oid),
+                content_type: "text/plain".to_string(),
+                sha256: Some(format!("{:064}", bytes)),
+                md5: None,
+                crc32c: None,
+                blob_oid: None,
+                expires_at: None,
+            },
+            quota,
+            blob: Some(UploadBlob::Share(oid)),
+        }
+    }
+
+    async fn create_link(repo: &MemoryRepository, max_downloads: Option<i64>) -> FileLink {
+        LinkRepository::create(
+            repo,
+            NewFileLink {
+                oid: 1,
+                id: "link_1".to_string(),
+                file_oid: 1,
+                key: "key".to_string(),
+                expires_at: Utc::now().naive_utc() + Duration::hours(1),
+                starts_at: None,
+                max_downloads,
+                password_hash: None,
+            },
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_tenant_ids_are_unique() {
+        let repo = MemoryRepository::default();
+        TenantRepository::create(&repo, new_tenant(1, "acme"))
+            .await
+            .unwrap();
+
+        let err = TenantRepository::create(&repo, new_tenant(2, "acme"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::Conflict(_)));
+
+        let tenant = repo.create_or_get(new_tenant(3, "acme")).await.unwrap();
+        assert_eq!(tenant.oid, 1);
+    }
+
+    #[tokio::test]
+    async fn test_uploads_over_quota_leave_no_trace() {
+        let repo = MemoryRepository::default();
+        let tenant = TenantRepository::create(&repo, new_tenant(1, "acme"))
+            .await
+            .unwrap();
+        let quota = TenantQuota {
+            max_total_bytes: Some(10),
+            max_file_count: None,
+            max_file_size_bytes: 10,
+        };
+        repo.record_upload(new_upload(tenant.oid, 1, 6, quota))
+            .await
+            .unwrap();
+        let err = repo
+            .record_upload(new_upload(tenant.oid, 2, 5, quota))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::QuotaExceeded(_)));
+
+        let tenant = TenantRepository::find(&repo, tenant.oid)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((tenant.total_files_bytes, tenant.file_count), (6, 1));
+        assert!(repo
+            .find_blob(tenant.oid, &format!("{:064}", 5))
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_shared_objects_are_scheduled_with_their_last_file() {
+        let repo = MemoryRepository::default();
+        let tenant = TenantRepository::create(&repo, new_tenant(1, "acme"))
+            .await
+            .unwrap();
+        let quota = TenantQuota {
+            max_total_bytes: None,
+            max_file_count: None,
+            max_file_size_bytes: 10,
+        };
+        for oid in [1, 2] {
+            repo.record_upload(new_upload(tenant.oid, oid, 3, quota))
+                .await
+                .unwrap();
+        }
+
+        let first = repo.remove_permanently(1, None).await.unwrap().unwrap();
+        assert!(first.scheduled.is_empty());
+        let last = repo.remove_permanently(2, None).await.unwrap().unwrap();
+        let keys: Vec<&str> = last
+            .scheduled
+            .iter()
+            .map(|deletion| deletion.storage_key.as_str())
+            .collect();
+        assert_eq!(keys, ["acme/file_1"]);
+        assert!(repo.remove_permanently(2, None).await.unwrap().is_none());
+
+        let tenant = TenantRepository::find(&repo, tenant.oid)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((tenant.total_files_bytes, tenant.file_count), (0, 0));
+        assert_eq!(DeletionRepository::count(&repo).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_downloads_stop_at_the_limit_until_released() {
+        let repo = MemoryRepository::default();
+        let link = create_link(&repo, Some(1)).await;
+
+        let counted = repo.record_download(link.oid).await.unwrap().unwrap();
+        assert_eq!(counted.download_count, 1);
+        assert!(counted.last_accessed_at.is_some());
+        assert!(repo.record_download(link.oid).await.unwrap().is_none());
+
+        repo.release_download(link.oid).await.unwrap();
+        assert!(repo.record_download(link.oid).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_revoking_keeps_the_first_revocation() {
+        let repo = MemoryRepository::default();
+        let link = create_link(&repo, None).await;
+
+        let first = LinkRepository::revoke(&repo, link.oid)
+            .await
+            .unwrap()
+            .unwrap();
+        let second = LinkRepository::revoke(&repo, link.oid)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(first.revoked_at.is_some());
+        assert_eq!(second.revoked_at, first.revoked_at);
+    }
+
+    #[tokio::test]
+    async fn test_password_attempts_lock_at_the_limit() {
+        let repo = MemoryRepository::default();
+        let link = create_link(&repo, None).await;
+        let lockout = Duration::minutes(5);
+        let reserve = || repo.reserve_password_attempt(link.oid, 2, lockout);
+
+        assert_eq!(reserve().await.unwrap(), PasswordAttempt::Reserved);
+        let stored = repo.find_by_key("key").await.unwrap().unwrap();
+        assert_eq!(stored.failed_password_attempts, 1);
+        assert!(stored.password_locked_until.is_none());
+
+        assert_eq!(reserve().await.unwrap(), PasswordAttempt::Reserved);
+        let stored = repo.find_by_key("key").await.unwrap().unwrap();
+        assert_eq!(stored.failed_password_attempts, 0);
+        let locked_until = stored.password_locked_until.unwrap();
+
+        assert_eq!(
+            reserve().await.unwrap(),
+            PasswordAttempt::Locked(locked_until)
+        );
+
+        repo.reset_failed_attempts(link.oid).await.unwrap();
+        assert_eq!(reserve().await.unwrap(), PasswordAttempt::Reserved);
+    }
+}
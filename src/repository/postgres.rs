@@ -0,0 +1,944 @@
+use super::{
+    reserve_attempt, ApiKeyRepository, DeletionRepository, Due, FileRepository, LinkFilter,
+    LinkRepository, NewUpload, PasswordAttempt, PurposeRepository, RemovedFile, StorageKeys,
+    TenantRepository, UploadBlob,
+};
+use crate::blobs;
+use crate::db::{self, escape_like, DbPool};
+use crate::error::AppError;
+use crate::file_listing::{self, FilePage, Listing};
+use crate::models::*;
+use crate::pagination::{self, PageParams};
+use crate::schema::*;
+use crate::snowflake::SnowflakeGeneratorWrapper;
+use axum::async_trait;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::dsl::{count_star, sql};
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel::sql_types::BigInt;
+use std::sync::Arc;
+
+/// The repositories backed by the database.
+pub struct PgRepository {
+    pool: DbPool,
+    snowflake_gen: Arc<SnowflakeGeneratorWrapper>,
+}
+
+impl PgRepository {
+    pub fn new(pool: DbPool, snowflake_gen: Arc<SnowflakeGeneratorWrapper>) -> Self {
+        Self {
+            pool,
+            snowflake_gen,
+        }
+    }
+}
+
+/// Records storage objects that are about to lose their last database row,
+/// inside the transaction that removes those rows. If the process dies or
+/// storage is unavailable before they are deleted, the records remain for a
+/// later retry.
+fn schedule(
+    conn: &mut PgConnection,
+    snowflake_gen: &SnowflakeGeneratorWrapper,
+    storage_keys: impl IntoIterator<Item = String>,
+) -> Result<Vec<PendingDeletion>, AppError> {
+    let new_deletions = storage_keys
+        .into_iter()
+        .map(|storage_key| {
+            let oid = snowflake_gen.generate().map_err(AppError::internal)?;
+            Ok(NewPendingDeletion {
+                oid,
+                storage_key,
+                attempts: 0,
+                last_error: None,
+            })
+        })
+        .collect::<Result<Vec<_>, AppError>>()?;
+
+    if new_deletions.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    diesel::insert_into(pending_deletions::table)
+        .values(&new_deletions)
+        .get_results(conn)
+        .map_err(AppError::database)
+}
+
+/// Deletes a file's row and uncharges its tenant, scheduling its object for
+/// deletion unless a deduplicated blob is still shared. Links go with the row
+/// through `ON DELETE CASCADE`. Call this inside a transaction.
+fn remove_file(
+    conn: &mut PgConnection,
+    snowflake_gen: &SnowflakeGeneratorWrapper,
+    file: &File,
+) -> Result<Vec<PendingDeletion>, AppError> {
+    let deleted = diesel::delete(files::table.find(file.oid)).execute(conn)?;
+    if deleted == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    // Deduplicated content is shared, so storage is only freed with the last reference.
+    let unreferenced_key = match file.blob_oid {
+        Some(blob_oid) => blobs::release(conn, blob_oid)?,
+        None => Some(file.storage_key.clone()),
+    };
+
+    diesel::update(tenants::table.find(file.tenant_oid))
+        .set((
+            tenants::total_files_bytes.eq(tenants::total_files_bytes - file.bytes),
+            tenants::file_count.eq(tenants::file_count - 1),
+            tenants::updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(conn)?;
+
+    schedule(conn, snowflake_gen, unreferenced_key)
+}
+
+/// The SQL form of [`Due::includes`].
+fn due(
+    due: Due,
+) -> diesel::dsl::Or<
+    diesel::dsl::LtEq<files::expires_at, NaiveDateTime>,
+    diesel::dsl::LtEq<files::deleted_at, NaiveDateTime>,
+> {
+    files::expires_at
+        .le(due.now)
+        .or(files::deleted_at.le(due.purge_before))
+}
+
+/// Maps a unique violation to `Conflict` with `message`.
+fn conflict(message: &'static str) -> impl Fn(DieselError) -> AppError {
+    move |e| match e {
+        DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+            AppError::Conflict(message.to_string())
+        }
+        e => AppError::database(e),
+    }
+}
+
+#[async_trait]
+impl FileRepository for PgRepository {
+    async fn find_by_id(&self, id: &str) -> Result<Option<FileRecord>, AppError> {
+        let id = id.to_string();
+        db::with_conn(&self.pool, move |conn| {
+            FileRecord::query()
+                .filter(files::id.eq(&id))
+                .first(conn)
+                .optional()
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn find(&self, oid: i64) -> Result<Option<FileRecord>, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            FileRecord::query()
+                .filter(files::oid.eq(oid))
+                .first(conn)
+                .optional()
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn create(&self, file: NewFile) -> Result<File, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            diesel::insert_into(files::table)
+                .values(&file)
+                .get_result(conn)
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn record_upload(&self, upload: NewUpload) -> Result<File, AppError> {
+        let NewUpload {
+            mut file,
+            quota,
+            blob,
+        } = upload;
+
+        db::with_conn(&self.pool, move |conn| {
+            conn.transaction(|conn| {
+                quota.reserve(conn, file.tenant_oid, file.bytes)?;
+
+                let blob = match blob {
+                    None => None,
+                    Some(UploadBlob::Reuse(blob)) => Some(
+                        blobs::retain(conn, blob.oid)
+                            .map_err(AppError::database)?
+                            .ok_or_else(|| {
+                                tracing::warn!("Blob {} was released while being reused", blob.id);
+                                AppError::internal("blob released while being reused")
+                            })?,
+                    ),
+                    Some(UploadBlob::Share(blob_oid)) => Some(
+                        blobs::acquire(
+                            conn,
+                            blob_oid,
+                            file.tenant_oid,
+                            file.sha256.as_deref().unwrap_or_default(),
+                            file.bytes,
+                            &file.storage_key,
+                        )
+                        .map_err(AppError::database)?,
+                    ),
+                };
+                if let Some(blob) = blob {
+                    file.storage_key = blob.storage_key;
+                    file.blob_oid = Some(blob.oid);
+                }
+
+                diesel::insert_into(files::table)
+                    .values(&file)
+                    .get_result(conn)
+                    .map_err(AppError::database)
+            })
+        })
+        .await
+    }
+
+    async fn find_blob(&self, tenant_oid: i64, sha256: &str) -> Result<Option<Blob>, AppError> {
+        let sha256 = sha256.to_string();
+        db::with_conn(&self.pool, move |conn| {
+            blobs::find_by_hash(conn, tenant_oid, &sha256).map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn update(&self, oid: i64, update: UpdateFile) -> Result<Option<File>, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            diesel::update(files::table.find(oid))
+                .set(&update)
+                .get_result(conn)
+                .optional()
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn trash(&self, oid: i64) -> Result<Option<File>, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            diesel::update(files::table.find(oid).filter(files::deleted_at.is_null()))
+                .set(files::deleted_at.eq(Utc::now().naive_utc()))
+                .get_result(conn)
+                .optional()
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn restore(&self, oid: i64) -> Result<Option<File>, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            diesel::update(files::table.find(oid))
+                .set((
+                    files::deleted_at.eq(None::<chrono::NaiveDateTime>),
+                    files::updated_at.eq(Utc::now().naive_utc()),
+                ))
+                .get_result(conn)
+                .optional()
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn set_sha256(&self, oid: i64, sha256: String) -> Result<(), AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            diesel::update(files::table.find(oid))
+                .set(files::sha256.eq(sha256))
+                .execute(conn)
+                .map(|_| ())
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn list(&self, listing: Listing) -> Result<FilePage, AppError> {
+        db::with_conn(&self.pool, move |conn| file_listing::load(conn, &listing)).await
+    }
+
+    async fn remove_permanently(
+        &self,
+        oid: i64,
+        due: Option<Due>,
+    ) -> Result<Option<RemovedFile>, AppError> {
+        let snowflake_gen = self.snowflake_gen.clone();
+        db::with_conn(&self.pool, move |conn| {
+            conn.transaction(|conn| {
+                let file: Option<File> =
+                    files::table.find(oid).for_update().first(conn).optional()?;
+                let Some(file) = file.filter(|file| due.is_none_or(|due| due.includes(file)))
+                else {
+                    return Ok(None);
+                };
+
+                let links: i64 = file_links::table
+                    .filter(file_links::file_oid.eq(file.oid))
+                    .count()
+                    .get_result(conn)?;
+
+                let scheduled = remove_file(conn, &snowflake_gen, &file)?;
+                Ok(Some(RemovedFile {
+                    file,
+                    links,
+                    scheduled,
+                }))
+            })
+        })
+        .await
+    }
+
+    async fn find_due(&self, due: Due, limit: i64) -> Result<Vec<File>, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            files::table
+                .filter(self::due(due))
+                .order(files::oid.asc())
+                .limit(limit)
+                .load(conn)
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn storage_keys(&self, tenant_oid: i64) -> Result<StorageKeys, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            let files = files::table
+                .filter(files::tenant_oid.eq(tenant_oid))
+                .select(files::storage_key)
+                .load(conn)?;
+            let blobs = crate::schema::blobs::table
+                .filter(crate::schema::blobs::tenant_oid.eq(tenant_oid))
+                .select(crate::schema::blobs::storage_key)
+                .load(conn)?;
+            Ok(StorageKeys { files, blobs })
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl TenantRepository for PgRepository {
+    async fn find(&self, oid: i64) -> Result<Option<Tenant>, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            tenants::table
+                .find(oid)
+                .first(conn)
+                .optional()
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn find_by_id(&self, id: &str) -> Result<Option<Tenant>, AppError> {
+        let id = id.to_string();
+        db::with_conn(&self.pool, move |conn| {
+            tenants::table
+                .filter(tenants::id.eq(&id))
+                .first(conn)
+                .optional()
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn create(&self, tenant: NewTenant) -> Result<Tenant, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            diesel::insert_into(tenants::table)
+                .values(&tenant)
+                .get_result(conn)
+                .map_err(conflict("Tenant already exists"))
+        })
+        .await
+    }
+
+    async fn create_or_get(&self, tenant: NewTenant) -> Result<Tenant, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            diesel::insert_into(tenants::table)
+                .values(&tenant)
+                .on_conflict(tenants::id)
+                .do_nothing()
+                .execute(conn)
+                .map_err(AppError::database)?;
+
+            tenants::table
+                .filter(tenants::id.eq(&tenant.id))
+                .first(conn)
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn update(&self, id: &str, update: UpdateTenant) -> Result<Option<Tenant>, AppError> {
+        let id = id.to_string();
+        db::with_conn(&self.pool, move |conn| {
+            diesel::update(tenants::table.filter(tenants::id.eq(&id)))
+                .set(&update)
+                .get_result(conn)
+                .optional()
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn list(&self, page: PageParams) -> Result<(Vec<Tenant>, PaginationResponse), AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            pagination::paginate(
+                &page,
+                |tenant: &Tenant| tenant.oid,
+                |range, descending, limit| {
+                    let mut base_query = tenants::table.into_boxed();
+                    if let Some(gt) = range.gt {
+                        base_query = base_query.filter(tenants::oid.gt(gt));
+                    }
+                    if let Some(lt) = range.lt {
+                        base_query = base_query.filter(tenants::oid.lt(lt));
+                    }
+                    if descending {
+                        base_query = base_query.order(tenants::oid.desc());
+                    } else {
+                        base_query = base_query.order(tenants::oid.asc());
+                    }
+                    base_query
+                        .limit(limit)
+                        .load(conn)
+                        .map_err(AppError::database)
+                },
+            )
+        })
+        .await
+    }
+
+    async fn all(&self) -> Result<Vec<Tenant>, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            tenants::table
+                .order(tenants::oid.asc())
+                .load(conn)
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn delete(&self, id: &str) -> Result<Option<(Tenant, Vec<PendingDeletion>)>, AppError> {
+        let id = id.to_string();
+        let snowflake_gen = self.snowflake_gen.clone();
+        db::with_conn(&self.pool, move |conn| {
+            conn.transaction(|conn| {
+                // Holding the row lock keeps concurrent uploads from adding files
+                // whose objects would be missed below.
+                let Some(tenant) = tenants::table
+                    .filter(tenants::id.eq(&id))
+                    .for_update()
+                    .first::<Tenant>(conn)
+                    .optional()?
+                else {
+                    return Ok(None);
+                };
+
+                let mut storage_keys: Vec<String> = files::table
+                    .filter(files::tenant_oid.eq(tenant.oid))
+                    .filter(files::blob_oid.is_null())
+                    .select(files::storage_key)
+                    .load(conn)?;
+
+                let blob_keys: Vec<String> = crate::schema::blobs::table
+                    .filter(crate::schema::blobs::tenant_oid.eq(tenant.oid))
+                    .select(crate::schema::blobs::storage_key)
+                    .load(conn)?;
+                storage_keys.extend(blob_keys);
+
+                // Files, blobs, links and API keys go with the tenant via ON DELETE CASCADE.
+                diesel::delete(tenants::table.find(tenant.oid)).execute(conn)?;
+
+                let scheduled = schedule(conn, &snowflake_gen, storage_keys)?;
+                Ok(Some((tenant, scheduled)))
+            })
+        })
+        .await
+    }
+
+    /// The tenant row is locked first. Uploads and deletes update that row in
+    /// the same transaction as the file row, so the sums read afterwards
+    /// cannot miss a change that is still in flight.
+    async fn recount(&self, oid: i64, fix: bool) -> Result<Option<CounterDriftReport>, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            conn.transaction::<_, DieselError, _>(|conn| {
+                let tenant: Tenant = tenants::table.find(oid).for_update().first(conn)?;
+
+                let (actual_bytes, actual_count): (i64, i64) = files::table
+                    .filter(files::tenant_oid.eq(oid))
+                    .select((
+                        sql::<BigInt>("COALESCE(SUM(bytes), 0)::BIGINT"),
+                        count_star(),
+                    ))
+                    .first(conn)?;
+
+                if tenant.total_files_bytes == actual_bytes && tenant.file_count == actual_count {
+                    return Ok(None);
+                }
+
+                if fix {
+                    diesel::update(tenants::table.find(oid))
+                        .set((
+                            tenants::total_files_bytes.eq(actual_bytes),
+                            tenants::file_count.eq(actual_count),
+                            tenants::updated_at.eq(Utc::now().naive_utc()),
+                        ))
+                        .execute(conn)?;
+                }
+
+                Ok(Some(CounterDriftReport {
+                    tenant_id: tenant.id,
+                    recorded_bytes: tenant.total_files_bytes,
+                    actual_bytes,
+                    recorded_count: tenant.file_count,
+                    actual_count,
+                    fixed: fix,
+                }))
+            })
+            .map_err(AppError::database)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl LinkRepository for PgRepository {
+    async fn find_by_id(&self, id: &str) -> Result<Option<(FileLink, String)>, AppError> {
+        let id = id.to_string();
+        db::with_conn(&self.pool, move |conn| {
+            file_links::table
+                .inner_join(files::table)
+                .filter(file_links::id.eq(&id))
+                .select((FileLink::as_select(), files::id))
+                .first(conn)
+                .optional()
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn find_by_key(&self, key: &str) -> Result<Option<FileLink>, AppError> {
+        let key = key.to_string();
+        db::with_conn(&self.pool, move |conn| {
+            file_links::table
+                .filter(file_links::key.eq(&key))
+                .first(conn)
+                .optional()
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn create(&self, link: NewFileLink) -> Result<FileLink, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            diesel::insert_into(file_links::table)
+                .values(&link)
+                .get_result(conn)
+                .map_err(conflict("Link key already in use"))
+        })
+        .await
+    }
+
+    async fn delete(&self, oid: i64) -> Result<(), AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            diesel::delete(file_links::table.find(oid))
+                .execute(conn)
+                .map(|_| ())
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn revoke(&self, oid: i64) -> Result<Option<FileLink>, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            conn.transaction(|conn| {
+                diesel::update(
+                    file_links::table
+                        .find(oid)
+                        .filter(file_links::revoked_at.is_null()),
+                )
+                .set(file_links::revoked_at.eq(Utc::now().naive_utc()))
+                .execute(conn)?;
+
+                file_links::table.find(oid).first(conn).optional()
+            })
+            .map_err(|e: DieselError| AppError::database(e))
+        })
+        .await
+    }
+
+    async fn revoke_all(&self, file_oid: i64) -> Result<usize, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            diesel::update(
+                file_links::table
+                    .filter(file_links::file_oid.eq(file_oid))
+                    .filter(file_links::revoked_at.is_null()),
+            )
+            .set(file_links::revoked_at.eq(Utc::now().naive_utc()))
+            .execute(conn)
+            .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn delete_expired(
+        &self,
+        cutoff: NaiveDateTime,
+        limit: i64,
+    ) -> Result<(usize, usize), AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            // Oldest first, so the expires_at index serves the lookup.
+            let expired: Vec<i64> = file_links::table
+                .select(file_links::oid)
+                .filter(file_links::expires_at.lt(cutoff))
+                .order(file_links::expires_at.asc())
+                .limit(limit)
+                .load(conn)
+                .map_err(AppError::database)?;
+
+            if expired.is_empty() {
+                return Ok((0, 0));
+            }
+
+            let deleted =
+                diesel::delete(file_links::table.filter(file_links::oid.eq_any(&expired)))
+                    .execute(conn)
+                    .map_err(AppError::database)?;
+            Ok((expired.len(), deleted))
+        })
+        .await
+    }
+
+    async fn list(
+        &self,
+        filter: LinkFilter,
+        page: PageParams,
+    ) -> Result<(Vec<(FileLink, String)>, PaginationResponse), AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            let now = filter.now;
+            pagination::paginate(
+                &page,
+                |(link, _): &(FileLink, String)| link.oid,
+                |range, descending, limit| {
+                    let mut base_query = file_links::table
+                        .inner_join(files::table)
+                        .select((FileLink::as_select(), files::id))
+                        .into_boxed();
+
+                    if let Some(file_oid) = filter.file_oid {
+                        base_query = base_query.filter(file_links::file_oid.eq(file_oid));
+                    }
+
+                    if let Some(tenant_oid) = filter.tenant_oid {
+                        base_query = base_query.filter(files::tenant_oid.eq(tenant_oid));
+                    }
+
+                    // Mirrors `LinkState::of`: a link is active unless it is revoked,
+                    // expired, used up or not yet valid.
+                    match filter.active {
+                        Some(true) => {
+                            base_query = base_query
+                                .filter(file_links::revoked_at.is_null())
+                                .filter(file_links::expires_at.ge(now))
+                                .filter(
+                                    file_links::max_downloads
+                                        .is_null()
+                                        .or(file_links::download_count
+                                            .lt(file_links::max_downloads.assume_not_null())),
+                                )
+                                .filter(
+                                    file_links::starts_at
+                                        .is_null()
+                                        .or(file_links::starts_at.assume_not_null().le(now)),
+                                );
+                        }
+                        Some(false) => {
+                            base_query = base_query.filter(
+                                file_links::revoked_at
+                                    .is_not_null()
+                                    .or(file_links::expires_at.lt(now))
+                                    .or(file_links::max_downloads.is_not_null().and(
+                                        file_links::download_count
+                                            .ge(file_links::max_downloads.assume_not_null()),
+                                    ))
+                                    .or(file_links::starts_at
+                                        .is_not_null()
+                                        .and(file_links::starts_at.assume_not_null().gt(now))),
+                            );
+                        }
+                        None => {}
+                    }
+
+                    if let Some(gt) = range.gt {
+                        base_query = base_query.filter(file_links::oid.gt(gt));
+                    }
+                    if let Some(lt) = range.lt {
+                        base_query = base_query.filter(file_links::oid.lt(lt));
+                    }
+                    if descending {
+                        base_query = base_query.order(file_links::oid.desc());
+                    } else {
+                        base_query = base_query.order(file_links::oid.asc());
+                    }
+
+                    base_query
+                        .limit(limit)
+                        .load(conn)
+                        .map_err(AppError::database)
+                },
+            )
+        })
+        .await
+    }
+
+    /// The limit is checked in the same `UPDATE` that bumps the counter.
+    async fn record_download(&self, oid: i64) -> Result<Option<FileLink>, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            diesel::update(
+                file_links::table
+                    .find(oid)
+                    .filter(file_links::max_downloads.is_null().or(
+                        file_links::download_count.lt(file_links::max_downloads.assume_not_null()),
+                    )),
+            )
+            .set((
+                file_links::download_count.eq(file_links::download_count + 1),
+                file_links::last_accessed_at.eq(Utc::now().naive_utc()),
+            ))
+            .get_result(conn)
+            .optional()
+            .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn release_download(&self, oid: i64) -> Result<(), AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            diesel::update(
+                file_links::table
+                    .find(oid)
+                    .filter(file_links::download_count.gt(0)),
+            )
+            .set(file_links::download_count.eq(file_links::download_count - 1))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn reset_failed_attempts(&self, oid: i64) -> Result<(), AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            diesel::update(file_links::table.find(oid))
+                .set((
+                    file_links::failed_password_attempts.eq(0),
+                    file_links::password_locked_until.eq(None::<chrono::NaiveDateTime>),
+                ))
+                .execute(conn)
+                .map(|_| ())
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn reserve_password_attempt(
+        &self,
+        oid: i64,
+        max_attempts: i32,
+        lockout: Duration,
+    ) -> Result<PasswordAttempt, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            // The row lock makes concurrent attempts queue up, so each sees
+            // the count and lock the one before it left.
+            conn.transaction(|conn| {
+                let (attempts, locked_until) = file_links::table
+                    .find(oid)
+                    .select((
+                        file_links::failed_password_attempts,
+                        file_links::password_locked_until,
+                    ))
+                    .for_update()
+                    .first(conn)?;
+                let (attempt, attempts, locked_until) =
+                    reserve_attempt(attempts, locked_until, max_attempts, lockout);
+
+                if attempt == PasswordAttempt::Reserved {
+                    diesel::update(file_links::table.find(oid))
+                        .set((
+                            file_links::failed_password_attempts.eq(attempts),
+                            file_links::password_locked_until.eq(locked_until),
+                        ))
+                        .execute(conn)?;
+                }
+
+                Ok(attempt)
+            })
+            .map_err(|e: DieselError| AppError::database(e))
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl PurposeRepository for PgRepository {
+    async fn find_by_slug(&self, slug: &str) -> Result<Option<Purpose>, AppError> {
+        let slug = slug.to_string();
+        db::with_conn(&self.pool, move |conn| {
+            purposes::table
+                .filter(purposes::slug.eq(&slug))
+                .first(conn)
+                .optional()
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn create(&self, purpose: NewPurpose) -> Result<Purpose, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            diesel::insert_into(purposes::table)
+                .values(&purpose)
+                .get_result(conn)
+                .map_err(conflict("Purpose already exists"))
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for PgRepository {
+    async fn find_by_lookup(&self, lookup: &str) -> Result<Option<ApiKey>, AppError> {
+        let lookup = lookup.to_string();
+        db::with_conn(&self.pool, move |conn| {
+            api_keys::table
+                .filter(api_keys::lookup.eq(&lookup))
+                .filter(api_keys::revoked_at.is_null())
+                .first(conn)
+                .optional()
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn create(&self, api_key: NewApiKey) -> Result<ApiKey, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            diesel::insert_into(api_keys::table)
+                .values(&api_key)
+                .get_result(conn)
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn revoke(&self, id: &str) -> Result<Option<ApiKey>, AppError> {
+        let id = id.to_string();
+        db::with_conn(&self.pool, move |conn| {
+            conn.transaction(|conn| {
+                diesel::update(
+                    api_keys::table
+                        .filter(api_keys::id.eq(&id))
+                        .filter(api_keys::revoked_at.is_null()),
+                )
+                .set(api_keys::revoked_at.eq(Utc::now().naive_utc()))
+                .execute(conn)?;
+
+                api_keys::table
+                    .filter(api_keys::id.eq(&id))
+                    .first(conn)
+                    .optional()
+            })
+            .map_err(|e: DieselError| AppError::database(e))
+        })
+        .await
+    }
+
+    async fn mark_used(&self, oid: i64) -> Result<(), AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            diesel::update(api_keys::table.find(oid))
+                .set(api_keys::last_used_at.eq(Utc::now().naive_utc()))
+                .execute(conn)
+                .map(|_| ())
+                .map_err(AppError::database)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl DeletionRepository for PgRepository {
+    async fn record_failed(&self, storage_key: &str, error: String) -> Result<(), AppError> {
+        let new_deletion = NewPendingDeletion {
+            oid: self.snowflake_gen.generate().map_err(AppError::internal)?,
+            storage_key: storage_key.to_string(),
+            attempts: 1,
+            last_error: Some(error),
+        };
+        db::with_conn(&self.pool, move |conn| {
+            diesel::insert_into(pending_deletions::table)
+                .values(&new_deletion)
+                .execute(conn)
+                .map(|_| ())
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn clear(&self, oid: i64) -> Result<bool, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            diesel::delete(pending_deletions::table.find(oid))
+                .execute(conn)
+                .map(|deleted| deleted > 0)
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn record_attempt(&self, oid: i64, error: String) -> Result<(), AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            diesel::update(pending_deletions::table.find(oid))
+                .set((
+                    pending_deletions::attempts.eq(pending_deletions::attempts + 1),
+                    pending_deletions::last_error.eq(error),
+                    pending_deletions::updated_at.eq(Utc::now().naive_utc()),
+                ))
+                .execute(conn)
+                .map(|_| ())
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn oldest(&self, limit: i64) -> Result<Vec<PendingDeletion>, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            pending_deletions::table
+                .order(pending_deletions::updated_at.asc())
+                .limit(limit)
+                .load(conn)
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, AppError> {
+        let pattern = format!("{}%", escape_like(prefix));
+        db::with_conn(&self.pool, move |conn| {
+            pending_deletions::table
+                .filter(pending_deletions::storage_key.like(pattern))
+                .select(pending_deletions::storage_key)
+                .load(conn)
+                .map_err(AppError::database)
+        })
+        .await
+    }
+
+    async fn count(&self) -> Result<i64, AppError> {
+        db::with_conn(&self.pool, move |conn| {
+            pending_deletions::table
+                .count()
+                .get_result(conn)
+                .map_err(AppError::database)
+        })
+        .await
+    }
+}
@@ -7,6 +7,9 @@ diesel::table! {
         updated_at -> Timestamp,
         total_files_bytes -> Int8,
         file_count -> Int8,
+        max_total_bytes -> Nullable<Int8>,
+        max_file_count -> Nullable<Int8>,
+        max_file_size_bytes -> Nullable<Int8>,
     }
 }
 
@@ -29,6 +32,26 @@ diesel::table! {
         storage_key -> Varchar,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        content_type -> Varchar,
+        sha256 -> Nullable<Varchar>,
+        md5 -> Nullable<Varchar>,
+        crc32c -> Nullable<Varchar>,
+        blob_oid -> Nullable<Int8>,
+        expires_at -> Nullable<Timestamp>,
+        deleted_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    blobs (oid) {
+        oid -> Int8,
+        id -> Varchar,
+        tenant_oid -> Int8,
+        sha256 -> Varchar,
+        bytes -> Int8,
+        storage_key -> Varchar,
+        ref_count -> Int8,
+        created_at -> Timestamp,
     }
 }
 
@@ -40,11 +63,55 @@ diesel::table! {
         key -> Varchar,
         expires_at -> Timestamp,
         created_at -> Timestamp,
+        starts_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+        max_downloads -> Nullable<Int8>,
+        download_count -> Int8,
+        last_accessed_at -> Nullable<Timestamp>,
+        password_hash -> Nullable<Varchar>,
+        failed_password_attempts -> Int4,
+        password_locked_until -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    api_keys (oid) {
+        oid -> Int8,
+        id -> Varchar,
+        tenant_oid -> Int8,
+        name -> Varchar,
+        lookup -> Varchar,
+        key_hash -> Varchar,
+        created_at -> Timestamp,
+        last_used_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    pending_deletions (oid) {
+        oid -> Int8,
+        storage_key -> Varchar,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
 diesel::joinable!(files -> tenants (tenant_oid));
 diesel::joinable!(files -> purposes (purpose_oid));
+diesel::joinable!(files -> blobs (blob_oid));
 diesel::joinable!(file_links -> files (file_oid));
+diesel::joinable!(blobs -> tenants (tenant_oid));
+diesel::joinable!(api_keys -> tenants (tenant_oid));
 
-diesel::allow_tables_to_appear_in_same_query!(tenants, purposes, files, file_links,);
+diesel::allow_tables_to_appear_in_same_query!(
+    tenants,
+    purposes,
+    files,
+    file_links,
+    blobs,
+    api_keys,
+    pending_deletions,
+);
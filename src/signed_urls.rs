@@ -0,0 +1,254 @@
+use crate::content::Disposition;
+use crate::error::AppError;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::str::FromStr;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Longest lifetime a signed URL may be minted with.
+pub const MAX_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+
+const MIN_SECRET_LEN: usize = 32;
+
+/// Everything except RFC 3986 unreserved characters.
+const QUERY_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// An HMAC key for signing URLs, configured as `<key id>:<secret>`.
+#[derive(Clone)]
+pub struct SigningKey {
+    pub id: String,
+    secret: Vec<u8>,
+}
+
+impl FromStr for SigningKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (id, secret) = s
+            .split_once(':')
+            .ok_or_else(|| "URL signing keys must look like <key id>:<secret>".to_string())?;
+
+        if id.is_empty()
+            || !id
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        {
+            return Err(format!("Invalid URL signing key id: {:?}", id));
+        }
+        if secret.len() < MIN_SECRET_LEN {
+            return Err(format!(
+                "URL signing key {} must be at least {} bytes",
+                id, MIN_SECRET_LEN
+            ));
+        }
+
+        Ok(Self {
+            id: id.to_string(),
+            secret: secret.as_bytes().to_vec(),
+        })
+    }
+}
+
+/// What a signed URL grants: one file until `expires`, optionally served
+/// with a different disposition or content type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedUrlClaims {
+    pub file_id: String,
+    pub expires: i64,
+    pub disposition: Option<Disposition>,
+    pub content_type: Option<String>,
+}
+
+/// The query string of a signed URL.
+#[derive(Deserialize)]
+pub struct SignedUrlQuery {
+    pub expires: Option<i64>,
+    pub kid: Option<String>,
+    pub sig: Option<String>,
+    pub disposition: Option<String>,
+    pub content_type: Option<String>,
+}
+
+impl SignedUrlClaims {
+    /// The exact bytes that are signed. The key id is included so a signature
+    /// cannot be replayed under a different key.
+    fn message(&self, kid: &str) -> String {
+        format!(
+            "{}\n{}\n{}\n{}\n{}",
+            kid,
+            self.file_id,
+            self.expires,
+            self.disposition.map(|d| d.as_str()).unwrap_or_default(),
+            self.content_type.as_deref().unwrap_or_default(),
+        )
+    }
+
+    /// Signs with the first configured key and returns the path and query
+    /// of the URL, relative to the public API.
+    pub fn sign(&self, keys: &[SigningKey]) -> Result<String, AppError> {
+        let key = keys
+            .first()
+            .ok_or_else(|| AppError::BadRequest("URL signing is not configured".to_string()))?;
+
+        let mut mac = HmacSha256::new_from_slice(&key.secret).map_err(AppError::internal)?;
+        mac.update(self.message(&key.id).as_bytes());
+        let sig = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+
+        let mut url = format!(
+            "/s/{}?expires={}&kid={}",
+            encode(&self.file_id),
+            self.expires,
+            encode(&key.id)
+        );
+        if let Some(disposition) = self.disposition {
+            url.push_str(&format!("&disposition={}", disposition.as_str()));
+        }
+        if let Some(content_type) = &self.content_type {
+            url.push_str(&format!("&content_type={}", encode(content_type)));
+        }
+        url.push_str(&format!("&sig={}", sig));
+
+        Ok(url)
+    }
+
+    /// Rebuilds the claims of a signed URL and checks its signature, then its
+    /// expiry. Any key still in the configuration is accepted, so URLs signed
+    /// before a rotation keep working until they expire.
+    pub fn verify(
+        keys: &[SigningKey],
+        file_id: String,
+        query: SignedUrlQuery,
+        now: i64,
+    ) -> Result<Self, AppError> {
+        let expires = query.expires.ok_or(AppError::MissingParam("expires"))?;
+        let kid = query.kid.ok_or(AppError::MissingParam("kid"))?;
+        let sig = query.sig.ok_or(AppError::MissingParam("sig"))?;
+        let disposition = query
+            .disposition
+            .map(|d| d.parse())
+            .transpose()
+            .map_err(|e: String| AppError::invalid_param("disposition", e))?;
+
+        let claims = Self {
+            file_id,
+            expires,
+            disposition,
+            content_type: query.content_type,
+        };
+
+        let invalid = || AppError::Forbidden("Invalid signature".to_string());
+        let key = keys.iter().find(|key| key.id == kid).ok_or_else(invalid)?;
+        let sig = URL_SAFE_NO_PAD.decode(sig).map_err(|_| invalid())?;
+
+        let mut mac = HmacSha256::new_from_slice(&key.secret).map_err(AppError::internal)?;
+        mac.update(claims.message(&key.id).as_bytes());
+        mac.verify_slice(&sig).map_err(|_| invalid())?;
+
+        if claims.expires < now {
+            return Err(AppError::Gone("Signed URL expired".to_string()));
+        }
+
+        Ok(claims)
+    }
+}
+
+fn encode(value: &str) -> String {
+    utf8_percent_encode(value, QUERY_ENCODE_SET).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+
+    fn keys() -> Vec<SigningKey> {
+        vec![
+            "new:0123456789abcdef0123456789abcdef".parse().unwrap(),
+            "old:fedcba9876543210fedcba9876543210".parse().unwrap(),
+        ]
+    }
+
+    fn claims() -> SignedUrlClaims {
+        SignedUrlClaims {
+            file_id: "file_1".to_string(),
+            expires: 2_000,
+            disposition: Some(Disposition::Attachment),
+            content_type: Some("text/plain; charset=utf-8".to_string()),
+        }
+    }
+
+    /// Parses the query of a signed URL back the way axum would.
+    fn query(url: &str) -> SignedUrlQuery {
+        let uri: axum::http::Uri = url.parse().unwrap();
+        axum::extract::Query::try_from_uri(&uri).unwrap().0
+    }
+
+    #[test]
+    fn test_signing_key_parsing() {
+        assert!("k1:0123456789abcdef0123456789abcdef"
+            .parse::<SigningKey>()
+            .is_ok());
+        assert!("k1:short".parse::<SigningKey>().is_err());
+        assert!("no-secret".parse::<SigningKey>().is_err());
+        assert!("bad id:0123456789abcdef0123456789abcdef"
+            .parse::<SigningKey>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_sign_and_verify_roundtrip() {
+        let url = claims().sign(&keys()).unwrap();
+        assert!(url.starts_with("/s/file_1?expires=2000&kid=new&"));
+
+        let verified =
+            SignedUrlClaims::verify(&keys(), "file_1".to_string(), query(&url), 1_000).unwrap();
+        assert_eq!(verified, claims());
+    }
+
+    #[test]
+    fn test_rotated_key_still_verifies() {
+        let url = claims().sign(&keys()[1..]).unwrap();
+        assert!(SignedUrlClaims::verify(&keys(), "file_1".to_string(), query(&url), 1_000).is_ok());
+    }
+
+    #[test]
+    fn test_tampering_is_rejected() {
+        let url = claims().sign(&keys()).unwrap();
+
+        let forbidden = |file_id: &str, url: &str| {
+            SignedUrlClaims::verify(&keys(), file_id.to_string(), query(url), 1_000)
+                .unwrap_err()
+                .status()
+        };
+
+        assert_eq!(forbidden("file_2", &url), StatusCode::FORBIDDEN);
+        assert_eq!(
+            forbidden("file_1", &url.replace("expires=2000", "expires=3000")),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            forbidden("file_1", &url.replace("attachment", "inline")),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            forbidden("file_1", &url.replace("kid=new", "kid=gone")),
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[test]
+    fn test_expired_url_is_gone() {
+        let url = claims().sign(&keys()).unwrap();
+        let err =
+            SignedUrlClaims::verify(&keys(), "file_1".to_string(), query(&url), 2_001).unwrap_err();
+        assert_eq!(err.status(), StatusCode::GONE);
+    }
+}
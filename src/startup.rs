@@ -1,20 +1,14 @@
-use crate::models::{NewPurpose, Purpose};
-use crate::schema::purposes;
+use crate::models::NewPurpose;
+use crate::repository::PurposeRepository;
 use crate::snowflake::SnowflakeGeneratorWrapper;
-use diesel::prelude::*;
 
-pub fn upsert_purposes(
-    conn: &mut PgConnection,
+pub async fn upsert_purposes(
+    purposes: &dyn PurposeRepository,
     snowflake_gen: &SnowflakeGeneratorWrapper,
     purpose_slugs: &[String],
 ) -> Result<(), Box<dyn std::error::Error>> {
     for slug in purpose_slugs {
-        let existing: Option<Purpose> = purposes::table
-            .filter(purposes::slug.eq(slug))
-            .first(conn)
-            .optional()?;
-
-        if existing.is_none() {
+        if purposes.find_by_slug(slug).await?.is_none() {
             let oid = snowflake_gen.generate()?;
             let id = crate::snowflake::generate_prefixed_id("purpose", oid);
 
@@ -24,9 +18,7 @@ pub fn upsert_purposes(
                 slug: slug.clone(),
             };
 
-            diesel::insert_into(purposes::table)
-                .values(&new_purpose)
-                .execute(conn)?;
+            purposes.create(new_purpose).await?;
 
             tracing::info!("Created purpose: {}", slug);
         }
@@ -1,7 +1,19 @@
+use crate::range::ByteRange;
 use bytes::Bytes;
-use reqwest::Client;
+use chrono::{DateTime, Utc};
+use futures::{Stream, TryStream};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::{Body, Client, StatusCode};
+use serde::Deserialize;
 use thiserror::Error;
 
+/// Everything except RFC 3986 unreserved characters.
+const KEY_SEGMENT_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
 #[derive(Error, Debug)]
 pub enum StorageError {
     #[error("HTTP request failed: {0}")]
@@ -10,6 +22,34 @@ pub enum StorageError {
     OperationFailed(String),
 }
 
+/// A download whose body is streamed from storage as it is read.
+pub struct ObjectStream {
+    /// Whether storage honoured the requested range with `206 Partial Content`.
+    pub partial: bool,
+    pub content_length: Option<u64>,
+    response: reqwest::Response,
+}
+
+impl ObjectStream {
+    pub fn into_stream(self) -> impl Stream<Item = Result<Bytes, reqwest::Error>> {
+        self.response.bytes_stream()
+    }
+}
+
+/// An entry from a bucket listing.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ObjectInfo {
+    pub key: String,
+    pub size: Option<i64>,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+struct ListObjectsPage {
+    objects: Vec<ObjectInfo>,
+    next_cursor: Option<String>,
+}
+
 #[derive(Clone)]
 pub struct ObjectStorageClient {
     client: Client,
@@ -26,15 +66,57 @@ impl ObjectStorageClient {
         }
     }
 
+    /// Each segment of the key is percent-encoded, so no key can reach outside
+    /// its object path or add a query.
+    fn object_url(&self, key: &str) -> String {
+        let key: Vec<String> = key
+            .split('/')
+            .map(|segment| utf8_percent_encode(segment, KEY_SEGMENT_ENCODE_SET).to_string())
+            .collect();
+        format!(
+            "{}/buckets/{}/objects/{}",
+            self.base_url,
+            self.bucket,
+            key.join("/")
+        )
+    }
+
+    #[allow(dead_code)]
     pub async fn upload(
         &self,
         key: &str,
         data: Bytes,
         content_type: Option<&str>,
     ) -> Result<(), StorageError> {
-        let url = format!("{}/buckets/{}/objects/{}", self.base_url, self.bucket, key);
+        self.put(key, Body::from(data), content_type).await
+    }
+
+    /// Uploads an object from a stream of chunks without buffering it in memory.
+    ///
+    /// If the stream yields an error the request body is aborted and the upload fails.
+    pub async fn upload_stream<S>(
+        &self,
+        key: &str,
+        stream: S,
+        content_type: Option<&str>,
+    ) -> Result<(), StorageError>
+    where
+        S: TryStream + Send + Sync + 'static,
+        S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        Bytes: From<S::Ok>,
+    {
+        self.put(key, Body::wrap_stream(stream), content_type).await
+    }
+
+    async fn put(
+        &self,
+        key: &str,
+        body: Body,
+        content_type: Option<&str>,
+    ) -> Result<(), StorageError> {
+        let url = self.object_url(key);
 
-        let mut req = self.client.put(&url).body(data);
+        let mut req = self.client.put(&url).body(body);
 
         if let Some(ct) = content_type {
             req = req.header("Content-Type", ct);
@@ -52,8 +134,9 @@ impl ObjectStorageClient {
         Ok(())
     }
 
+    #[allow(dead_code)]
     pub async fn download(&self, key: &str) -> Result<Bytes, StorageError> {
-        let url = format!("{}/buckets/{}/objects/{}", self.base_url, self.bucket, key);
+        let url = self.object_url(key);
 
         let response = self.client.get(&url).send().await?;
 
@@ -67,8 +150,72 @@ impl ObjectStorageClient {
         Ok(response.bytes().await?)
     }
 
+    /// Opens a streaming download, optionally restricted to a byte range.
+    ///
+    /// Storage backends may ignore the range and answer with the full object;
+    /// check [`ObjectStream::partial`] before relying on the returned bytes.
+    pub async fn download_stream(
+        &self,
+        key: &str,
+        range: Option<ByteRange>,
+    ) -> Result<ObjectStream, StorageError> {
+        let url = self.object_url(key);
+
+        let mut req = self.client.get(&url);
+
+        if let Some(range) = range {
+            req = req.header("Range", range.header_value());
+        }
+
+        let response = req.send().await?;
+
+        if !response.status().is_success() {
+            return Err(StorageError::OperationFailed(format!(
+                "Download failed with status: {}",
+                response.status()
+            )));
+        }
+
+        Ok(ObjectStream {
+            partial: response.status() == StatusCode::PARTIAL_CONTENT,
+            content_length: response.content_length(),
+            response,
+        })
+    }
+
+    /// Lists every object whose key starts with `prefix`, following pagination.
+    pub async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>, StorageError> {
+        let url = format!("{}/buckets/{}/objects", self.base_url, self.bucket);
+        let mut objects = Vec::new();
+        let mut cursor: Option<String> = None;
+
+        loop {
+            let mut req = self.client.get(&url).query(&[("prefix", prefix)]);
+            if let Some(cursor) = &cursor {
+                req = req.query(&[("cursor", cursor)]);
+            }
+
+            let response = req.send().await?;
+
+            if !response.status().is_success() {
+                return Err(StorageError::OperationFailed(format!(
+                    "List failed with status: {}",
+                    response.status()
+                )));
+            }
+
+            let page: ListObjectsPage = response.json().await?;
+            objects.extend(page.objects);
+
+            match page.next_cursor {
+                Some(next) if !next.is_empty() => cursor = Some(next),
+                _ => return Ok(objects),
+            }
+        }
+    }
+
     pub async fn delete(&self, key: &str) -> Result<(), StorageError> {
-        let url = format!("{}/buckets/{}/objects/{}", self.base_url, self.bucket, key);
+        let url = self.object_url(key);
 
         let response = self.client.delete(&url).send().await?;
 
@@ -86,6 +233,7 @@ impl ObjectStorageClient {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use futures::TryStreamExt;
 
     #[tokio::test]
     async fn test_upload_success() {
@@ -139,6 +287,44 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_upload_stream_success() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("PUT", "/buckets/test-bucket/objects/test-key")
+            .match_body("test data")
+            .with_status(200)
+            .create();
+
+        let chunks: Vec<Result<Bytes, std::io::Error>> =
+            vec![Ok(Bytes::from("test ")), Ok(Bytes::from("data"))];
+
+        let client = ObjectStorageClient::new(server.url(), "test-bucket".to_string());
+        let result = client
+            .upload_stream("test-key", futures::stream::iter(chunks), None)
+            .await;
+
+        mock.assert();
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_upload_stream_aborted() {
+        let server = mockito::Server::new_async().await;
+
+        let chunks: Vec<Result<Bytes, std::io::Error>> = vec![
+            Ok(Bytes::from("test ")),
+            Err(std::io::Error::other("aborted")),
+        ];
+
+        let client = ObjectStorageClient::new(server.url(), "test-bucket".to_string());
+        let result = client
+            .upload_stream("test-key", futures::stream::iter(chunks), None)
+            .await;
+
+        assert!(result.is_err());
+    }
+
     #[tokio::test]
     async fn test_download_success() {
         let mut server = mockito::Server::new_async().await;
@@ -171,6 +357,77 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_download_stream_full() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/buckets/test-bucket/objects/test-key")
+            .with_status(200)
+            .with_body("test data")
+            .create();
+
+        let client = ObjectStorageClient::new(server.url(), "test-bucket".to_string());
+        let object = client.download_stream("test-key", None).await.unwrap();
+
+        mock.assert();
+        assert!(!object.partial);
+        assert_eq!(object.content_length, Some(9));
+
+        let chunks: Vec<Bytes> = object.into_stream().try_collect().await.unwrap();
+        assert_eq!(chunks.concat(), b"test data");
+    }
+
+    #[tokio::test]
+    async fn test_download_stream_with_range() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/buckets/test-bucket/objects/test-key")
+            .match_header("Range", "bytes=5-8")
+            .with_status(206)
+            .with_header("Content-Range", "bytes 5-8/9")
+            .with_body("data")
+            .create();
+
+        let client = ObjectStorageClient::new(server.url(), "test-bucket".to_string());
+        let object = client
+            .download_stream("test-key", Some(ByteRange { start: 5, end: 8 }))
+            .await
+            .unwrap();
+
+        mock.assert();
+        assert!(object.partial);
+        assert_eq!(object.content_length, Some(4));
+    }
+
+    #[tokio::test]
+    async fn test_download_stream_not_found() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/buckets/test-bucket/objects/test-key")
+            .with_status(404)
+            .create();
+
+        let client = ObjectStorageClient::new(server.url(), "test-bucket".to_string());
+        let result = client.download_stream("test-key", None).await;
+
+        mock.assert();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_object_url_encodes_key_segments() {
+        let client = ObjectStorageClient::new("http://storage".to_string(), "b".to_string());
+
+        assert_eq!(
+            client.object_url("tenant_1/file_2"),
+            "http://storage/buckets/b/objects/tenant_1/file_2"
+        );
+        assert_eq!(
+            client.object_url("a?b#c/d e%"),
+            "http://storage/buckets/b/objects/a%3Fb%23c/d%20e%25"
+        );
+    }
+
     #[tokio::test]
     async fn test_delete_success() {
         let mut server = mockito::Server::new_async().await;
@@ -200,4 +457,37 @@ mod tests {
         mock.assert();
         assert!(result.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_list_follows_cursor() {
+        let mut server = mockito::Server::new_async().await;
+        let first = server
+            .mock("GET", "/buckets/test-bucket/objects")
+            .match_query(mockito::Matcher::Regex("^prefix=tenant%2F$".into()))
+            .with_status(200)
+            .with_body(
+                r#"{"objects": [{"key": "tenant/a", "size": 3, "last_modified": "2026-01-01T00:00:00Z"}], "next_cursor": "page2"}"#,
+            )
+            .create();
+        let second = server
+            .mock("GET", "/buckets/test-bucket/objects")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("prefix".into(), "tenant/".into()),
+                mockito::Matcher::UrlEncoded("cursor".into(), "page2".into()),
+            ]))
+            .with_status(200)
+            .with_body(r#"{"objects": [{"key": "tenant/b"}], "next_cursor": null}"#)
+            .create();
+
+        let client = ObjectStorageClient::new(server.url(), "test-bucket".to_string());
+        let objects = client.list("tenant/").await.unwrap();
+
+        first.assert();
+        second.assert();
+        let keys: Vec<&str> = objects.iter().map(|o| o.key.as_str()).collect();
+        assert_eq!(keys, vec!["tenant/a", "tenant/b"]);
+        assert_eq!(objects[0].size, Some(3));
+        assert!(objects[0].last_modified.is_some());
+        assert!(objects[1].last_modified.is_none());
+    }
 }
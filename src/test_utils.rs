@@ -1,11 +1,16 @@
 #![allow(dead_code)]
 
 use crate::app_state::AppState;
+use crate::auth::{AuthChain, AuthMethod};
 use crate::config::Config;
 use crate::db::{create_pool, run_migrations, DbPool};
+use crate::repository::Repositories;
 use crate::snowflake::SnowflakeGeneratorWrapper;
 use crate::storage::ObjectStorageClient;
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::PgConnection;
 use std::sync::Once;
+use std::time::Duration;
 
 static INIT: Once = Once::new();
 
@@ -13,6 +18,10 @@ pub fn create_test_config() -> Config {
     Config {
         database_url: std::env::var("TEST_DATABASE_URL")
             .unwrap_or_else(|_| "postgres://localhost/cargo_hold_test".to_string()),
+        db_pool_max_size: 10,
+        db_pool_min_idle: None,
+        db_pool_timeout_secs: 30,
+        db_pool_idle_timeout_secs: 600,
         public_host: "127.0.0.1".to_string(),
         public_port: 0,
         private_host: "127.0.0.1".to_string(),
@@ -20,11 +29,40 @@ pub fn create_test_config() -> Config {
         storage_base_url: "http://localhost:9999".to_string(),
         storage_bucket: "test-bucket".to_string(),
         max_file_size_bytes: 1048576,
+        tenant_max_total_bytes: None,
+        tenant_max_file_count: None,
         allowed_purposes: vec![
             "test-purpose".to_string(),
             "document".to_string(),
             "image".to_string(),
         ],
+        purpose_default_ttls: vec![],
+        file_reap_interval_secs: 0,
+        file_reap_batch_size: 100,
+        file_trash_retention_secs: 604_800,
+        extra_checksums: vec![],
+        dedup_enabled: false,
+        tenant_auto_create: true,
+        reconcile_interval_secs: 0,
+        reconcile_fix: false,
+        reconcile_orphan_grace_secs: 3600,
+        auth_methods: vec![AuthMethod::TenantHeader],
+        jwt_hs256_secret: None,
+        jwt_rs256_public_key: None,
+        jwt_tenant_claim: "tenant_id".to_string(),
+        jwt_issuer: None,
+        jwt_audience: None,
+        brand_name: None,
+        link_password_max_attempts: 5,
+        link_password_lockout_secs: 300,
+        link_min_ttl_secs: 60,
+        link_max_ttl_secs: 2_592_000,
+        link_key_length: 16,
+        link_sweep_interval_secs: 0,
+        link_sweep_batch_size: 1000,
+        link_sweep_grace_secs: 604_800,
+        url_signing_keys: Vec::new(),
+        public_base_url: String::new(),
         worker_id: 1,
         datacenter_id: 1,
     }
@@ -33,7 +71,7 @@ pub fn create_test_config() -> Config {
 fn init_test_db() {
     INIT.call_once(|| {
         let config = create_test_config();
-        let pool = create_pool(&config.database_url).expect("Failed to create test pool");
+        let pool = create_pool(&config).expect("Failed to create test pool");
         let mut conn = pool.get().expect("Failed to get connection");
         run_migrations(&mut conn).expect("Failed to run migrations");
     });
@@ -42,12 +80,41 @@ fn init_test_db() {
 pub fn setup_test_db() -> DbPool {
     init_test_db();
     let config = create_test_config();
-    create_pool(&config.database_url).expect("Failed to create test pool")
+    create_pool(&config).expect("Failed to create test pool")
 }
 
 pub fn create_test_app_state() -> AppState {
-    let config = create_test_config();
-    let db_pool = setup_test_db();
+    create_test_app_state_with_config(create_test_config())
+}
+
+pub fn create_test_app_state_with_storage(storage_base_url: &str) -> AppState {
+    let mut config = create_test_config();
+    config.storage_base_url = storage_base_url.to_string();
+    create_test_app_state_with_config(config)
+}
+
+pub fn create_test_app_state_with_config(config: Config) -> AppState {
+    app_state(setup_test_db(), config)
+}
+
+/// An app state whose repositories keep their data in memory, for tests that
+/// run without Postgres. The pool never connects, so a test that reaches for
+/// `db_pool` fails after a second.
+pub fn create_memory_app_state(storage_base_url: &str) -> AppState {
+    let mut config = create_test_config();
+    config.storage_base_url = storage_base_url.to_string();
+    create_memory_app_state_with_config(config)
+}
+
+pub fn create_memory_app_state_with_config(config: Config) -> AppState {
+    let db_pool = Pool::builder()
+        .connection_timeout(Duration::from_secs(1))
+        .build_unchecked(ConnectionManager::<PgConnection>::new(&config.database_url));
+
+    app_state(db_pool, config).with_repositories(Repositories::in_memory())
+}
+
+fn app_state(db_pool: DbPool, config: Config) -> AppState {
     let storage_client = ObjectStorageClient::new(
         config.storage_base_url.clone(),
         config.storage_bucket.clone(),
@@ -55,7 +122,9 @@ pub fn create_test_app_state() -> AppState {
     let snowflake_gen =
         SnowflakeGeneratorWrapper::new(config.worker_id, config.datacenter_id).unwrap();
 
-    AppState::new(db_pool, storage_client, snowflake_gen, config)
+    let auth = AuthChain::from_config(&config).expect("Invalid test auth config");
+
+    AppState::new(db_pool, storage_client, snowflake_gen, auth, config)
 }
 
 pub fn cleanup_test_db(pool: &DbPool) {
@@ -75,4 +144,7 @@ pub fn cleanup_test_db(pool: &DbPool) {
     diesel::sql_query("TRUNCATE TABLE tenants CASCADE")
         .execute(&mut conn)
         .ok();
+    diesel::sql_query("TRUNCATE TABLE pending_deletions")
+        .execute(&mut conn)
+        .ok();
 }
@@ -4,36 +4,74 @@ use axum::{
     Router,
 };
 use cargo_hold::{
-    handlers_private, handlers_public, handlers_unauthenticated, models::*, schema::*, startup,
-    test_utils::*,
+    file_reaper, handlers_private, handlers_public, handlers_unauthenticated, link_sweeper,
+    models::*, request_id, schema::*, startup, test_utils::*,
 };
 use diesel::prelude::*;
 use serde_json::json;
-use std::sync::Mutex;
+use tokio::sync::{Mutex, MutexGuard};
 use tower::ServiceExt;
 
-static TEST_MUTEX: Mutex<()> = Mutex::new(());
+static TEST_MUTEX: Mutex<()> = Mutex::const_new(());
 
 async fn setup_test_router() -> (
     Router,
     cargo_hold::app_state::AppState,
-    std::sync::MutexGuard<'static, ()>,
+    MutexGuard<'static, ()>,
 ) {
-    let guard = TEST_MUTEX.lock().unwrap();
+    let guard = TEST_MUTEX.lock().await;
     let state = create_test_app_state();
+    let router = build_test_router(&state).await;
 
+    (router, state, guard)
+}
+
+async fn setup_test_router_with_storage(
+    storage_base_url: &str,
+) -> (
+    Router,
+    cargo_hold::app_state::AppState,
+    MutexGuard<'static, ()>,
+) {
+    let guard = TEST_MUTEX.lock().await;
+    let state = create_test_app_state_with_storage(storage_base_url);
+    let router = build_test_router(&state).await;
+
+    (router, state, guard)
+}
+
+async fn setup_test_router_with_config(
+    config: cargo_hold::config::Config,
+) -> (
+    Router,
+    cargo_hold::app_state::AppState,
+    MutexGuard<'static, ()>,
+) {
+    let guard = TEST_MUTEX.lock().await;
+    let state = create_test_app_state_with_config(config);
+    let router = build_test_router(&state).await;
+
+    (router, state, guard)
+}
+
+async fn build_test_router(state: &cargo_hold::app_state::AppState) -> Router {
     cleanup_test_db(&state.db_pool);
 
-    let mut conn = state.db_pool.get().unwrap();
     startup::upsert_purposes(
-        &mut conn,
+        state.repos.purposes.as_ref(),
         &state.snowflake_gen,
         &state.config.allowed_purposes,
     )
+    .await
     .unwrap();
 
-    let router = Router::new()
+    test_routes(state)
+}
+
+fn test_routes(state: &cargo_hold::app_state::AppState) -> Router {
+    Router::new()
         .route("/files", axum::routing::post(handlers_public::upload_file))
+        .route("/files", axum::routing::get(handlers_public::list_files))
         .route(
             "/files/:file_id",
             axum::routing::get(handlers_public::get_file),
@@ -46,6 +84,34 @@ async fn setup_test_router() -> (
             "/f/:link_key",
             axum::routing::get(handlers_unauthenticated::get_file_by_link),
         )
+        .route(
+            "/f/:link_key",
+            axum::routing::post(handlers_unauthenticated::submit_link_password),
+        )
+        .route(
+            "/s/:file_id",
+            axum::routing::get(handlers_unauthenticated::get_file_by_signed_url),
+        )
+        .route(
+            "/admin/files/:file_id/restore",
+            axum::routing::post(handlers_private::restore_file),
+        )
+        .route(
+            "/admin/files/:file_id/signed-urls",
+            axum::routing::post(handlers_private::create_signed_url),
+        )
+        .route(
+            "/admin/links",
+            axum::routing::get(handlers_private::list_links),
+        )
+        .route(
+            "/admin/files/:file_id/links",
+            axum::routing::get(handlers_private::list_file_links),
+        )
+        .route(
+            "/admin/files/:file_id/links",
+            axum::routing::delete(handlers_private::revoke_file_links),
+        )
         .route(
             "/admin/files/:file_id",
             axum::routing::delete(handlers_private::delete_file),
@@ -58,10 +124,46 @@ async fn setup_test_router() -> (
             "/admin/files/:file_id",
             axum::routing::get(handlers_private::get_file_private),
         )
+        .route(
+            "/admin/files/:file_id/verify",
+            axum::routing::post(handlers_private::verify_file),
+        )
         .route(
             "/admin/files",
             axum::routing::get(handlers_private::list_files),
         )
+        .route(
+            "/admin/tenants",
+            axum::routing::post(handlers_private::create_tenant),
+        )
+        .route(
+            "/admin/tenants",
+            axum::routing::get(handlers_private::list_tenants),
+        )
+        .route(
+            "/admin/tenants/:tenant_id",
+            axum::routing::get(handlers_private::get_tenant),
+        )
+        .route(
+            "/admin/tenants/:tenant_id",
+            axum::routing::put(handlers_private::update_tenant),
+        )
+        .route(
+            "/admin/tenants/:tenant_id",
+            axum::routing::delete(handlers_private::delete_tenant),
+        )
+        .route(
+            "/admin/reconcile",
+            axum::routing::post(handlers_private::reconcile_storage),
+        )
+        .route(
+            "/admin/api-keys",
+            axum::routing::post(handlers_private::create_api_key),
+        )
+        .route(
+            "/admin/api-keys/:api_key_id",
+            axum::routing::delete(handlers_private::revoke_api_key),
+        )
         .route(
             "/admin/links",
             axum::routing::post(handlers_private::create_link),
@@ -74,9 +176,106 @@ async fn setup_test_router() -> (
             "/admin/links/:link_id",
             axum::routing::delete(handlers_private::delete_link),
         )
-        .with_state(state.clone());
+        .route(
+            "/admin/links/:link_id/revoke",
+            axum::routing::post(handlers_private::revoke_link),
+        )
+        .layer(axum::middleware::from_fn(request_id::assign))
+        .with_state(state.clone())
+}
 
-    (router, state, guard)
+fn insert_test_tenant(state: &cargo_hold::app_state::AppState) -> Tenant {
+    let mut conn = state.db_pool.get().unwrap();
+    let tenant_oid = state.snowflake_gen.generate().unwrap();
+
+    diesel::insert_into(tenants::table)
+        .values(NewTenant {
+            oid: tenant_oid,
+            id: cargo_hold::snowflake::generate_prefixed_id("tenant", tenant_oid),
+            name: "Test Tenant".to_string(),
+            max_total_bytes: None,
+            max_file_count: None,
+            max_file_size_bytes: None,
+        })
+        .get_result(&mut conn)
+        .unwrap()
+}
+
+fn insert_test_file(
+    state: &cargo_hold::app_state::AppState,
+    tenant: &Tenant,
+    bytes: i64,
+    storage_key: &str,
+) -> File {
+    let mut conn = state.db_pool.get().unwrap();
+
+    let purpose: Purpose = purposes::table
+        .filter(purposes::slug.eq("test-purpose"))
+        .first(&mut conn)
+        .unwrap();
+
+    let file_oid = state.snowflake_gen.generate().unwrap();
+    diesel::insert_into(files::table)
+        .values(NewFile {
+            oid: file_oid,
+            id: cargo_hold::snowflake::generate_prefixed_id("file", file_oid),
+            tenant_oid: tenant.oid,
+            filename: "test.txt".to_string(),
+            purpose_oid: purpose.oid,
+            bytes,
+            storage_key: storage_key.to_string(),
+            content_type: "text/plain".to_string(),
+            sha256: None,
+            md5: None,
+            crc32c: None,
+            blob_oid: None,
+            expires_at: None,
+        })
+        .get_result(&mut conn)
+        .unwrap()
+}
+
+fn multipart_upload_request(tenant_id: &str, purpose: &str, content: &[u8]) -> Request<Body> {
+    multipart_upload_request_with_fields(tenant_id, &[("purpose", purpose)], content)
+}
+
+fn multipart_upload_request_with_fields(
+    tenant_id: &str,
+    fields: &[(&str, &str)],
+    content: &[u8],
+) -> Request<Body> {
+    let boundary = "----WebKitFormBoundary";
+
+    let mut body = Vec::new();
+    for (name, value) in fields {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                boundary, name, value
+            )
+            .as_bytes(),
+        );
+    }
+    body.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"test.txt\"\r\n\r\n",
+            boundary
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(content);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+    Request::builder()
+        .uri("/files")
+        .method("POST")
+        .header("X-Tenant-ID", tenant_id)
+        .header(
+            header::CONTENT_TYPE,
+            format!("multipart/form-data; boundary={}", boundary),
+        )
+        .body(Body::from(body))
+        .unwrap()
 }
 
 #[tokio::test]
@@ -100,7 +299,7 @@ async fn test_upload_file_missing_tenant_header() {
         .unwrap();
 
     let response = router.oneshot(request).await.unwrap();
-    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
 
     cleanup_test_db(&state.db_pool);
 }
@@ -119,6 +318,44 @@ async fn test_get_file_not_found() {
     let response = router.oneshot(request).await.unwrap();
     assert_eq!(response.status(), StatusCode::NOT_FOUND);
 
+    let request_id = response.headers()["x-request-id"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert!(request_id.starts_with("req_"));
+
+    let body = parse_json(response).await;
+    assert_eq!(body["error"]["code"], "not_found");
+    assert!(body["error"]["param"].is_null());
+    assert_eq!(body["error"]["request_id"], request_id);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_invalid_cursor_error_names_param() {
+    let (router, state, _guard) = setup_test_router().await;
+    let tenant = insert_test_tenant(&state);
+
+    let request = Request::builder()
+        .uri(format!(
+            "/admin/files?tenant_id={}&after=file_missing",
+            tenant.id
+        ))
+        .method("GET")
+        .header("X-Request-ID", "client-supplied-1")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(response.headers()["x-request-id"], "client-supplied-1");
+
+    let body = parse_json(response).await;
+    assert_eq!(body["error"]["code"], "invalid_parameter");
+    assert_eq!(body["error"]["param"], "after");
+    assert_eq!(body["error"]["request_id"], "client-supplied-1");
+
     cleanup_test_db(&state.db_pool);
 }
 
@@ -134,6 +371,9 @@ async fn test_list_files_pagination() {
             oid: tenant_oid,
             id: cargo_hold::snowflake::generate_prefixed_id("tenant", tenant_oid),
             name: "Test Tenant".to_string(),
+            max_total_bytes: None,
+            max_file_count: None,
+            max_file_size_bytes: None,
         })
         .get_result::<cargo_hold::models::Tenant>(&mut conn)
         .unwrap();
@@ -154,6 +394,12 @@ async fn test_list_files_pagination() {
                 purpose_oid: purpose.oid,
                 bytes: 100,
                 storage_key: format!("test-key-{}", i),
+                content_type: "text/plain".to_string(),
+                sha256: None,
+                md5: None,
+                crc32c: None,
+                blob_oid: None,
+                expires_at: None,
             })
             .execute(&mut conn)
             .unwrap();
@@ -191,6 +437,9 @@ async fn test_update_file() {
             oid: tenant_oid,
             id: cargo_hold::snowflake::generate_prefixed_id("tenant", tenant_oid),
             name: "Test Tenant".to_string(),
+            max_total_bytes: None,
+            max_file_count: None,
+            max_file_size_bytes: None,
         })
         .get_result::<cargo_hold::models::Tenant>(&mut conn)
         .unwrap();
@@ -210,6 +459,12 @@ async fn test_update_file() {
             purpose_oid: purpose.oid,
             bytes: 100,
             storage_key: "test-key".to_string(),
+            content_type: "text/plain".to_string(),
+            sha256: None,
+            md5: None,
+            crc32c: None,
+            blob_oid: None,
+            expires_at: None,
         })
         .get_result::<cargo_hold::models::File>(&mut conn)
         .unwrap();
@@ -237,3 +492,4024 @@ async fn test_update_file() {
 
     cleanup_test_db(&state.db_pool);
 }
+
+#[tokio::test]
+async fn test_upload_file_streams_to_storage() {
+    let mut server = mockito::Server::new_async().await;
+    let upload_mock = server
+        .mock(
+            "PUT",
+            mockito::Matcher::Regex(r"^/buckets/test-bucket/objects/".to_string()),
+        )
+        .match_body("hello streaming world")
+        .with_status(200)
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+
+    let request = multipart_upload_request("test-tenant", "document", b"hello streaming world");
+
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let file_response: FileResponse = serde_json::from_slice(&body_bytes).unwrap();
+
+    assert_eq!(file_response.bytes, 21);
+    assert_eq!(
+        file_response.sha256.as_deref(),
+        Some("e8dae0d48f17602939549198221e9b273b9dd4a20d5c391e39a165a9c7c5830d")
+    );
+    assert_eq!(file_response.filename, "test.txt");
+    assert_eq!(file_response.purpose, "document");
+    upload_mock.assert_async().await;
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_upload_file_sniffs_content_type() {
+    let mut server = mockito::Server::new_async().await;
+    let upload_mock = server
+        .mock(
+            "PUT",
+            mockito::Matcher::Regex(r"^/buckets/test-bucket/objects/".to_string()),
+        )
+        .match_header("Content-Type", "image/png")
+        .with_status(200)
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+
+    let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+    let request = multipart_upload_request("test-tenant", "image", png);
+
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let file_response: FileResponse = serde_json::from_slice(&body_bytes).unwrap();
+
+    assert_eq!(file_response.content_type, "image/png");
+    upload_mock.assert_async().await;
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_upload_file_exceeding_max_size_is_aborted() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("PUT", mockito::Matcher::Any)
+        .with_status(200)
+        .create_async()
+        .await;
+    let delete_mock = server
+        .mock("DELETE", mockito::Matcher::Any)
+        .with_status(204)
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+
+    let content = vec![b'a'; state.config.max_file_size_bytes as usize + 1];
+    let request = multipart_upload_request("test-tenant", "document", &content);
+
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    delete_mock.assert_async().await;
+
+    let mut conn = state.db_pool.get().unwrap();
+    let file_count: i64 = files::table.count().get_result(&mut conn).unwrap();
+    assert_eq!(file_count, 0);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_upload_text_field_size_is_capped() {
+    let (router, state, _guard) = setup_test_router().await;
+
+    let purpose = "a".repeat(2048);
+    let request = multipart_upload_request("test-tenant", &purpose, b"hello");
+
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = parse_json(response).await;
+    assert_eq!(body["error"]["param"], "purpose");
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_get_file_content_full() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/buckets/test-bucket/objects/content-key")
+        .with_status(200)
+        .with_body("0123456789")
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 10, "content-key");
+
+    let request = Request::builder()
+        .uri(format!("/files/{}/content", file.id))
+        .method("GET")
+        .header("X-Tenant-ID", &tenant.id)
+        .body(Body::empty())
+        .unwrap();
+
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::ACCEPT_RANGES], "bytes");
+    assert_eq!(response.headers()[header::CONTENT_LENGTH], "10");
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "text/plain");
+    assert_eq!(
+        response.headers()[header::CONTENT_DISPOSITION],
+        "inline; filename=\"test.txt\"; filename*=UTF-8''test.txt"
+    );
+
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&body_bytes[..], b"0123456789");
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_get_file_content_range() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/buckets/test-bucket/objects/content-key")
+        .match_header("Range", "bytes=2-5")
+        .with_status(206)
+        .with_header("Content-Range", "bytes 2-5/10")
+        .with_body("2345")
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 10, "content-key");
+
+    let request = Request::builder()
+        .uri(format!("/files/{}/content", file.id))
+        .method("GET")
+        .header("X-Tenant-ID", &tenant.id)
+        .header(header::RANGE, "bytes=2-5")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 2-5/10");
+    assert_eq!(response.headers()[header::CONTENT_LENGTH], "4");
+
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&body_bytes[..], b"2345");
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_get_file_content_range_when_storage_ignores_it() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/buckets/test-bucket/objects/content-key")
+        .with_status(200)
+        .with_body("0123456789")
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 10, "content-key");
+
+    let request = Request::builder()
+        .uri(format!("/files/{}/content", file.id))
+        .method("GET")
+        .header("X-Tenant-ID", &tenant.id)
+        .header(header::RANGE, "bytes=-3")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 7-9/10");
+
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&body_bytes[..], b"789");
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_get_file_content_unsatisfiable_range() {
+    let (router, state, _guard) = setup_test_router().await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 10, "content-key");
+
+    let request = Request::builder()
+        .uri(format!("/files/{}/content", file.id))
+        .method("GET")
+        .header("X-Tenant-ID", &tenant.id)
+        .header(header::RANGE, "bytes=10-")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes */10");
+
+    cleanup_test_db(&state.db_pool);
+}
+
+fn set_file_sha256(state: &cargo_hold::app_state::AppState, file: &File, sha256: &str) {
+    let mut conn = state.db_pool.get().unwrap();
+    diesel::update(files::table.find(file.oid))
+        .set(files::sha256.eq(sha256))
+        .execute(&mut conn)
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_get_file_content_emits_etag_and_digest() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/buckets/test-bucket/objects/content-key")
+        .with_status(200)
+        .with_body("hello world")
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 11, "content-key");
+    set_file_sha256(
+        &state,
+        &file,
+        "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+    );
+
+    let request = Request::builder()
+        .uri(format!("/files/{}/content", file.id))
+        .method("GET")
+        .header("X-Tenant-ID", &tenant.id)
+        .body(Body::empty())
+        .unwrap();
+
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::ETAG],
+        "\"b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9\""
+    );
+    assert_eq!(
+        response.headers()["Digest"],
+        "sha-256=uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek="
+    );
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_verify_file() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/buckets/test-bucket/objects/content-key")
+        .with_status(200)
+        .with_body("hello world")
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 11, "content-key");
+    set_file_sha256(
+        &state,
+        &file,
+        "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+    );
+
+    let request = Request::builder()
+        .uri(format!("/admin/files/{}/verify", file.id))
+        .method("POST")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let verification: FileVerificationResponse = serde_json::from_slice(&body_bytes).unwrap();
+
+    assert_eq!(verification.valid, Some(true));
+    assert!(!verification.backfilled);
+    assert_eq!(verification.actual_bytes, 11);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_verify_file_without_stored_hash_is_unverified() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/buckets/test-bucket/objects/content-key")
+        .with_status(200)
+        .with_body("hello world")
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 11, "content-key");
+
+    let request = Request::builder()
+        .uri(format!("/admin/files/{}/verify", file.id))
+        .method("POST")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = parse_json(response).await;
+    assert_eq!(body["valid"], serde_json::Value::Null);
+    assert_eq!(body["backfilled"], true);
+
+    let mut conn = state.db_pool.get().unwrap();
+    let stored: Option<String> = files::table
+        .find(file.oid)
+        .select(files::sha256)
+        .first(&mut conn)
+        .unwrap();
+    assert_eq!(
+        stored.as_deref(),
+        Some("b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9")
+    );
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_verify_file_detects_corruption() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/buckets/test-bucket/objects/content-key")
+        .with_status(200)
+        .with_body("hello wor1d")
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 11, "content-key");
+    set_file_sha256(
+        &state,
+        &file,
+        "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+    );
+
+    let request = Request::builder()
+        .uri(format!("/admin/files/{}/verify", file.id))
+        .method("POST")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = router.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let verification: FileVerificationResponse = serde_json::from_slice(&body_bytes).unwrap();
+
+    assert_eq!(verification.valid, Some(false));
+
+    cleanup_test_db(&state.db_pool);
+}
+
+async fn upload_and_parse(router: &Router, request: Request<Body>) -> FileResponse {
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    serde_json::from_slice(&body_bytes).unwrap()
+}
+
+fn dedup_config(storage_base_url: &str) -> cargo_hold::config::Config {
+    let mut config = create_test_config();
+    config.storage_base_url = storage_base_url.to_string();
+    config.dedup_enabled = true;
+    config
+}
+
+#[tokio::test]
+async fn test_dedup_shares_blob_between_identical_uploads() {
+    let mut server = mockito::Server::new_async().await;
+    let upload_mock = server
+        .mock("PUT", mockito::Matcher::Any)
+        .with_status(200)
+        .expect(2)
+        .create_async()
+        .await;
+    let delete_mock = server
+        .mock("DELETE", mockito::Matcher::Any)
+        .with_status(204)
+        .expect(1)
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_config(dedup_config(&server.url())).await;
+    let tenant = insert_test_tenant(&state);
+
+    let first = upload_and_parse(
+        &router,
+        multipart_upload_request(&tenant.id, "document", b"same content"),
+    )
+    .await;
+    let second = upload_and_parse(
+        &router,
+        multipart_upload_request(&tenant.id, "document", b"same content"),
+    )
+    .await;
+
+    upload_mock.assert_async().await;
+    // The second, redundant object is discarded.
+    delete_mock.assert_async().await;
+
+    let mut conn = state.db_pool.get().unwrap();
+    let stored: Vec<File> = files::table
+        .filter(files::id.eq_any([&first.id, &second.id]))
+        .load(&mut conn)
+        .unwrap();
+    assert_eq!(stored.len(), 2);
+    assert_eq!(stored[0].storage_key, stored[1].storage_key);
+    assert_eq!(stored[0].blob_oid, stored[1].blob_oid);
+
+    let blob: Blob = blobs::table
+        .find(stored[0].blob_oid.unwrap())
+        .first(&mut conn)
+        .unwrap();
+    assert_eq!(blob.ref_count, 2);
+
+    let tenant: Tenant = tenants::table.find(tenant.oid).first(&mut conn).unwrap();
+    assert_eq!(tenant.total_files_bytes, 24);
+    assert_eq!(tenant.file_count, 2);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_dedup_skips_upload_when_hash_is_known() {
+    let mut server = mockito::Server::new_async().await;
+    let upload_mock = server
+        .mock("PUT", mockito::Matcher::Any)
+        .with_status(200)
+        .expect(1)
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_config(dedup_config(&server.url())).await;
+    let tenant = insert_test_tenant(&state);
+
+    let first = upload_and_parse(
+        &router,
+        multipart_upload_request(&tenant.id, "document", b"hello world"),
+    )
+    .await;
+
+    let sha256 = first.sha256.clone().unwrap();
+    let second = upload_and_parse(
+        &router,
+        multipart_upload_request_with_fields(
+            &tenant.id,
+            &[("purpose", "document"), ("sha256", &sha256)],
+            b"hello world",
+        ),
+    )
+    .await;
+
+    upload_mock.assert_async().await;
+    assert_eq!(second.sha256, first.sha256);
+
+    let mismatched = router
+        .clone()
+        .oneshot(multipart_upload_request_with_fields(
+            &tenant.id,
+            &[("purpose", "document"), ("sha256", &sha256)],
+            b"something else",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(mismatched.status(), StatusCode::BAD_REQUEST);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_dedup_deletes_object_with_last_reference() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("PUT", mockito::Matcher::Any)
+        .with_status(200)
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_config(dedup_config(&server.url())).await;
+    let tenant = insert_test_tenant(&state);
+
+    let first = upload_and_parse(
+        &router,
+        multipart_upload_request(&tenant.id, "document", b"shared"),
+    )
+    .await;
+    let sha256 = first.sha256.clone().unwrap();
+    let second = upload_and_parse(
+        &router,
+        multipart_upload_request_with_fields(
+            &tenant.id,
+            &[("purpose", "document"), ("sha256", &sha256)],
+            b"shared",
+        ),
+    )
+    .await;
+
+    let delete_mock = server
+        .mock("DELETE", mockito::Matcher::Any)
+        .with_status(204)
+        .expect(1)
+        .create_async()
+        .await;
+
+    for file_id in [&first.id, &second.id] {
+        let response = router
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/admin/files/{}?permanent=true", file_id))
+                    .method("DELETE")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    delete_mock.assert_async().await;
+
+    let mut conn = state.db_pool.get().unwrap();
+    let blob_count: i64 = blobs::table.count().get_result(&mut conn).unwrap();
+    assert_eq!(blob_count, 0);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+fn auth_config(methods: Vec<cargo_hold::auth::AuthMethod>) -> cargo_hold::config::Config {
+    let mut config = create_test_config();
+    config.auth_methods = methods;
+    config.jwt_hs256_secret = Some("test-secret".to_string());
+    config
+}
+
+fn get_file_request(file_id: &str, auth_header: (&str, &str)) -> Request<Body> {
+    Request::builder()
+        .uri(format!("/files/{}", file_id))
+        .method("GET")
+        .header(auth_header.0, auth_header.1)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_api_key_authentication() {
+    use cargo_hold::auth::AuthMethod;
+
+    let (router, state, _guard) =
+        setup_test_router_with_config(auth_config(vec![AuthMethod::ApiKey])).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 10, "content-key");
+
+    let request = Request::builder()
+        .uri("/admin/api-keys")
+        .method("POST")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            json!({ "tenant_id": tenant.id, "name": "ci" }).to_string(),
+        ))
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let api_key: ApiKeyResponse = serde_json::from_slice(&body_bytes).unwrap();
+    let key = api_key.key.unwrap();
+
+    let response = router
+        .clone()
+        .oneshot(get_file_request(&file.id, ("X-API-Key", &key)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let bearer = format!("Bearer {}", key);
+    let response = router
+        .clone()
+        .oneshot(get_file_request(&file.id, ("Authorization", &bearer)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // The legacy header is ignored when only API keys are enabled.
+    let response = router
+        .clone()
+        .oneshot(get_file_request(&file.id, ("X-Tenant-ID", &tenant.id)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let request = Request::builder()
+        .uri(format!("/admin/api-keys/{}", api_key.id))
+        .method("DELETE")
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = router
+        .clone()
+        .oneshot(get_file_request(&file.id, ("X-API-Key", &key)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_jwt_authentication() {
+    use cargo_hold::auth::AuthMethod;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+
+    let (router, state, _guard) =
+        setup_test_router_with_config(auth_config(vec![AuthMethod::ApiKey, AuthMethod::Jwt])).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 10, "content-key");
+
+    let exp = chrono::Utc::now().timestamp() + 300;
+    let sign = |secret: &str, tenant_id: &str| {
+        encode(
+            &Header::default(),
+            &json!({ "tenant_id": tenant_id, "exp": exp }),
+            &EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .unwrap()
+    };
+
+    let bearer = format!("Bearer {}", sign("test-secret", &tenant.id));
+    let response = router
+        .clone()
+        .oneshot(get_file_request(&file.id, ("Authorization", &bearer)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let forged = format!("Bearer {}", sign("wrong-secret", &tenant.id));
+    let response = router
+        .clone()
+        .oneshot(get_file_request(&file.id, ("Authorization", &forged)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+fn json_request(method: &str, uri: &str, body: serde_json::Value) -> Request<Body> {
+    Request::builder()
+        .uri(uri)
+        .method(method)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+async fn parse_tenant(response: axum::response::Response) -> TenantResponse {
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    serde_json::from_slice(&body_bytes).unwrap()
+}
+
+#[tokio::test]
+async fn test_tenant_crud() {
+    let (router, state, _guard) = setup_test_router().await;
+
+    let response = router
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/admin/tenants",
+            json!({ "id": "acme", "name": "Acme" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let created = parse_tenant(response).await;
+    assert_eq!(created.id, "acme");
+    assert_eq!(created.object, "tenant");
+    assert_eq!(created.file_count, 0);
+    assert_eq!(created.total_files_bytes, 0);
+
+    let response = router
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/admin/tenants",
+            json!({ "id": "acme", "name": "Other" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let response = router
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/admin/tenants",
+            json!({ "name": "Generated" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let generated = parse_tenant(response).await;
+    assert!(generated.id.starts_with("tenant_"));
+
+    let response = router
+        .clone()
+        .oneshot(json_request(
+            "PUT",
+            "/admin/tenants/acme",
+            json!({ "name": "Acme Corp" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(parse_tenant(response).await.name, "Acme Corp");
+
+    let tenant: Tenant = {
+        let mut conn = state.db_pool.get().unwrap();
+        tenants::table
+            .filter(tenants::id.eq("acme"))
+            .first(&mut conn)
+            .unwrap()
+    };
+    insert_test_file(&state, &tenant, 42, "acme/file");
+
+    let response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/admin/tenants/acme")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let fetched = parse_tenant(response).await;
+    assert_eq!(fetched.name, "Acme Corp");
+
+    let response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/admin/tenants?limit=1")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let page: ListTenantsResponse = serde_json::from_slice(&body_bytes).unwrap();
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].id, generated.id);
+    assert!(page.pagination.has_more_after);
+
+    let response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/admin/tenants/missing")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_delete_tenant_removes_stored_objects() {
+    let mut server = mockito::Server::new_async().await;
+    let delete_mock = server
+        .mock(
+            "DELETE",
+            mockito::Matcher::Regex(r"^/buckets/test-bucket/objects/tenant-objects/".to_string()),
+        )
+        .with_status(204)
+        .expect(2)
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 10, "tenant-objects/a");
+    insert_test_file(&state, &tenant, 20, "tenant-objects/b");
+
+    let response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/admin/tenants/{}", tenant.id))
+                .method("DELETE")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    delete_mock.assert_async().await;
+    assert!(pending_deletion_keys(&state).is_empty());
+
+    let mut conn = state.db_pool.get().unwrap();
+    let tenant_count: i64 = tenants::table.count().get_result(&mut conn).unwrap();
+    let file_count: i64 = files::table
+        .filter(files::id.eq(&file.id))
+        .count()
+        .get_result(&mut conn)
+        .unwrap();
+    assert_eq!(tenant_count, 0);
+    assert_eq!(file_count, 0);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_tenant_ids_are_restricted_to_safe_characters() {
+    let (router, state, _guard) = setup_test_router().await;
+
+    for id in ["acme/x", "..", "a?b", "a b"] {
+        let response = router
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/admin/tenants",
+                json!({ "id": id, "name": "Acme" }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(parse_json(response).await["error"]["param"], "id");
+
+        let response = router
+            .clone()
+            .oneshot(get_file_request("file_missing", ("X-Tenant-ID", id)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(parse_json(response).await["error"]["param"], "X-Tenant-ID");
+    }
+
+    let mut conn = state.db_pool.get().unwrap();
+    let tenant_count: i64 = tenants::table.count().get_result(&mut conn).unwrap();
+    assert_eq!(tenant_count, 0);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_unknown_tenant_rejected_without_auto_create() {
+    let mut config = create_test_config();
+    config.tenant_auto_create = false;
+    let (router, state, _guard) = setup_test_router_with_config(config).await;
+
+    let response = router
+        .oneshot(get_file_request("file_missing", ("X-Tenant-ID", "typo")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let mut conn = state.db_pool.get().unwrap();
+    let tenant_count: i64 = tenants::table.count().get_result(&mut conn).unwrap();
+    assert_eq!(tenant_count, 0);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_auto_created_tenant_is_reused() {
+    let (router, state, _guard) = setup_test_router().await;
+
+    for _ in 0..2 {
+        let response = router
+            .clone()
+            .oneshot(get_file_request("file_missing", ("X-Tenant-ID", "acme")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    let mut conn = state.db_pool.get().unwrap();
+    let tenant_ids: Vec<String> = tenants::table.select(tenants::id).load(&mut conn).unwrap();
+    assert_eq!(tenant_ids, vec!["acme".to_string()]);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+async fn parse_json(response: axum::response::Response) -> serde_json::Value {
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    serde_json::from_slice(&body_bytes).unwrap()
+}
+
+#[tokio::test]
+async fn test_tenant_quota_settings() {
+    let (router, state, _guard) = setup_test_router().await;
+
+    let response = router
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/admin/tenants",
+            json!({ "id": "acme", "name": "Acme", "max_file_count": 5 }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(parse_tenant(response).await.max_file_count, Some(5));
+
+    let response = router
+        .clone()
+        .oneshot(json_request(
+            "PUT",
+            "/admin/tenants/acme",
+            json!({ "max_total_bytes": 1000, "max_file_count": null }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let tenant = parse_tenant(response).await;
+    assert_eq!(tenant.name, "Acme");
+    assert_eq!(tenant.max_total_bytes, Some(1000));
+    assert_eq!(tenant.max_file_count, None);
+
+    let response = router
+        .clone()
+        .oneshot(json_request(
+            "PUT",
+            "/admin/tenants/acme",
+            json!({ "max_file_size_bytes": -1 }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_upload_rejected_when_file_count_quota_reached() {
+    let mut config = create_test_config();
+    config.tenant_max_file_count = Some(1);
+    let (router, state, _guard) = setup_test_router_with_config(config).await;
+    let tenant = insert_test_tenant(&state);
+    insert_test_file(&state, &tenant, 10, "existing");
+
+    {
+        let mut conn = state.db_pool.get().unwrap();
+        diesel::update(tenants::table.find(tenant.oid))
+            .set(tenants::file_count.eq(1))
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    let response = router
+        .oneshot(multipart_upload_request(&tenant.id, "document", b"more"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let body = parse_json(response).await;
+    assert_eq!(body["error"]["code"], "quota_exceeded");
+    assert_eq!(body["error"]["param"], "max_file_count");
+    assert_eq!(body["error"]["limit"], 1);
+    assert_eq!(body["error"]["current"], 1);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_upload_rejected_when_byte_quota_exceeded() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("PUT", mockito::Matcher::Any)
+        .with_status(200)
+        .create_async()
+        .await;
+    let delete_mock = server
+        .mock("DELETE", mockito::Matcher::Any)
+        .with_status(204)
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+    {
+        let mut conn = state.db_pool.get().unwrap();
+        diesel::update(tenants::table.find(tenant.oid))
+            .set((
+                tenants::max_total_bytes.eq(Some(10)),
+                tenants::total_files_bytes.eq(6),
+            ))
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    let response = router
+        .oneshot(multipart_upload_request(
+            &tenant.id,
+            "document",
+            b"too long",
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    delete_mock.assert_async().await;
+
+    let body = parse_json(response).await;
+    assert_eq!(body["error"]["code"], "quota_exceeded");
+    assert_eq!(body["error"]["param"], "max_total_bytes");
+    assert_eq!(body["error"]["limit"], 10);
+    assert_eq!(body["error"]["current"], 6);
+
+    let mut conn = state.db_pool.get().unwrap();
+    let tenant: Tenant = tenants::table.find(tenant.oid).first(&mut conn).unwrap();
+    assert_eq!(tenant.total_files_bytes, 6);
+    assert_eq!(tenant.file_count, 0);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_concurrent_uploads_cannot_overrun_quota() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("PUT", mockito::Matcher::Any)
+        .with_status(200)
+        .create_async()
+        .await;
+    server
+        .mock("DELETE", mockito::Matcher::Any)
+        .with_status(204)
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+    {
+        let mut conn = state.db_pool.get().unwrap();
+        diesel::update(tenants::table.find(tenant.oid))
+            .set(tenants::max_total_bytes.eq(Some(10)))
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    // Each upload fits on its own, but only one of them fits in the quota.
+    let uploads = (0..4).map(|_| {
+        router
+            .clone()
+            .oneshot(multipart_upload_request(&tenant.id, "document", b"123456"))
+    });
+    let responses = futures::future::join_all(uploads).await;
+
+    let statuses: Vec<StatusCode> = responses.into_iter().map(|r| r.unwrap().status()).collect();
+    assert_eq!(statuses.iter().filter(|s| **s == StatusCode::OK).count(), 1);
+    assert!(statuses
+        .iter()
+        .all(|s| *s == StatusCode::OK || *s == StatusCode::PAYLOAD_TOO_LARGE));
+
+    let mut conn = state.db_pool.get().unwrap();
+    let tenant: Tenant = tenants::table.find(tenant.oid).first(&mut conn).unwrap();
+    assert_eq!(tenant.total_files_bytes, 6);
+    assert_eq!(tenant.file_count, 1);
+    let file_count: i64 = files::table.count().get_result(&mut conn).unwrap();
+    assert_eq!(file_count, 1);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+/// Makes every `operation` on `table` fail until dropped.
+struct InjectedFailure {
+    pool: cargo_hold::db::DbPool,
+    table: &'static str,
+}
+
+impl InjectedFailure {
+    fn new(state: &cargo_hold::app_state::AppState, operation: &str, table: &'static str) -> Self {
+        let mut conn = state.db_pool.get().unwrap();
+        diesel::sql_query(
+            "CREATE OR REPLACE FUNCTION injected_failure() RETURNS trigger AS $$ \
+             BEGIN RAISE EXCEPTION 'injected failure'; END; $$ LANGUAGE plpgsql",
+        )
+        .execute(&mut conn)
+        .unwrap();
+        diesel::sql_query(format!(
+            "CREATE TRIGGER injected_failure BEFORE {} ON {} \
+             FOR EACH ROW EXECUTE FUNCTION injected_failure()",
+            operation, table
+        ))
+        .execute(&mut conn)
+        .unwrap();
+
+        Self {
+            pool: state.db_pool.clone(),
+            table,
+        }
+    }
+}
+
+impl Drop for InjectedFailure {
+    fn drop(&mut self) {
+        let mut conn = self.pool.get().unwrap();
+        diesel::sql_query(format!(
+            "DROP TRIGGER IF EXISTS injected_failure ON {}",
+            self.table
+        ))
+        .execute(&mut conn)
+        .unwrap();
+    }
+}
+
+fn set_tenant_counters(
+    state: &cargo_hold::app_state::AppState,
+    tenant: &Tenant,
+    bytes: i64,
+    count: i64,
+) {
+    let mut conn = state.db_pool.get().unwrap();
+    diesel::update(tenants::table.find(tenant.oid))
+        .set((
+            tenants::total_files_bytes.eq(bytes),
+            tenants::file_count.eq(count),
+        ))
+        .execute(&mut conn)
+        .unwrap();
+}
+
+fn assert_tenant_counters(
+    state: &cargo_hold::app_state::AppState,
+    tenant: &Tenant,
+    bytes: i64,
+    count: i64,
+) {
+    let mut conn = state.db_pool.get().unwrap();
+    let tenant: Tenant = tenants::table.find(tenant.oid).first(&mut conn).unwrap();
+    assert_eq!(tenant.total_files_bytes, bytes);
+    assert_eq!(tenant.file_count, count);
+}
+
+fn pending_deletion_keys(state: &cargo_hold::app_state::AppState) -> Vec<String> {
+    let mut conn = state.db_pool.get().unwrap();
+    pending_deletions::table
+        .select(pending_deletions::storage_key)
+        .load(&mut conn)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_upload_storage_failure_records_nothing() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("PUT", mockito::Matcher::Any)
+        .with_status(500)
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+
+    let response = router
+        .oneshot(multipart_upload_request(&tenant.id, "document", b"payload"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    let mut conn = state.db_pool.get().unwrap();
+    let file_count: i64 = files::table.count().get_result(&mut conn).unwrap();
+    assert_eq!(file_count, 0);
+    assert_tenant_counters(&state, &tenant, 0, 0);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_upload_db_failures_discard_object() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("PUT", mockito::Matcher::Any)
+        .with_status(200)
+        .create_async()
+        .await;
+    let delete_mock = server
+        .mock("DELETE", mockito::Matcher::Any)
+        .with_status(204)
+        .expect(2)
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+
+    // Once for the counter update, once for the file insert.
+    for (operation, table) in [("UPDATE", "tenants"), ("INSERT", "files")] {
+        let _failure = InjectedFailure::new(&state, operation, table);
+
+        let response = router
+            .clone()
+            .oneshot(multipart_upload_request(&tenant.id, "document", b"payload"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    delete_mock.assert_async().await;
+
+    let mut conn = state.db_pool.get().unwrap();
+    let file_count: i64 = files::table.count().get_result(&mut conn).unwrap();
+    assert_eq!(file_count, 0);
+    assert_tenant_counters(&state, &tenant, 0, 0);
+    assert!(pending_deletion_keys(&state).is_empty());
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_upload_failed_compensation_is_recorded() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("PUT", mockito::Matcher::Any)
+        .with_status(200)
+        .create_async()
+        .await;
+    server
+        .mock("DELETE", mockito::Matcher::Any)
+        .with_status(500)
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+    let _failure = InjectedFailure::new(&state, "INSERT", "files");
+
+    let response = router
+        .oneshot(multipart_upload_request(&tenant.id, "document", b"payload"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    let keys = pending_deletion_keys(&state);
+    assert_eq!(keys.len(), 1);
+    assert!(keys[0].starts_with(&format!("{}/file_", tenant.id)));
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_delete_file_db_failure_keeps_object() {
+    let mut server = mockito::Server::new_async().await;
+    let delete_mock = server
+        .mock("DELETE", mockito::Matcher::Any)
+        .with_status(204)
+        .expect(0)
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 10, "kept-object");
+    set_tenant_counters(&state, &tenant, 10, 1);
+
+    for (operation, table) in [("DELETE", "files"), ("UPDATE", "tenants")] {
+        let _failure = InjectedFailure::new(&state, operation, table);
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/admin/files/{}?permanent=true", file.id))
+                    .method("DELETE")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    delete_mock.assert_async().await;
+
+    let mut conn = state.db_pool.get().unwrap();
+    let file_count: i64 = files::table.count().get_result(&mut conn).unwrap();
+    assert_eq!(file_count, 1);
+    assert_tenant_counters(&state, &tenant, 10, 1);
+    assert!(pending_deletion_keys(&state).is_empty());
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_delete_file_storage_failure_is_recorded() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("DELETE", "/buckets/test-bucket/objects/flaky-object")
+        .with_status(500)
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 10, "flaky-object");
+    set_tenant_counters(&state, &tenant, 10, 1);
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri(format!("/admin/files/{}?permanent=true", file.id))
+                .method("DELETE")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let mut conn = state.db_pool.get().unwrap();
+    let file_count: i64 = files::table.count().get_result(&mut conn).unwrap();
+    assert_eq!(file_count, 0);
+    assert_tenant_counters(&state, &tenant, 0, 0);
+
+    let pending: PendingDeletion = pending_deletions::table.first(&mut conn).unwrap();
+    assert_eq!(pending.storage_key, "flaky-object");
+    assert_eq!(pending.attempts, 1);
+    assert!(pending.last_error.is_some());
+
+    cleanup_test_db(&state.db_pool);
+}
+
+/// Storage holds a referenced object, an old orphan and a fresh orphan; the
+/// database has a file whose object is gone and inflated tenant counters.
+async fn setup_reconcile_fixture(
+    server: &mut mockito::ServerGuard,
+) -> (
+    Router,
+    cargo_hold::app_state::AppState,
+    MutexGuard<'static, ()>,
+    Tenant,
+) {
+    let (router, state, guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+    insert_test_file(&state, &tenant, 10, &format!("{}/known", tenant.id));
+    insert_test_file(&state, &tenant, 5, &format!("{}/missing", tenant.id));
+    set_tenant_counters(&state, &tenant, 999, 7);
+
+    let fresh = chrono::Utc::now().to_rfc3339();
+    server
+        .mock("GET", "/buckets/test-bucket/objects")
+        .match_query(mockito::Matcher::UrlEncoded(
+            "prefix".into(),
+            format!("{}/", tenant.id),
+        ))
+        .with_status(200)
+        .with_body(
+            json!({
+                "objects": [
+                    { "key": format!("{}/known", tenant.id), "size": 10, "last_modified": "2026-01-01T00:00:00Z" },
+                    { "key": format!("{}/old-orphan", tenant.id), "size": 3, "last_modified": "2026-01-01T00:00:00Z" },
+                    { "key": format!("{}/new-orphan", tenant.id), "size": 4, "last_modified": fresh },
+                ],
+                "next_cursor": null
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await;
+
+    (router, state, guard, tenant)
+}
+
+async fn reconcile_request(router: &Router, mode: &str) -> ReconciliationResponse {
+    let response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/admin/reconcile?mode={}", mode))
+                .method("POST")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    serde_json::from_slice(&body_bytes).unwrap()
+}
+
+#[tokio::test]
+async fn test_reconcile_dry_run_only_reports() {
+    let mut server = mockito::Server::new_async().await;
+    let delete_mock = server
+        .mock("DELETE", mockito::Matcher::Any)
+        .with_status(204)
+        .expect(0)
+        .create_async()
+        .await;
+    let (router, state, _guard, tenant) = setup_reconcile_fixture(&mut server).await;
+
+    let report = reconcile_request(&router, "dry_run").await;
+
+    assert_eq!(report.mode, "dry_run");
+    assert_eq!(report.tenants_checked, 1);
+    assert_eq!(report.objects_scanned, 3);
+    assert!(report.errors.is_empty());
+
+    assert_eq!(report.orphans.len(), 2);
+    let old = &report.orphans[0];
+    assert_eq!(old.storage_key, format!("{}/old-orphan", tenant.id));
+    assert!(!old.recent);
+    assert!(!old.deleted);
+    assert!(report.orphans[1].recent);
+
+    assert_eq!(
+        report.missing_objects,
+        vec![format!("{}/missing", tenant.id)]
+    );
+
+    assert_eq!(report.counter_drift.len(), 1);
+    let drift = &report.counter_drift[0];
+    assert_eq!((drift.recorded_bytes, drift.actual_bytes), (999, 15));
+    assert_eq!((drift.recorded_count, drift.actual_count), (7, 2));
+    assert!(!drift.fixed);
+
+    delete_mock.assert_async().await;
+    assert_tenant_counters(&state, &tenant, 999, 7);
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri("/admin/reconcile?mode=nuke")
+                .method("POST")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_reconcile_fix_repairs_drift() {
+    let mut server = mockito::Server::new_async().await;
+    let (router, state, _guard, tenant) = setup_reconcile_fixture(&mut server).await;
+
+    let orphan_delete = server
+        .mock(
+            "DELETE",
+            format!("/buckets/test-bucket/objects/{}/old-orphan", tenant.id).as_str(),
+        )
+        .with_status(204)
+        .expect(1)
+        .create_async()
+        .await;
+    let pending_delete = server
+        .mock("DELETE", "/buckets/test-bucket/objects/queued-object")
+        .with_status(204)
+        .expect(1)
+        .create_async()
+        .await;
+
+    {
+        let mut conn = state.db_pool.get().unwrap();
+        diesel::insert_into(pending_deletions::table)
+            .values(NewPendingDeletion {
+                oid: state.snowflake_gen.generate().unwrap(),
+                storage_key: "queued-object".to_string(),
+                attempts: 1,
+                last_error: Some("storage unavailable".to_string()),
+            })
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    let report = reconcile_request(&router, "fix").await;
+
+    assert_eq!(report.mode, "fix");
+    assert_eq!(report.pending_deletions_cleared, 1);
+    assert_eq!(report.pending_deletions, 0);
+    assert!(report.orphans[0].deleted);
+    assert!(!report.orphans[1].deleted);
+    assert!(report.counter_drift[0].fixed);
+
+    orphan_delete.assert_async().await;
+    pending_delete.assert_async().await;
+    assert_tenant_counters(&state, &tenant, 15, 2);
+    assert!(pending_deletion_keys(&state).is_empty());
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_reconcile_ignores_tenants_sharing_a_prefix() {
+    let mut server = mockito::Server::new_async().await;
+    let delete_mock = server
+        .mock("DELETE", mockito::Matcher::Any)
+        .with_status(204)
+        .expect(0)
+        .create_async()
+        .await;
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+
+    // Ids like `acme/x` are rejected now, but may predate that check.
+    let tenant_with_id = |id: &str| {
+        let mut conn = state.db_pool.get().unwrap();
+        diesel::insert_into(tenants::table)
+            .values(NewTenant {
+                oid: state.snowflake_gen.generate().unwrap(),
+                id: id.to_string(),
+                name: id.to_string(),
+                max_total_bytes: None,
+                max_file_count: None,
+                max_file_size_bytes: None,
+            })
+            .get_result::<Tenant>(&mut conn)
+            .unwrap()
+    };
+    let acme = tenant_with_id("acme");
+    let nested = tenant_with_id("acme/x");
+    insert_test_file(&state, &acme, 10, "acme/known");
+    insert_test_file(&state, &nested, 10, "acme/x/file_1");
+    set_tenant_counters(&state, &acme, 10, 1);
+
+    server
+        .mock("GET", "/buckets/test-bucket/objects")
+        .match_query(mockito::Matcher::UrlEncoded(
+            "prefix".into(),
+            "acme/".into(),
+        ))
+        .with_status(200)
+        .with_body(
+            json!({
+                "objects": [
+                    { "key": "acme/known", "size": 10, "last_modified": "2026-01-01T00:00:00Z" },
+                    { "key": "acme/x/file_1", "size": 10, "last_modified": "2026-01-01T00:00:00Z" },
+                ],
+                "next_cursor": null
+            })
+            .to_string(),
+        )
+        .create_async()
+        .await;
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri("/admin/reconcile?mode=fix&tenant_id=acme")
+                .method("POST")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let report: ReconciliationResponse =
+        serde_json::from_value(parse_json(response).await).unwrap();
+
+    assert_eq!(report.objects_scanned, 1);
+    assert!(report.orphans.is_empty());
+    assert!(report.missing_objects.is_empty());
+    delete_mock.assert_async().await;
+
+    cleanup_test_db(&state.db_pool);
+}
+
+async fn create_link(router: &Router, body: serde_json::Value) -> FileLinkResponse {
+    let response = router
+        .clone()
+        .oneshot(json_request("POST", "/admin/links", body))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    serde_json::from_value(parse_json(response).await).unwrap()
+}
+
+/// Links can no longer be created already expired, so move one into the past.
+async fn create_expired_link(
+    router: &Router,
+    state: &cargo_hold::app_state::AppState,
+    file: &File,
+) -> FileLinkResponse {
+    let link = create_link(router, json!({ "file_id": file.id, "expires_in": 3600 })).await;
+
+    let mut conn = state.db_pool.get().unwrap();
+    diesel::update(file_links::table.filter(file_links::id.eq(&link.id)))
+        .set(
+            file_links::expires_at
+                .eq(chrono::Utc::now().naive_utc() - chrono::Duration::seconds(60)),
+        )
+        .execute(&mut conn)
+        .unwrap();
+
+    let response = router
+        .clone()
+        .oneshot(get_request(&format!("/admin/links/{}", link.id)))
+        .await
+        .unwrap();
+    serde_json::from_value(parse_json(response).await).unwrap()
+}
+
+fn link_download_request(key: &str, accept: Option<&str>) -> Request<Body> {
+    let mut builder = Request::builder().uri(format!("/f/{}", key)).method("GET");
+    if let Some(accept) = accept {
+        builder = builder.header(header::ACCEPT, accept);
+    }
+    builder.body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn test_link_states_have_distinct_statuses() {
+    let (router, state, _guard) = setup_test_router().await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 5, "link-key");
+
+    let expired = create_expired_link(&router, &state, &file).await;
+    assert_eq!(expired.state, "expired");
+
+    let scheduled = create_link(
+        &router,
+        json!({
+            "file_id": file.id,
+            "expires_in": 3600,
+            "starts_at": chrono::Utc::now().timestamp() + 600,
+        }),
+    )
+    .await;
+    assert_eq!(scheduled.state, "not_yet_valid");
+
+    let revoked = create_link(&router, json!({ "file_id": file.id, "expires_in": 3600 })).await;
+    assert_eq!(revoked.state, "active");
+
+    let response = router
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            &format!("/admin/links/{}/revoke", revoked.id),
+            json!({}),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let revoked: FileLinkResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    assert_eq!(revoked.state, "revoked");
+    assert!(revoked.revoked_at.is_some());
+
+    for (key, status, message) in [
+        (&expired.key, StatusCode::GONE, "Link expired"),
+        (&revoked.key, StatusCode::GONE, "Link revoked"),
+        (
+            &scheduled.key,
+            StatusCode::FORBIDDEN,
+            "Link is not yet valid",
+        ),
+    ] {
+        let response = router
+            .clone()
+            .oneshot(link_download_request(key, None))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), status);
+        let body = parse_json(response).await;
+        assert_eq!(body["error"]["message"], message);
+    }
+
+    let response = router
+        .oneshot(link_download_request("missing-key", None))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_link_errors_render_branded_html_for_browsers() {
+    let mut config = create_test_config();
+    config.brand_name = Some("Acme <Files>".to_string());
+    let (router, state, _guard) = setup_test_router_with_config(config).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 5, "link-key");
+
+    let expired = create_expired_link(&router, &state, &file).await;
+
+    let response = router
+        .clone()
+        .oneshot(link_download_request(
+            &expired.key,
+            Some("text/html,application/xhtml+xml,*/*;q=0.8"),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::GONE);
+    assert!(response.headers()[header::CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let html = String::from_utf8(body.to_vec()).unwrap();
+    assert!(html.contains("Link no longer available"));
+    assert!(html.contains("Acme &lt;Files&gt;"));
+
+    let response = router
+        .oneshot(link_download_request("missing-key", Some("text/html")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(response.headers()[header::CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_link_download_limit() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/buckets/test-bucket/objects/link-key")
+        .with_status(200)
+        .with_body("hello")
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 5, "link-key");
+
+    let link = create_link(
+        &router,
+        json!({ "file_id": file.id, "expires_in": 3600, "max_downloads": 1 }),
+    )
+    .await;
+    assert_eq!(link.max_downloads, Some(1));
+    assert_eq!(link.download_count, 0);
+    assert!(link.last_accessed_at.is_none());
+
+    let response = router
+        .clone()
+        .oneshot(link_download_request(&link.key, None))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = router
+        .clone()
+        .oneshot(link_download_request(&link.key, None))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::GONE);
+
+    let response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/admin/links/{}", link.id))
+                .method("GET")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let link: FileLinkResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    assert_eq!(link.state, "exhausted");
+    assert_eq!(link.download_count, 1);
+    assert!(link.last_accessed_at.is_some());
+
+    let response = router
+        .oneshot(json_request(
+            "POST",
+            "/admin/links",
+            json!({ "file_id": file.id, "expires_in": 3600, "max_downloads": 0 }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        parse_json(response).await["error"]["param"],
+        "max_downloads"
+    );
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_concurrent_link_downloads_respect_limit() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/buckets/test-bucket/objects/link-key")
+        .with_status(200)
+        .with_body("hello")
+        .expect(2)
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 5, "link-key");
+
+    let link = create_link(
+        &router,
+        json!({ "file_id": file.id, "expires_in": 3600, "max_downloads": 2 }),
+    )
+    .await;
+
+    let downloads = (0..8).map(|_| {
+        let router = router.clone();
+        let key = link.key.clone();
+        tokio::spawn(async move {
+            router
+                .oneshot(link_download_request(&key, None))
+                .await
+                .unwrap()
+                .status()
+        })
+    });
+    let statuses = futures::future::join_all(downloads).await;
+
+    let served = statuses
+        .iter()
+        .filter(|status| *status.as_ref().unwrap() == StatusCode::OK)
+        .count();
+    assert_eq!(served, 2);
+
+    let mut conn = state.db_pool.get().unwrap();
+    let download_count: i64 = file_links::table
+        .filter(file_links::id.eq(&link.id))
+        .select(file_links::download_count)
+        .first(&mut conn)
+        .unwrap();
+    assert_eq!(download_count, 2);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_failed_link_download_is_not_counted() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/buckets/test-bucket/objects/link-key")
+        .with_status(500)
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 5, "link-key");
+
+    let link = create_link(
+        &router,
+        json!({ "file_id": file.id, "expires_in": 3600, "max_downloads": 1 }),
+    )
+    .await;
+
+    let response = router
+        .oneshot(link_download_request(&link.key, None))
+        .await
+        .unwrap();
+    assert!(response.status().is_server_error());
+
+    let mut conn = state.db_pool.get().unwrap();
+    let download_count: i64 = file_links::table
+        .filter(file_links::id.eq(&link.id))
+        .select(file_links::download_count)
+        .first(&mut conn)
+        .unwrap();
+    assert_eq!(download_count, 0);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+fn basic_auth(password: &str) -> String {
+    use base64::Engine;
+    format!(
+        "Basic {}",
+        base64::engine::general_purpose::STANDARD.encode(format!(":{}", password))
+    )
+}
+
+fn link_request_with_auth(key: &str, password: &str) -> Request<Body> {
+    Request::builder()
+        .uri(format!("/f/{}", key))
+        .method("GET")
+        .header(header::AUTHORIZATION, basic_auth(password))
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_password_protected_link() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/buckets/test-bucket/objects/link-key")
+        .with_status(200)
+        .with_body("hello")
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 5, "link-key");
+
+    let link = create_link(
+        &router,
+        json!({ "file_id": file.id, "expires_in": 3600, "password": "s3cret" }),
+    )
+    .await;
+    assert!(link.password_protected);
+
+    let response = router
+        .clone()
+        .oneshot(link_download_request(&link.key, None))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(response.headers()[header::WWW_AUTHENTICATE]
+        .to_str()
+        .unwrap()
+        .starts_with("Basic "));
+
+    let response = router
+        .clone()
+        .oneshot(link_request_with_auth(&link.key, "wrong"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        parse_json(response).await["error"]["message"],
+        "Incorrect password"
+    );
+
+    let response = router
+        .clone()
+        .oneshot(link_request_with_auth(&link.key, "s3cret"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = router
+        .clone()
+        .oneshot(link_download_request(&link.key, Some("text/html")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(response.headers().get(header::WWW_AUTHENTICATE).is_none());
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert!(String::from_utf8_lossy(&body).contains("<form method=\"post\">"));
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri(format!("/f/{}", link.key))
+                .method("POST")
+                .header(header::ACCEPT, "text/html")
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from("password=s3cret"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&body[..], b"hello");
+
+    let mut conn = state.db_pool.get().unwrap();
+    let stored: FileLink = file_links::table
+        .filter(file_links::id.eq(&link.id))
+        .first(&mut conn)
+        .unwrap();
+    assert_ne!(stored.password_hash.as_deref(), Some("s3cret"));
+    assert_eq!(stored.failed_password_attempts, 0);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_link_password_attempts_are_throttled() {
+    let mut config = create_test_config();
+    config.link_password_max_attempts = 2;
+    config.link_password_lockout_secs = 60;
+    let (router, state, _guard) = setup_test_router_with_config(config).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 5, "link-key");
+
+    let link = create_link(
+        &router,
+        json!({ "file_id": file.id, "expires_in": 3600, "password": "s3cret" }),
+    )
+    .await;
+
+    for _ in 0..2 {
+        let response = router
+            .clone()
+            .oneshot(link_request_with_auth(&link.key, "wrong"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    // Locked out: even the right password is refused until the lockout ends.
+    let response = router
+        .oneshot(link_request_with_auth(&link.key, "s3cret"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response.headers()[header::RETRY_AFTER]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 60);
+    assert_eq!(parse_json(response).await["error"]["code"], "rate_limited");
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_concurrent_password_guesses_respect_the_limit() {
+    let mut config = create_test_config();
+    config.link_password_max_attempts = 2;
+    config.link_password_lockout_secs = 60;
+    let (router, state, _guard) = setup_test_router_with_config(config).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 5, "link-key");
+
+    let link = create_link(
+        &router,
+        json!({ "file_id": file.id, "expires_in": 3600, "password": "s3cret" }),
+    )
+    .await;
+
+    let guesses = (0..8).map(|_| {
+        router
+            .clone()
+            .oneshot(link_request_with_auth(&link.key, "wrong"))
+    });
+    let statuses: Vec<StatusCode> = futures::future::join_all(guesses)
+        .await
+        .into_iter()
+        .map(|response| response.unwrap().status())
+        .collect();
+
+    let checked = statuses
+        .iter()
+        .filter(|status| **status == StatusCode::UNAUTHORIZED)
+        .count();
+    assert_eq!(checked, 2);
+    assert!(statuses
+        .iter()
+        .all(|status| *status == StatusCode::UNAUTHORIZED
+            || *status == StatusCode::TOO_MANY_REQUESTS));
+
+    cleanup_test_db(&state.db_pool);
+}
+
+async fn mint_signed_url(
+    router: &Router,
+    file_id: &str,
+    body: serde_json::Value,
+) -> axum::response::Response {
+    router
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            &format!("/admin/files/{}/signed-urls", file_id),
+            body,
+        ))
+        .await
+        .unwrap()
+}
+
+fn get_request(uri: &str) -> Request<Body> {
+    Request::builder()
+        .uri(uri)
+        .method("GET")
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_signed_url_serves_file_with_overrides() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/buckets/test-bucket/objects/signed-key")
+        .with_status(200)
+        .with_body("hello")
+        .create_async()
+        .await;
+
+    let mut config = create_test_config();
+    config.storage_base_url = server.url();
+    config.url_signing_keys = vec!["k1:0123456789abcdef0123456789abcdef".parse().unwrap()];
+    config.public_base_url = "https://files.example.com".to_string();
+    let (router, state, _guard) = setup_test_router_with_config(config).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 5, "signed-key");
+
+    let response = mint_signed_url(
+        &router,
+        &file.id,
+        json!({
+            "expires_in": 600,
+            "disposition": "attachment",
+            "content_type": "application/octet-stream",
+        }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let signed: SignedUrlResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    assert_eq!(signed.file_id, file.id);
+
+    let path = signed
+        .url
+        .strip_prefix("https://files.example.com")
+        .unwrap()
+        .to_string();
+    assert!(path.starts_with(&format!("/s/{}?", file.id)));
+
+    let response = router.clone().oneshot(get_request(&path)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/octet-stream"
+    );
+    assert!(response.headers()[header::CONTENT_DISPOSITION]
+        .to_str()
+        .unwrap()
+        .starts_with("attachment;"));
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(&body[..], b"hello");
+
+    let tampered = path.replace("disposition=attachment", "disposition=inline");
+    let response = router
+        .clone()
+        .oneshot(get_request(&tampered))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let unsigned = format!("/s/{}?expires={}", file.id, signed.expires_at);
+    let response = router.oneshot(get_request(&unsigned)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(parse_json(response).await["error"]["param"], "kid");
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_signed_url_minting_validation() {
+    let (router, state, _guard) = setup_test_router().await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 5, "signed-key");
+
+    // No signing keys are configured.
+    let response = mint_signed_url(&router, &file.id, json!({ "expires_in": 600 })).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = mint_signed_url(&router, &file.id, json!({ "expires_in": 0 })).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(parse_json(response).await["error"]["param"], "expires_in");
+
+    let response = mint_signed_url(
+        &router,
+        &file.id,
+        json!({ "expires_in": 600, "disposition": "download" }),
+    )
+    .await;
+    assert_eq!(parse_json(response).await["error"]["param"], "disposition");
+
+    let response = mint_signed_url(&router, "file_missing", json!({ "expires_in": 600 })).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+async fn list_links(router: &Router, uri: &str) -> ListLinksResponse {
+    let response = router.clone().oneshot(get_request(uri)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    serde_json::from_value(parse_json(response).await).unwrap()
+}
+
+#[tokio::test]
+async fn test_list_links_filters_and_pagination() {
+    let (router, state, _guard) = setup_test_router().await;
+    let tenant = insert_test_tenant(&state);
+    let other_tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 5, "links-key");
+    let other_file = insert_test_file(&state, &other_tenant, 5, "other-links-key");
+
+    let first = create_link(&router, json!({ "file_id": file.id, "expires_in": 3600 })).await;
+    let expired = create_expired_link(&router, &state, &file).await;
+    let used_up = create_link(
+        &router,
+        json!({ "file_id": file.id, "expires_in": 3600, "max_downloads": 1 }),
+    )
+    .await;
+    let other = create_link(
+        &router,
+        json!({ "file_id": other_file.id, "expires_in": 3600 }),
+    )
+    .await;
+
+    {
+        let mut conn = state.db_pool.get().unwrap();
+        diesel::update(file_links::table.filter(file_links::id.eq(&used_up.id)))
+            .set(file_links::download_count.eq(1))
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    let all = list_links(&router, "/admin/links").await;
+    let ids: Vec<_> = all.items.iter().map(|l| l.id.as_str()).collect();
+    assert_eq!(ids, [&other.id, &used_up.id, &expired.id, &first.id]);
+
+    let by_tenant = list_links(&router, &format!("/admin/links?tenant_id={}", tenant.id)).await;
+    assert_eq!(by_tenant.items.len(), 3);
+    assert!(by_tenant.items.iter().all(|l| l.file_id == file.id));
+
+    let active = list_links(
+        &router,
+        &format!("/admin/links?file_id={}&active=true", file.id),
+    )
+    .await;
+    let ids: Vec<_> = active.items.iter().map(|l| l.id.as_str()).collect();
+    assert_eq!(ids, [&first.id]);
+    assert_eq!(active.items[0].state, "active");
+
+    let inactive = list_links(
+        &router,
+        &format!("/admin/links?file_id={}&active=false", file.id),
+    )
+    .await;
+    let states: Vec<_> = inactive.items.iter().map(|l| l.state.as_str()).collect();
+    assert_eq!(states, ["exhausted", "expired"]);
+
+    let page = list_links(
+        &router,
+        &format!("/admin/files/{}/links?limit=2&order=asc", file.id),
+    )
+    .await;
+    let ids: Vec<_> = page.items.iter().map(|l| l.id.as_str()).collect();
+    assert_eq!(ids, [&first.id, &expired.id]);
+    assert!(!page.pagination.has_more_before);
+    assert!(page.pagination.has_more_after);
+
+    let page = list_links(
+        &router,
+        &format!(
+            "/admin/files/{}/links?limit=2&order=asc&after={}",
+            file.id,
+            page.pagination.next_cursor.unwrap()
+        ),
+    )
+    .await;
+    let ids: Vec<_> = page.items.iter().map(|l| l.id.as_str()).collect();
+    assert_eq!(ids, [&used_up.id]);
+    assert!(page.pagination.has_more_before);
+    assert!(!page.pagination.has_more_after);
+
+    for (uri, param) in [
+        ("/admin/links?file_id=file_missing", "file_id"),
+        ("/admin/links?tenant_id=tenant_missing", "tenant_id"),
+        ("/admin/links?after=link_missing", "after"),
+    ] {
+        let response = router.clone().oneshot(get_request(uri)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(parse_json(response).await["error"]["param"], param);
+    }
+
+    let response = router
+        .oneshot(get_request("/admin/files/file_missing/links"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_revoke_all_links_of_file() {
+    let (router, state, _guard) = setup_test_router().await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 5, "revoke-all-key");
+    let other_file = insert_test_file(&state, &tenant, 5, "revoke-other-key");
+
+    let first = create_link(&router, json!({ "file_id": file.id, "expires_in": 3600 })).await;
+    let second = create_link(&router, json!({ "file_id": file.id, "expires_in": 3600 })).await;
+    let other = create_link(
+        &router,
+        json!({ "file_id": other_file.id, "expires_in": 3600 }),
+    )
+    .await;
+
+    let revoke_all = || {
+        Request::builder()
+            .uri(format!("/admin/files/{}/links", file.id))
+            .method("DELETE")
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = router.clone().oneshot(revoke_all()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let result: RevokeLinksResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    assert_eq!(result.file_id, file.id);
+    assert_eq!(result.revoked, 2);
+
+    // Already revoked links are left alone.
+    let response = router.clone().oneshot(revoke_all()).await.unwrap();
+    let result: RevokeLinksResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    assert_eq!(result.revoked, 0);
+
+    for key in [&first.key, &second.key] {
+        let response = router
+            .clone()
+            .oneshot(link_download_request(key, None))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::GONE);
+    }
+
+    let links = list_links(&router, &format!("/admin/files/{}/links", file.id)).await;
+    assert!(links.items.iter().all(|l| l.state == "revoked"));
+
+    let links = list_links(&router, &format!("/admin/files/{}/links", other_file.id)).await;
+    assert_eq!(links.items[0].id, other.id);
+    assert_eq!(links.items[0].state, "active");
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_create_link_validates_key_and_ttl() {
+    let (router, state, _guard) = setup_test_router().await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 5, "key-validation");
+
+    let create = |body: serde_json::Value| {
+        router
+            .clone()
+            .oneshot(json_request("POST", "/admin/links", body))
+    };
+
+    for (body, param) in [
+        (
+            json!({ "file_id": file.id, "expires_in": 3600, "key": "" }),
+            "key",
+        ),
+        (
+            json!({ "file_id": file.id, "expires_in": 3600, "key": "a/b" }),
+            "key",
+        ),
+        (
+            json!({ "file_id": file.id, "expires_in": 3600, "key": "with space" }),
+            "key",
+        ),
+        (
+            json!({ "file_id": file.id, "expires_in": 3600, "key": "admin" }),
+            "key",
+        ),
+        (
+            json!({ "file_id": file.id, "expires_in": -60 }),
+            "expires_in",
+        ),
+        (
+            json!({ "file_id": file.id, "expires_in": 59 }),
+            "expires_in",
+        ),
+        (
+            json!({ "file_id": file.id, "expires_in": 2_592_001 }),
+            "expires_in",
+        ),
+        (
+            json!({
+                "file_id": file.id,
+                "expires_in": 3600,
+                "starts_at": chrono::Utc::now().timestamp() + 7200,
+            }),
+            "starts_at",
+        ),
+    ] {
+        let response = create(body).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(parse_json(response).await["error"]["param"], param);
+    }
+
+    let link = create_link(
+        &router,
+        json!({ "file_id": file.id, "expires_in": 60, "key": "quarterly-report" }),
+    )
+    .await;
+    assert_eq!(link.key, "quarterly-report");
+
+    let response =
+        create(json!({ "file_id": file.id, "expires_in": 3600, "key": "quarterly-report" }))
+            .await
+            .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(parse_json(response).await["error"]["code"], "conflict");
+
+    let generated = create_link(&router, json!({ "file_id": file.id, "expires_in": 3600 })).await;
+    assert_eq!(generated.key.len(), 16);
+    assert!(generated.key.chars().all(|c| c.is_ascii_alphanumeric()));
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_generated_link_key_length_is_configurable() {
+    let mut config = create_test_config();
+    config.link_key_length = 48;
+    let (router, state, _guard) = setup_test_router_with_config(config).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 5, "key-length");
+
+    let link = create_link(&router, json!({ "file_id": file.id, "expires_in": 3600 })).await;
+    assert_eq!(link.key.len(), 48);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_link_sweeper_deletes_links_past_grace_period() {
+    let mut config = create_test_config();
+    config.link_sweep_batch_size = 1;
+    config.link_sweep_grace_secs = 3600;
+    let (router, state, _guard) = setup_test_router_with_config(config).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 5, "sweep-key");
+
+    let active = create_link(&router, json!({ "file_id": file.id, "expires_in": 3600 })).await;
+    let recently_expired = create_expired_link(&router, &state, &file).await;
+    let old = [
+        create_link(&router, json!({ "file_id": file.id, "expires_in": 3600 })).await,
+        create_link(&router, json!({ "file_id": file.id, "expires_in": 3600 })).await,
+    ];
+
+    {
+        let mut conn = state.db_pool.get().unwrap();
+        let long_ago = chrono::Utc::now().naive_utc() - chrono::Duration::days(2);
+        for link in &old {
+            diesel::update(file_links::table.filter(file_links::id.eq(&link.id)))
+                .set(file_links::expires_at.eq(long_ago))
+                .execute(&mut conn)
+                .unwrap();
+        }
+    }
+
+    let cancelled = tokio_util::sync::CancellationToken::new();
+    cancelled.cancel();
+    let report = link_sweeper::sweep(&state, &cancelled).await.unwrap();
+    assert_eq!(report.deleted, 0);
+
+    let report = link_sweeper::sweep(&state, &tokio_util::sync::CancellationToken::new())
+        .await
+        .unwrap();
+    assert_eq!(
+        report,
+        link_sweeper::SweepReport {
+            deleted: 2,
+            batches: 2
+        }
+    );
+
+    let remaining = list_links(&router, &format!("/admin/files/{}/links", file.id)).await;
+    let ids: Vec<_> = remaining.items.iter().map(|l| l.id.as_str()).collect();
+    assert_eq!(ids, [&recently_expired.id, &active.id]);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_file_expiry_from_upload_update_and_purpose_default() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock(
+            "PUT",
+            mockito::Matcher::Regex(r"^/buckets/test-bucket/objects/".to_string()),
+        )
+        .with_status(200)
+        .create_async()
+        .await;
+    server
+        .mock("DELETE", mockito::Matcher::Any)
+        .with_status(204)
+        .create_async()
+        .await;
+
+    let mut config = create_test_config();
+    config.storage_base_url = server.url();
+    config.purpose_default_ttls = vec!["image=3600".parse().unwrap()];
+    let (router, state, _guard) = setup_test_router_with_config(config).await;
+
+    let upload = |purpose: &str, expires_at: Option<i64>| {
+        let expires_at = expires_at.map(|ts| ts.to_string());
+        let mut fields = vec![("purpose", purpose)];
+        if let Some(expires_at) = &expires_at {
+            fields.push(("expires_at", expires_at.as_str()));
+        }
+        router.clone().oneshot(multipart_upload_request_with_fields(
+            "test-tenant",
+            &fields,
+            b"temporary",
+        ))
+    };
+
+    let now = chrono::Utc::now().timestamp();
+
+    let response = upload("document", Some(now + 600)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let explicit: FileResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    assert_eq!(explicit.expires_at, Some(now + 600));
+
+    let response = upload("image", None).await.unwrap();
+    let defaulted: FileResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    let expires_at = defaulted.expires_at.unwrap();
+    assert!((now + 3600..now + 3610).contains(&expires_at));
+
+    let response = upload("document", None).await.unwrap();
+    let permanent: FileResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    assert_eq!(permanent.expires_at, None);
+
+    let response = upload("document", Some(now - 1)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(parse_json(response).await["error"]["param"], "expires_at");
+
+    let update = |body: serde_json::Value| {
+        router.clone().oneshot(json_request(
+            "PUT",
+            &format!("/admin/files/{}", explicit.id),
+            body,
+        ))
+    };
+
+    let response = update(json!({ "filename": "renamed.txt" })).await.unwrap();
+    let updated: FileResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    assert_eq!(updated.expires_at, Some(now + 600));
+
+    let response = update(json!({ "expires_at": now + 7200 })).await.unwrap();
+    let updated: FileResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    assert_eq!(updated.expires_at, Some(now + 7200));
+
+    let response = update(json!({ "expires_at": null })).await.unwrap();
+    let updated: FileResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    assert_eq!(updated.expires_at, None);
+
+    let response = update(json!({ "expires_at": now - 60 })).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_expired_files_are_hidden_before_the_reaper_runs() {
+    let mut server = mockito::Server::new_async().await;
+    let download_mock = server
+        .mock("GET", mockito::Matcher::Any)
+        .with_status(200)
+        .with_body("0123456789")
+        .expect(0)
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+    let expired = insert_test_file(&state, &tenant, 10, "expired-object");
+    let kept = insert_test_file(&state, &tenant, 10, "kept-object");
+    let link = create_link(
+        &router,
+        json!({ "file_id": expired.id, "expires_in": 3600 }),
+    )
+    .await;
+
+    {
+        let mut conn = state.db_pool.get().unwrap();
+        diesel::update(files::table.find(expired.oid))
+            .set(
+                files::expires_at.eq(chrono::Utc::now().naive_utc() - chrono::Duration::seconds(1)),
+            )
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    let tenant_request = |uri: String| {
+        Request::builder()
+            .uri(uri)
+            .method("GET")
+            .header("X-Tenant-ID", &tenant.id)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    for request in [
+        tenant_request(format!("/files/{}", expired.id)),
+        tenant_request(format!("/files/{}/content", expired.id)),
+        get_request(&format!("/admin/files/{}", expired.id)),
+        link_download_request(&link.key, None),
+    ] {
+        let uri = request.uri().to_string();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", uri);
+    }
+
+    let response = router
+        .clone()
+        .oneshot(tenant_request("/files".to_string()))
+        .await
+        .unwrap();
+    let page: ListFilesResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    let ids: Vec<&str> = page.items.iter().map(|file| file.id.as_str()).collect();
+    assert_eq!(ids, [kept.id.as_str()]);
+
+    let response = router
+        .oneshot(json_request(
+            "POST",
+            "/admin/links",
+            json!({ "file_id": expired.id, "expires_in": 3600 }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    download_mock.assert_async().await;
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_file_reaper_deletes_expired_files() {
+    let mut server = mockito::Server::new_async().await;
+    let delete_mock = server
+        .mock("DELETE", "/buckets/test-bucket/objects/expired-object")
+        .with_status(204)
+        .expect(1)
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+    let expired = insert_test_file(&state, &tenant, 10, "expired-object");
+    let later = insert_test_file(&state, &tenant, 10, "later-object");
+    insert_test_file(&state, &tenant, 10, "permanent-object");
+    set_tenant_counters(&state, &tenant, 30, 3);
+
+    let link = create_link(
+        &router,
+        json!({ "file_id": expired.id, "expires_in": 3600 }),
+    )
+    .await;
+
+    {
+        let now = chrono::Utc::now().naive_utc();
+        let mut conn = state.db_pool.get().unwrap();
+        for (file, expires_at) in [
+            (&expired, now - chrono::Duration::seconds(1)),
+            (&later, now + chrono::Duration::hours(1)),
+        ] {
+            diesel::update(files::table.find(file.oid))
+                .set(files::expires_at.eq(expires_at))
+                .execute(&mut conn)
+                .unwrap();
+        }
+    }
+
+    let report = file_reaper::reap(&state, &tokio_util::sync::CancellationToken::new())
+        .await
+        .unwrap();
+    assert_eq!(
+        report,
+        file_reaper::ReapReport {
+            files: 1,
+            bytes: 10,
+            links: 1,
+            purged: 0,
+        }
+    );
+
+    delete_mock.assert_async().await;
+    assert_tenant_counters(&state, &tenant, 20, 2);
+    assert!(pending_deletion_keys(&state).is_empty());
+
+    let mut conn = state.db_pool.get().unwrap();
+    let remaining: Vec<String> = files::table
+        .select(files::storage_key)
+        .order(files::storage_key.asc())
+        .load(&mut conn)
+        .unwrap();
+    assert_eq!(remaining, ["later-object", "permanent-object"]);
+
+    let response = router
+        .oneshot(get_request(&format!("/admin/links/{}", link.id)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+fn delete_file_request(file_id: &str) -> Request<Body> {
+    Request::builder()
+        .uri(format!("/admin/files/{}", file_id))
+        .method("DELETE")
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_soft_deleted_files_are_hidden_until_restored() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/buckets/test-bucket/objects/trash-key")
+        .with_status(200)
+        .with_body("hello")
+        .create_async()
+        .await;
+    let delete_mock = server
+        .mock("DELETE", mockito::Matcher::Any)
+        .with_status(204)
+        .expect(0)
+        .create_async()
+        .await;
+
+    let (router, state, _guard) = setup_test_router_with_storage(&server.url()).await;
+    let tenant = insert_test_tenant(&state);
+    let file = insert_test_file(&state, &tenant, 5, "trash-key");
+    set_tenant_counters(&state, &tenant, 5, 1);
+    let link = create_link(&router, json!({ "file_id": file.id, "expires_in": 3600 })).await;
+
+    let response = router
+        .clone()
+        .oneshot(delete_file_request(&file.id))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let deleted: FileResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    assert!(deleted.deleted_at.is_some());
+
+    let hidden = [
+        get_request(&format!("/admin/files/{}", file.id)),
+        get_file_request(&file.id, ("X-Tenant-ID", &tenant.id)),
+        link_download_request(&link.key, None),
+        delete_file_request(&file.id),
+    ];
+    for request in hidden {
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    let response = router
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/admin/links",
+            json!({ "file_id": file.id, "expires_in": 3600 }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(parse_json(response).await["error"]["param"], "file_id");
+
+    let list = |uri: String| {
+        let router = router.clone();
+        async move {
+            let response = router.oneshot(get_request(&uri)).await.unwrap();
+            let list: ListFilesResponse =
+                serde_json::from_value(parse_json(response).await).unwrap();
+            list.items.into_iter().map(|f| f.id).collect::<Vec<_>>()
+        }
+    };
+    assert!(list(format!("/admin/files?tenant_id={}", tenant.id))
+        .await
+        .is_empty());
+    assert_eq!(
+        list(format!("/admin/files?tenant_id={}&deleted=true", tenant.id)).await,
+        [file.id.as_str()]
+    );
+
+    // The trash still counts towards the tenant's quota.
+    assert_tenant_counters(&state, &tenant, 5, 1);
+
+    let response = router
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            &format!("/admin/files/{}/restore", file.id),
+            json!({}),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let restored: FileResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    assert_eq!(restored.deleted_at, None);
+
+    let response = router
+        .clone()
+        .oneshot(link_download_request(&link.key, None))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = router
+        .oneshot(json_request(
+            "POST",
+            "/admin/files/file_missing/restore",
+            json!({}),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    delete_mock.assert_async().await;
+    assert_tenant_counters(&state, &tenant, 5, 1);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_file_reaper_purges_trash_after_retention() {
+    let mut server = mockito::Server::new_async().await;
+    let delete_mock = server
+        .mock("DELETE", "/buckets/test-bucket/objects/old-trash")
+        .with_status(204)
+        .expect(1)
+        .create_async()
+        .await;
+
+    let mut config = create_test_config();
+    config.storage_base_url = server.url();
+    config.file_trash_retention_secs = 3600;
+    let (_router, state, _guard) = setup_test_router_with_config(config).await;
+    let tenant = insert_test_tenant(&state);
+    let old_trash = insert_test_file(&state, &tenant, 10, "old-trash");
+    let recent_trash = insert_test_file(&state, &tenant, 10, "recent-trash");
+    insert_test_file(&state, &tenant, 10, "live");
+    set_tenant_counters(&state, &tenant, 30, 3);
+
+    {
+        let now = chrono::Utc::now().naive_utc();
+        let mut conn = state.db_pool.get().unwrap();
+        for (file, deleted_at) in [
+            (&old_trash, now - chrono::Duration::hours(2)),
+            (&recent_trash, now - chrono::Duration::minutes(10)),
+        ] {
+            diesel::update(files::table.find(file.oid))
+                .set(files::deleted_at.eq(deleted_at))
+                .execute(&mut conn)
+                .unwrap();
+        }
+    }
+
+    let report = file_reaper::reap(&state, &tokio_util::sync::CancellationToken::new())
+        .await
+        .unwrap();
+    assert_eq!(
+        report,
+        file_reaper::ReapReport {
+            files: 1,
+            bytes: 10,
+            links: 0,
+            purged: 1,
+        }
+    );
+
+    delete_mock.assert_async().await;
+    assert_tenant_counters(&state, &tenant, 20, 2);
+
+    let mut conn = state.db_pool.get().unwrap();
+    let remaining: Vec<String> = files::table
+        .select(files::storage_key)
+        .order(files::storage_key.asc())
+        .load(&mut conn)
+        .unwrap();
+    assert_eq!(remaining, ["live", "recent-trash"]);
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_list_files_cursors_in_both_directions() {
+    let (router, state, _guard) = setup_test_router().await;
+    let tenant = insert_test_tenant(&state);
+    let files: Vec<File> = (0..5)
+        .map(|i| insert_test_file(&state, &tenant, 1, &format!("page-{}", i)))
+        .collect();
+
+    let list = |query: String| {
+        let router = router.clone();
+        let uri = format!("/admin/files?tenant_id={}&limit=2&{}", tenant.id, query);
+        async move {
+            let response = router.oneshot(get_request(&uri)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let page: ListFilesResponse =
+                serde_json::from_value(parse_json(response).await).unwrap();
+            page
+        }
+    };
+    let ids =
+        |page: &ListFilesResponse| page.items.iter().map(|f| f.id.clone()).collect::<Vec<_>>();
+
+    let first = list("include_total=true".to_string()).await;
+    assert_eq!(ids(&first), [files[4].id.as_str(), files[3].id.as_str()]);
+    assert!(!first.pagination.has_more_before);
+    assert!(first.pagination.has_more_after);
+    assert_eq!(first.pagination.prev_cursor, None);
+    assert_eq!(first.total_count, Some(5));
+
+    let second = list(format!(
+        "after={}",
+        first.pagination.next_cursor.as_deref().unwrap()
+    ))
+    .await;
+    assert_eq!(ids(&second), [files[2].id.as_str(), files[1].id.as_str()]);
+    assert!(second.pagination.has_more_before);
+    assert!(second.pagination.has_more_after);
+    assert_eq!(second.total_count, None);
+
+    let last = list(format!(
+        "after={}",
+        second.pagination.next_cursor.as_deref().unwrap()
+    ))
+    .await;
+    assert_eq!(ids(&last), [files[0].id.as_str()]);
+    assert!(last.pagination.has_more_before);
+    assert!(!last.pagination.has_more_after);
+    assert_eq!(last.pagination.next_cursor, None);
+
+    // Walking back from the last page lands on the second one again.
+    let back = list(format!(
+        "before={}",
+        last.pagination.prev_cursor.as_deref().unwrap()
+    ))
+    .await;
+    assert_eq!(ids(&back), ids(&second));
+    assert!(back.pagination.has_more_before);
+    assert!(back.pagination.has_more_after);
+
+    let asc = list("order=asc".to_string()).await;
+    assert_eq!(ids(&asc), [files[0].id.as_str(), files[1].id.as_str()]);
+    assert!(!asc.pagination.has_more_before);
+    assert!(asc.pagination.has_more_after);
+
+    // Cursors stay valid after the row they point at is gone.
+    {
+        let mut conn = state.db_pool.get().unwrap();
+        diesel::delete(files::table.find(files[3].oid))
+            .execute(&mut conn)
+            .unwrap();
+    }
+    let after_deleted = list(format!(
+        "after={}",
+        first.pagination.next_cursor.as_deref().unwrap()
+    ))
+    .await;
+    assert_eq!(ids(&after_deleted), ids(&second));
+
+    cleanup_test_db(&state.db_pool);
+}
+
+/// Inserts a file and rewrites the columns the listing filters and sorts on.
+fn insert_listed_file(
+    state: &cargo_hold::app_state::AppState,
+    tenant: &Tenant,
+    filename: &str,
+    bytes: i64,
+    content_type: &str,
+    purpose: &str,
+) -> File {
+    let file = insert_test_file(state, tenant, bytes, filename);
+    let mut conn = state.db_pool.get().unwrap();
+
+    let purpose_oid: i64 = purposes::table
+        .filter(purposes::slug.eq(purpose))
+        .select(purposes::oid)
+        .first(&mut conn)
+        .unwrap();
+
+    diesel::update(files::table.find(file.oid))
+        .set((
+            files::filename.eq(filename),
+            files::content_type.eq(content_type),
+            files::purpose_oid.eq(purpose_oid),
+        ))
+        .get_result(&mut conn)
+        .unwrap()
+}
+
+async fn list_file_ids(router: &Router, uri: &str) -> Vec<String> {
+    let response = router.clone().oneshot(get_request(uri)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let page: ListFilesResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    page.items.into_iter().map(|f| f.id).collect()
+}
+
+#[tokio::test]
+async fn test_list_files_filters() {
+    let (router, state, _guard) = setup_test_router().await;
+    let tenant = insert_test_tenant(&state);
+
+    let report = insert_listed_file(
+        &state,
+        &tenant,
+        "Q3_report.pdf",
+        5_000,
+        "application/pdf",
+        "document",
+    );
+    let photo = insert_listed_file(&state, &tenant, "photo.png", 200, "image/png", "image");
+    let scan = insert_listed_file(&state, &tenant, "Q3-scan.jpg", 900, "image/jpeg", "image");
+    let notes = insert_listed_file(
+        &state,
+        &tenant,
+        "notes.txt",
+        10,
+        "text/plain",
+        "test-purpose",
+    );
+
+    let list = |filters: &str| {
+        let router = router.clone();
+        let uri = format!("/admin/files?tenant_id={}&order=asc&{}", tenant.id, filters);
+        async move { list_file_ids(&router, &uri).await }
+    };
+
+    assert_eq!(
+        list("purpose=image").await,
+        [photo.id.as_str(), scan.id.as_str()]
+    );
+    assert_eq!(
+        list("filename_prefix=Q3").await,
+        [report.id.as_str(), scan.id.as_str()]
+    );
+    // `_` matches literally, not as a wildcard.
+    assert_eq!(list("filename_prefix=Q3_").await, [report.id.as_str()]);
+    assert_eq!(list("filename_contains=REPORT").await, [report.id.as_str()]);
+    assert_eq!(
+        list("content_type=image/*").await,
+        [photo.id.as_str(), scan.id.as_str()]
+    );
+    assert_eq!(list("content_type=text/plain").await, [notes.id.as_str()]);
+    assert_eq!(
+        list("min_bytes=200&max_bytes=900").await,
+        [photo.id.as_str(), scan.id.as_str()]
+    );
+    assert_eq!(
+        list("purpose=image&min_bytes=500").await,
+        [scan.id.as_str()]
+    );
+
+    let now = chrono::Utc::now().timestamp();
+    assert_eq!(list(&format!("min_created_at={}", now - 60)).await.len(), 4);
+    assert!(list(&format!("min_created_at={}", now + 60))
+        .await
+        .is_empty());
+    assert!(list(&format!("max_updated_at={}", now - 60))
+        .await
+        .is_empty());
+
+    for (query, param) in [
+        ("purpose=nope", "purpose"),
+        ("sort=size", "sort"),
+        ("min_created_at=99999999999999", "min_created_at"),
+        ("min_bytes=900&max_bytes=200", "min_bytes"),
+        ("min_created_at=200&max_created_at=100", "min_created_at"),
+        ("min_updated_at=200&max_updated_at=100", "min_updated_at"),
+    ] {
+        let uri = format!("/admin/files?{}", query);
+        let response = router.clone().oneshot(get_request(&uri)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = parse_json(response).await;
+        assert_eq!(body["error"]["param"], param);
+    }
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_list_files_sorted_pages_through_ties() {
+    let (router, state, _guard) = setup_test_router().await;
+    let tenant = insert_test_tenant(&state);
+
+    let files: Vec<File> = [
+        (30, "c.txt"),
+        (10, "a.txt"),
+        (20, "b.txt"),
+        (10, "b.txt"),
+        (10, "a.txt"),
+    ]
+    .into_iter()
+    .map(|(bytes, name)| {
+        insert_listed_file(&state, &tenant, name, bytes, "text/plain", "test-purpose")
+    })
+    .collect();
+
+    /// Follows `next_cursor` to the end and returns every id seen.
+    async fn walk(router: &Router, base: &str) -> Vec<String> {
+        let mut ids = Vec::new();
+        let mut uri = base.to_string();
+        loop {
+            let response = router.clone().oneshot(get_request(&uri)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let page: ListFilesResponse =
+                serde_json::from_value(parse_json(response).await).unwrap();
+            ids.extend(page.items.into_iter().map(|f| f.id));
+            match page.pagination.next_cursor {
+                Some(cursor) => uri = format!("{}&after={}", base, cursor),
+                None => return ids,
+            }
+        }
+    }
+
+    let by_bytes = walk(
+        &router,
+        &format!(
+            "/admin/files?tenant_id={}&sort=bytes&order=asc&limit=2",
+            tenant.id
+        ),
+    )
+    .await;
+    let ids = |order: [usize; 5]| order.map(|i| files[i].id.clone()).to_vec();
+    assert_eq!(by_bytes, ids([1, 3, 4, 2, 0]));
+
+    let by_name = walk(
+        &router,
+        &format!("/admin/files?tenant_id={}&sort=filename&limit=2", tenant.id),
+    )
+    .await;
+    assert_eq!(by_name, ids([0, 3, 2, 4, 1]));
+
+    // Walking back from the end of a sorted listing.
+    let first = format!("/admin/files?tenant_id={}&sort=bytes&limit=4", tenant.id);
+    let response = router.clone().oneshot(get_request(&first)).await.unwrap();
+    let page: ListFilesResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    let last_id = page.items.last().unwrap().id.clone();
+    let cursor = page.pagination.next_cursor.unwrap();
+    let uri = format!(
+        "/admin/files?tenant_id={}&sort=bytes&limit=4&after={}",
+        tenant.id, cursor
+    );
+    let response = router.clone().oneshot(get_request(&uri)).await.unwrap();
+    let page: ListFilesResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    assert_eq!(page.items.len(), 1);
+    let uri = format!(
+        "/admin/files?tenant_id={}&sort=bytes&limit=1&before={}",
+        tenant.id,
+        page.pagination.prev_cursor.unwrap()
+    );
+    assert_eq!(list_file_ids(&router, &uri).await, [last_id]);
+
+    // A cursor only makes sense under the sort it was minted for.
+    let uri = format!("/admin/files?sort=filename&after={}", cursor);
+    let response = router.clone().oneshot(get_request(&uri)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = parse_json(response).await;
+    assert_eq!(body["error"]["param"], "after");
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_list_files_sorts_filenames_bytewise() {
+    let (router, state, _guard) = setup_test_router().await;
+    let tenant = insert_test_tenant(&state);
+
+    let files: Vec<File> = ["b.txt", "_c.txt", "B.txt", "a.txt"]
+        .into_iter()
+        .map(|name| insert_listed_file(&state, &tenant, name, 1, "text/plain", "test-purpose"))
+        .collect();
+    let ids = |order: &[usize]| {
+        order
+            .iter()
+            .map(|&i| files[i].id.clone())
+            .collect::<Vec<_>>()
+    };
+
+    // Byte order, whatever the database collation: "B" < "_" < "a" < "b".
+    let uri = format!(
+        "/admin/files?tenant_id={}&sort=filename&order=asc",
+        tenant.id
+    );
+    assert_eq!(list_file_ids(&router, &uri).await, ids(&[2, 1, 3, 0]));
+
+    // Cursors seek with the same ordering.
+    let uri = format!(
+        "/admin/files?tenant_id={}&sort=filename&order=asc&limit=2",
+        tenant.id
+    );
+    let response = router.clone().oneshot(get_request(&uri)).await.unwrap();
+    let page: ListFilesResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    let uri = format!("{}&after={}", uri, page.pagination.next_cursor.unwrap());
+    assert_eq!(list_file_ids(&router, &uri).await, ids(&[3, 0]));
+
+    cleanup_test_db(&state.db_pool);
+}
+
+#[tokio::test]
+async fn test_public_list_files_is_tenant_scoped() {
+    let (router, state, _guard) = setup_test_router().await;
+    let tenant = insert_test_tenant(&state);
+    let other = insert_test_tenant(&state);
+
+    let kept = insert_listed_file(&state, &tenant, "kept.txt", 1, "text/plain", "document");
+    let trashed = insert_listed_file(&state, &tenant, "trashed.txt", 1, "text/plain", "document");
+    insert_listed_file(&state, &other, "other.txt", 1, "text/plain", "document");
+    {
+        let mut conn = state.db_pool.get().unwrap();
+        diesel::update(files::table.find(trashed.oid))
+            .set(files::deleted_at.eq(chrono::Utc::now().naive_utc()))
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    let request = |query: &str| {
+        Request::builder()
+            .uri(format!("/files?{}", query))
+            .method("GET")
+            .header("X-Tenant-ID", &tenant.id)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = router
+        .clone()
+        .oneshot(request("purpose=document"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let page: ListFilesResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].id, kept.id);
+    assert_eq!(page.items[0].tenant_id, None);
+
+    for (query, param) in [
+        (format!("tenant_id={}", other.id), "tenant_id"),
+        ("deleted=true".to_string(), "deleted"),
+    ] {
+        let response = router.clone().oneshot(request(&query)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = parse_json(response).await;
+        assert_eq!(body["error"]["param"], param);
+    }
+
+    cleanup_test_db(&state.db_pool);
+}
+
+/// A router over in-memory repositories. These tests need neither Postgres
+/// nor the test mutex.
+async fn setup_memory_router(storage_base_url: &str) -> (Router, cargo_hold::app_state::AppState) {
+    memory_router(create_memory_app_state(storage_base_url)).await
+}
+
+async fn setup_memory_router_with_config(
+    config: cargo_hold::config::Config,
+) -> (Router, cargo_hold::app_state::AppState) {
+    memory_router(create_memory_app_state_with_config(config)).await
+}
+
+async fn memory_router(
+    state: cargo_hold::app_state::AppState,
+) -> (Router, cargo_hold::app_state::AppState) {
+    startup::upsert_purposes(
+        state.repos.purposes.as_ref(),
+        &state.snowflake_gen,
+        &state.config.allowed_purposes,
+    )
+    .await
+    .unwrap();
+
+    (test_routes(&state), state)
+}
+
+async fn create_memory_file(
+    state: &cargo_hold::app_state::AppState,
+    tenant: &Tenant,
+    storage_key: &str,
+) -> File {
+    let purpose = state
+        .repos
+        .purposes
+        .find_by_slug("document")
+        .await
+        .unwrap()
+        .unwrap();
+    let oid = state.snowflake_gen.generate().unwrap();
+
+    state
+        .repos
+        .files
+        .create(NewFile {
+            oid,
+            id: format!("file_{}", oid),
+            tenant_oid: tenant.oid,
+            filename: "memory.txt".to_string(),
+            purpose_oid: purpose.oid,
+            bytes: 5,
+            storage_key: storage_key.to_string(),
+            content_type: "text/plain".to_string(),
+            sha256: None,
+            md5: None,
+            crc32c: None,
+            blob_oid: None,
+            expires_at: None,
+        })
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_memory_tenant_lifecycle() {
+    let (router, _state) = setup_memory_router("http://localhost:9999").await;
+
+    let body = json!({ "id": "memory-tenant", "name": "Memory" });
+    let response = router
+        .clone()
+        .oneshot(json_request("POST", "/admin/tenants", body.clone()))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = router
+        .clone()
+        .oneshot(json_request("POST", "/admin/tenants", body))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let response = router
+        .clone()
+        .oneshot(json_request(
+            "PUT",
+            "/admin/tenants/memory-tenant",
+            json!({ "name": "Renamed" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = router
+        .clone()
+        .oneshot(get_request("/admin/tenants/memory-tenant"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(parse_json(response).await["name"], "Renamed");
+
+    let response = router
+        .clone()
+        .oneshot(get_request("/admin/tenants"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = parse_json(response).await;
+    assert_eq!(body["items"].as_array().unwrap().len(), 1);
+
+    let response = router
+        .oneshot(get_request("/admin/tenants/missing"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_memory_upload() {
+    let mut server = mockito::Server::new_async().await;
+    let upload_mock = server
+        .mock("PUT", mockito::Matcher::Any)
+        .with_status(200)
+        .expect(1)
+        .create_async()
+        .await;
+    let (router, state) = setup_memory_router(&server.url()).await;
+
+    let uploaded = upload_and_parse(
+        &router,
+        multipart_upload_request("memory-tenant", "document", b"hello world"),
+    )
+    .await;
+    upload_mock.assert_async().await;
+    assert_eq!(uploaded.bytes, 11);
+    assert_eq!(uploaded.purpose, "document");
+    assert!(uploaded.tenant_id.is_none());
+
+    let record = state
+        .repos
+        .files
+        .find_by_id(&uploaded.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(record.tenant_id, "memory-tenant");
+    assert_eq!(
+        record.file.storage_key,
+        format!("memory-tenant/{}", uploaded.id)
+    );
+    assert_eq!(record.file.sha256, uploaded.sha256);
+
+    let tenant = state
+        .repos
+        .tenants
+        .find_by_id("memory-tenant")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!((tenant.total_files_bytes, tenant.file_count), (11, 1));
+
+    let response = router
+        .clone()
+        .oneshot(get_file_request(
+            &uploaded.id,
+            ("X-Tenant-ID", "memory-tenant"),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = router
+        .oneshot(multipart_upload_request("memory-tenant", "nope", b"hello"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(parse_json(response).await["error"]["param"], "purpose");
+}
+
+#[tokio::test]
+async fn test_memory_dedup_upload_with_api_key() {
+    use cargo_hold::auth::AuthMethod;
+
+    let mut server = mockito::Server::new_async().await;
+    let upload_mock = server
+        .mock("PUT", mockito::Matcher::Any)
+        .with_status(200)
+        .expect(2)
+        .create_async()
+        .await;
+    let delete_mock = server
+        .mock("DELETE", mockito::Matcher::Any)
+        .with_status(204)
+        .expect(1)
+        .create_async()
+        .await;
+
+    let mut config = auth_config(vec![AuthMethod::ApiKey]);
+    config.storage_base_url = server.url();
+    config.dedup_enabled = true;
+    let (router, state) = setup_memory_router_with_config(config).await;
+
+    let response = router
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/admin/tenants",
+            json!({ "id": "memory-tenant", "name": "Memory" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = router
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            "/admin/api-keys",
+            json!({ "tenant_id": "memory-tenant", "name": "ci" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let api_key: ApiKeyResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    let key = api_key.key.unwrap();
+
+    let upload = || {
+        let mut request = multipart_upload_request("memory-tenant", "document", b"same content");
+        request.headers_mut().remove("X-Tenant-ID");
+        request
+            .headers_mut()
+            .insert("X-API-Key", key.parse().unwrap());
+        request
+    };
+    let first = upload_and_parse(&router, upload()).await;
+    let second = upload_and_parse(&router, upload()).await;
+
+    upload_mock.assert_async().await;
+    // The second, redundant object is discarded.
+    delete_mock.assert_async().await;
+
+    let find = |id: String| {
+        let state = state.clone();
+        async move { state.repos.files.find_by_id(&id).await.unwrap().unwrap() }
+    };
+    let (first, second) = (find(first.id).await.file, find(second.id).await.file);
+    assert_eq!(first.storage_key, second.storage_key);
+    assert!(first.blob_oid.is_some());
+    assert_eq!(first.blob_oid, second.blob_oid);
+
+    let tenant = state
+        .repos
+        .tenants
+        .find_by_id("memory-tenant")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!((tenant.total_files_bytes, tenant.file_count), (24, 2));
+
+    let response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/admin/api-keys/{}", api_key.id))
+                .method("DELETE")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = router.oneshot(upload()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_memory_file_trash_and_restore() {
+    let (router, state) = setup_memory_router("http://localhost:9999").await;
+    let tenant = state
+        .repos
+        .tenants
+        .create(NewTenant {
+            oid: state.snowflake_gen.generate().unwrap(),
+            id: "memory-tenant".to_string(),
+            name: "Memory".to_string(),
+            max_total_bytes: None,
+            max_file_count: None,
+            max_file_size_bytes: None,
+        })
+        .await
+        .unwrap();
+    let file = create_memory_file(&state, &tenant, "memory-key").await;
+
+    let tenant_request = |uri: &str| {
+        Request::builder()
+            .uri(uri)
+            .method("GET")
+            .header("X-Tenant-ID", &tenant.id)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = router
+        .clone()
+        .oneshot(tenant_request(&format!("/files/{}", file.id)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = router
+        .clone()
+        .oneshot(tenant_request("/files?purpose=document"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let page: ListFilesResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].id, file.id);
+
+    let response = router
+        .clone()
+        .oneshot(tenant_request("/files?purpose=unknown"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/admin/files/{}", file.id))
+                .method("DELETE")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    let response = router
+        .clone()
+        .oneshot(tenant_request(&format!("/files/{}", file.id)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = router
+        .clone()
+        .oneshot(json_request(
+            "POST",
+            &format!("/admin/files/{}/restore", file.id),
+            json!({}),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = router
+        .oneshot(tenant_request(&format!("/files/{}", file.id)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_memory_link_download_limit() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/buckets/test-bucket/objects/memory-key")
+        .with_status(200)
+        .with_body("hello")
+        .create_async()
+        .await;
+
+    let (router, state) = setup_memory_router(&server.url()).await;
+    let tenant = state
+        .repos
+        .tenants
+        .create_or_get(NewTenant {
+            oid: state.snowflake_gen.generate().unwrap(),
+            id: "memory-tenant".to_string(),
+            name: "Memory".to_string(),
+            max_total_bytes: None,
+            max_file_count: None,
+            max_file_size_bytes: None,
+        })
+        .await
+        .unwrap();
+    let file = create_memory_file(&state, &tenant, "memory-key").await;
+
+    let link = create_link(
+        &router,
+        json!({ "file_id": file.id, "expires_in": 3600, "max_downloads": 1 }),
+    )
+    .await;
+
+    let response = router
+        .clone()
+        .oneshot(link_download_request(&link.key, None))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = router
+        .clone()
+        .oneshot(link_download_request(&link.key, None))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::GONE);
+
+    let response = router
+        .oneshot(get_request(&format!("/admin/links/{}", link.id)))
+        .await
+        .unwrap();
+    let link: FileLinkResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    assert_eq!(link.download_count, 1);
+    assert!(link.last_accessed_at.is_some());
+}
+
+#[tokio::test]
+async fn test_malformed_requests_get_the_error_envelope() {
+    let (router, _state) = setup_memory_router("http://localhost:9999").await;
+
+    let response = router
+        .clone()
+        .oneshot(get_request("/admin/links?limit=ten"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = parse_json(response).await;
+    assert_eq!(body["error"]["code"], "invalid_parameter");
+    assert_eq!(body["error"]["param"], "query");
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri("/admin/tenants")
+                .method("POST")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from("{\"name\": "))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = parse_json(response).await;
+    assert_eq!(body["error"]["code"], "invalid_parameter");
+    assert_eq!(body["error"]["param"], "body");
+}
+
+#[tokio::test]
+async fn test_link_range_requests_count_against_the_limit() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("GET", "/buckets/test-bucket/objects/memory-key")
+        .with_status(200)
+        .with_body("hello")
+        .create_async()
+        .await;
+
+    let (router, state) = setup_memory_router(&server.url()).await;
+    let tenant = state
+        .repos
+        .tenants
+        .create_or_get(NewTenant {
+            oid: state.snowflake_gen.generate().unwrap(),
+            id: "memory-tenant".to_string(),
+            name: "Memory".to_string(),
+            max_total_bytes: None,
+            max_file_count: None,
+            max_file_size_bytes: None,
+        })
+        .await
+        .unwrap();
+    let file = create_memory_file(&state, &tenant, "memory-key").await;
+    let link = create_link(
+        &router,
+        json!({ "file_id": file.id, "expires_in": 3600, "max_downloads": 1 }),
+    )
+    .await;
+
+    let range_request = |range: &str| {
+        Request::builder()
+            .uri(format!("/f/{}", link.key))
+            .method("GET")
+            .header(header::RANGE, range)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    // A range from past the first byte takes the only download.
+    let response = router
+        .clone()
+        .oneshot(range_request("bytes=1-"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+
+    // The exhausted link refuses every range, and the whole file.
+    for range in ["bytes=1-", "bytes=0-0", "bytes=2-4"] {
+        let response = router.clone().oneshot(range_request(range)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::GONE, "{}", range);
+    }
+    let response = router
+        .clone()
+        .oneshot(link_download_request(&link.key, None))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::GONE);
+
+    let response = router
+        .oneshot(get_request(&format!("/admin/links/{}", link.id)))
+        .await
+        .unwrap();
+    let link: FileLinkResponse = serde_json::from_value(parse_json(response).await).unwrap();
+    assert_eq!(link.download_count, 1);
+}
+
+async fn create_memory_tenant(state: &cargo_hold::app_state::AppState) -> Tenant {
+    state
+        .repos
+        .tenants
+        .create(NewTenant {
+            oid: state.snowflake_gen.generate().unwrap(),
+            id: "memory-tenant".to_string(),
+            name: "Memory".to_string(),
+            max_total_bytes: None,
+            max_file_count: None,
+            max_file_size_bytes: None,
+        })
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_memory_permanent_and_tenant_deletes() {
+    let mut server = mockito::Server::new_async().await;
+    let delete_mock = server
+        .mock(
+            "DELETE",
+            mockito::Matcher::Regex(r"^/buckets/test-bucket/objects/memory-key-[12]$".to_string()),
+        )
+        .with_status(204)
+        .expect(2)
+        .create_async()
+        .await;
+    let (router, state) = setup_memory_router(&server.url()).await;
+    let tenant = create_memory_tenant(&state).await;
+    let file = create_memory_file(&state, &tenant, "memory-key-1").await;
+    create_memory_file(&state, &tenant, "memory-key-2").await;
+
+    let delete_request = |uri: String| {
+        Request::builder()
+            .uri(uri)
+            .method("DELETE")
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = router
+        .clone()
+        .oneshot(delete_request(format!(
+            "/admin/files/{}?permanent=true",
+            file.id
+        )))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(state
+        .repos
+        .files
+        .find_by_id(&file.id)
+        .await
+        .unwrap()
+        .is_none());
+
+    let response = router
+        .clone()
+        .oneshot(delete_request(format!("/admin/tenants/{}", tenant.id)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = router
+        .oneshot(get_request(&format!("/admin/tenants/{}", tenant.id)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    delete_mock.assert_async().await;
+    assert_eq!(state.repos.deletions.count().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_memory_failed_upload_is_recorded_for_deletion() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("PUT", mockito::Matcher::Any)
+        .with_status(200)
+        .create_async()
+        .await;
+    server
+        .mock("DELETE", mockito::Matcher::Any)
+        .with_status(500)
+        .create_async()
+        .await;
+    let (router, state) = setup_memory_router(&server.url()).await;
+    let tenant = create_memory_tenant(&state).await;
+
+    // The purpose is only checked once the content is stored.
+    let response = router
+        .oneshot(multipart_upload_request(&tenant.id, "nope", b"payload"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let pending = state.repos.deletions.oldest(10).await.unwrap();
+    assert_eq!(pending.len(), 1);
+    assert!(pending[0]
+        .storage_key
+        .starts_with(&format!("{}/file_", tenant.id)));
+    assert_eq!(pending[0].attempts, 1);
+    assert!(pending[0].last_error.is_some());
+}
+
+#[tokio::test]
+async fn test_memory_sweeper_and_reaper() {
+    let mut server = mockito::Server::new_async().await;
+    let delete_mock = server
+        .mock("DELETE", "/buckets/test-bucket/objects/memory-key")
+        .with_status(204)
+        .expect(1)
+        .create_async()
+        .await;
+
+    let mut config = create_test_config();
+    config.storage_base_url = server.url();
+    config.file_trash_retention_secs = 0;
+    config.link_sweep_grace_secs = 3600;
+    let (_router, state) = setup_memory_router_with_config(config).await;
+    let tenant = create_memory_tenant(&state).await;
+    let file = create_memory_file(&state, &tenant, "memory-key").await;
+
+    let link_oid = state.snowflake_gen.generate().unwrap();
+    state
+        .repos
+        .links
+        .create(NewFileLink {
+            oid: link_oid,
+            id: format!("link_{}", link_oid),
+            file_oid: file.oid,
+            key: "memory-link-key".to_string(),
+            expires_at: chrono::Utc::now().naive_utc() - chrono::Duration::days(2),
+            starts_at: None,
+            max_downloads: None,
+            password_hash: None,
+        })
+        .await
+        .unwrap();
+
+    let report = link_sweeper::sweep(&state, &tokio_util::sync::CancellationToken::new())
+        .await
+        .unwrap();
+    assert_eq!(
+        report,
+        link_sweeper::SweepReport {
+            deleted: 1,
+            batches: 1
+        }
+    );
+
+    state.repos.files.trash(file.oid).await.unwrap().unwrap();
+    let report = file_reaper::reap(&state, &tokio_util::sync::CancellationToken::new())
+        .await
+        .unwrap();
+    assert_eq!(
+        report,
+        file_reaper::ReapReport {
+            files: 1,
+            bytes: 5,
+            links: 0,
+            purged: 1,
+        }
+    );
+
+    delete_mock.assert_async().await;
+    assert!(state
+        .repos
+        .files
+        .find_by_id(&file.id)
+        .await
+        .unwrap()
+        .is_none());
+}